@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Every file below the scanned path has its header parsed, whatever it is
+fuzz_target!(|header: &[u8]| {
+    sourcelynk::fuzzing::file_type(header);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Remotes come from the configs of whatever repos the source files are in
+fuzz_target!(|remote: &str| {
+    sourcelynk::fuzzing::remote_url(remote);
+});
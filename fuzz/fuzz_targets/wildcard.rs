@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Directory and file names are whatever the repo's authors chose
+fuzz_target!(|rel_path: &str| {
+    sourcelynk::fuzzing::wildcard(rel_path);
+});
@@ -0,0 +1,222 @@
+use hmac::{Hmac, Mac};
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+use crate::{cleanup, embedded, is_possible_symbol_file, read_sourcelink};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// A database of the SHA-256 digest of every section sourcelynk embedded,
+// keyed by the canonical path of the binary. The database is signed with an
+// HMAC so that it can't be edited to match a tampered binary without the key.
+pub struct Database {
+    path: PathBuf,
+    key: Vec<u8>,
+    entries: BTreeMap<PathBuf, String>,
+    // The sections of ELF images embedded in binaries, by the offset of the
+    // image
+    images: Images,
+}
+
+type Images = BTreeMap<PathBuf, BTreeMap<usize, String>>;
+
+impl Database {
+    // Open the database at path, or start an empty one if it does not exist
+    // yet. Fails if the signature does not match.
+    pub fn open(path: &Path, key_path: &Path) -> Result<Database> {
+        let key = std::fs::read(key_path)?;
+        let key = key.trim_ascii().to_vec();
+        if key.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Audit key {} is empty", key_path.display()),
+            ));
+        }
+
+        let mut db = Database {
+            path: path.to_owned(),
+            key,
+            entries: BTreeMap::new(),
+            images: BTreeMap::new(),
+        };
+        if !path.exists() {
+            return Ok(db);
+        }
+
+        let json: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)?;
+        let entries: BTreeMap<PathBuf, String> = serde_json::from_value(json["entries"].clone())?;
+        // Databases written before images were recorded have none
+        let images: Images = match json.get("images") {
+            Some(images) => serde_json::from_value(images.clone())?,
+            None => BTreeMap::new(),
+        };
+        let signature = json["signature"].as_str().unwrap_or_default();
+        let mac = db.mac(&entries, &images)?;
+        let signature = hex::decode(signature).unwrap_or_default();
+        if mac.verify_slice(&signature).is_err() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Signature of audit database {} does not match, it was modified outside of sourcelynk or the key is wrong",
+                    path.display()
+                ),
+            ));
+        }
+        db.entries = entries;
+        db.images = images;
+        Ok(db)
+    }
+
+    fn mac(&self, entries: &BTreeMap<PathBuf, String>, images: &Images) -> Result<HmacSha256> {
+        let mut mac = HmacSha256::new_from_slice(&self.key)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
+        mac.update(&serde_json::to_vec(entries)?);
+        // Left out when there are none, so older databases still verify
+        if !images.is_empty() {
+            mac.update(&serde_json::to_vec(images)?);
+        }
+        Ok(mac)
+    }
+
+    pub fn record(&mut self, binary: &Path, section: &[u8]) -> Result<()> {
+        let binary = std::fs::canonicalize(binary)?;
+        debug!("Recording section digest of {}", binary.display());
+        self.entries.insert(binary, digest(section));
+        self.save()
+    }
+
+    // Record section as what sourcelynk wrote into the image at offset in
+    // binary
+    pub fn record_image(&mut self, binary: &Path, offset: usize, section: &[u8]) -> Result<()> {
+        let binary = std::fs::canonicalize(binary)?;
+        debug!(
+            "Recording section digest of the image at offset {} in {}",
+            offset,
+            binary.display()
+        );
+        self.images
+            .entry(binary)
+            .or_default()
+            .insert(offset, digest(section));
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let signature = hex::encode(
+            self.mac(&self.entries, &self.images)?
+                .finalize()
+                .into_bytes(),
+        );
+        let mut json = serde_json::json!({
+            "entries": self.entries,
+            "signature": signature,
+        });
+        if !self.images.is_empty() {
+            json["images"] = serde_json::to_value(&self.images)?;
+        }
+        cleanup::write(&self.path, &serde_json::to_vec_pretty(&json)?)
+    }
+}
+
+fn digest(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+// Compare the binaries under root against the database. Returns the number of
+// binaries whose section was modified, removed, or added outside of
+// sourcelynk.
+pub fn audit(db: &Database, root: &Path) -> usize {
+    let mut flagged = 0;
+    let mut seen = Vec::new();
+    for entry in WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| is_possible_symbol_file(e.path()))
+    {
+        let binary = match std::fs::canonicalize(entry.path()) {
+            Ok(binary) => binary,
+            Err(e) => {
+                warn!("Failed to resolve {}: {}", entry.path().display(), e);
+                continue;
+            }
+        };
+        let section = match read_sourcelink(&binary) {
+            Ok(section) => section,
+            Err(e) => {
+                warn!("Failed to read {}: {}", binary.display(), e);
+                continue;
+            }
+        };
+
+        let status = match (db.entries.get(&binary), section) {
+            (Some(expected), Some(section)) if *expected == digest(&section) => None,
+            (Some(_), Some(_)) => Some("modified"),
+            (Some(_), None) => Some("removed"),
+            (None, Some(_)) => Some("added"),
+            (None, None) => None,
+        };
+        match status {
+            Some(status) => {
+                println!("{} {}", status, binary.display());
+                flagged += 1;
+            }
+            None => debug!("{} matches the audit database", binary.display()),
+        }
+        if let Some(images) = db.images.get(&binary) {
+            flagged += audit_images(&binary, images);
+        }
+        seen.push(binary);
+    }
+
+    // Binaries stamped by sourcelynk which no longer exist
+    if let Ok(root) = std::fs::canonicalize(root) {
+        for binary in db.entries.keys() {
+            if binary.starts_with(&root) && !seen.contains(binary) && !binary.exists() {
+                println!("missing {}", binary.display());
+                flagged += 1;
+            }
+        }
+    }
+
+    info!("Audited {} binaries, {} flagged", seen.len(), flagged);
+    flagged
+}
+
+// Compare the sections of the images embedded in binary against their digests
+// by offset. Returns the number that differ.
+fn audit_images(binary: &Path, expected: &BTreeMap<usize, String>) -> usize {
+    let data = match std::fs::read(binary) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Failed to read {}: {}", binary.display(), e);
+            return 0;
+        }
+    };
+    let found = embedded::find(&data);
+    let mut flagged = 0;
+    for (offset, digest_of) in expected {
+        let section = found
+            .iter()
+            .find(|image| image.offset == *offset)
+            .and_then(|image| image.section.clone());
+        let status = match section {
+            Some(section) if digest(&data[section.clone()]) == *digest_of => continue,
+            Some(_) => "modified",
+            None => "removed",
+        };
+        println!(
+            "{} {} (image at offset {})",
+            status,
+            binary.display(),
+            offset
+        );
+        flagged += 1;
+    }
+    flagged
+}
@@ -0,0 +1,317 @@
+use log::{debug, error, warn};
+use sha2::{Digest, Sha256};
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+use crate::{cleanup, sink};
+
+const MANIFEST_NAME: &str = "sourcelynk-backup.json";
+
+// What --backup names. A value with a / in it is a directory to copy binaries
+// to, below the path they have below the scanned directory. Anything else is
+// a suffix for a copy next to each binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Spec {
+    Suffix(String),
+    Dir(PathBuf),
+}
+
+impl std::str::FromStr for Spec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.contains('/') || s.contains(std::path::MAIN_SEPARATOR) {
+            Ok(Spec::Dir(PathBuf::from(s)))
+        } else if !s.is_empty() {
+            Ok(Spec::Suffix(s.to_owned()))
+        } else {
+            Err("Expected a suffix like .orig or a directory like backups/".to_owned())
+        }
+    }
+}
+
+// The manifest of the backups taken of the binaries under root, keyed by the
+// canonical path of the binary:
+//
+//   {"binaries": {"/out/libfoo.so": {"backup": "/out/libfoo.so.orig",
+//                                    "stamped": "<SHA-256 of the rewrite>"}}}
+//
+// The digest tells whether a binary is still the one sourcelynk wrote, both so
+// a later run keeps the backup of the unstamped binary and so a restore
+// doesn't undo a rebuild.
+pub struct Manifest {
+    path: PathBuf,
+    root: PathBuf,
+    spec: Spec,
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+struct Entry {
+    backup: PathBuf,
+    stamped: String,
+}
+
+impl Manifest {
+    // The manifest of the backups spec names for a scan of root. It is kept in
+    // the backup directory, or the scanned one for suffixes.
+    pub fn open(spec: &Spec, root: &Path) -> Result<Manifest> {
+        let root = std::fs::canonicalize(root)?;
+        let path = match spec {
+            Spec::Dir(dir) => {
+                std::fs::create_dir_all(dir)?;
+                let dir = std::fs::canonicalize(dir)?;
+                if dir == root {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "the backup directory is the scanned one",
+                    ));
+                }
+                dir.join(MANIFEST_NAME)
+            }
+            Spec::Suffix(_) if root.is_dir() => root.join(MANIFEST_NAME),
+            Spec::Suffix(_) => root.with_file_name(MANIFEST_NAME),
+        };
+        let mut manifest = load(&path)?;
+        manifest.root = root;
+        manifest.spec = spec.clone();
+        Ok(manifest)
+    }
+
+    // Copy binary, whose content is data, aside before it is rewritten. A
+    // binary that is still what an earlier run wrote keeps the backup taken
+    // then.
+    pub fn back_up(&mut self, binary: &Path, data: &[u8]) -> Result<()> {
+        let binary = std::fs::canonicalize(binary)?;
+        if let Some(entry) = self.entries.get(&binary) {
+            if entry.stamped == digest(data) && entry.backup.is_file() {
+                debug!(
+                    "Keeping the backup of {} from an earlier run",
+                    binary.display()
+                );
+                return Ok(());
+            }
+        }
+
+        let backup = match &self.spec {
+            Spec::Suffix(suffix) => {
+                let mut backup = binary.clone().into_os_string();
+                backup.push(suffix);
+                PathBuf::from(backup)
+            }
+            Spec::Dir(_) => self
+                .path
+                .parent()
+                .unwrap()
+                .join(sink::rel_path(&self.root, &binary)),
+        };
+        if let Some(dir) = backup.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::copy(&binary, &backup)?;
+        debug!("Backed up {} to {}", binary.display(), backup.display());
+        // Not stamped yet, which record fills in
+        self.entries.insert(
+            binary,
+            Entry {
+                backup,
+                stamped: String::new(),
+            },
+        );
+        Ok(())
+    }
+
+    // Whether path is a backup, which is never processed. The daemon sees them
+    // being written like any other file.
+    pub fn is_backup(&self, path: &Path) -> bool {
+        match &self.spec {
+            Spec::Suffix(suffix) => path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().ends_with(suffix.as_str())),
+            Spec::Dir(_) => std::fs::canonicalize(path)
+                .is_ok_and(|path| self.path.parent().is_some_and(|dir| path.starts_with(dir))),
+        }
+    }
+
+    // Remember that binary was rewritten to data
+    pub fn record(&mut self, binary: &Path, data: &[u8]) -> Result<()> {
+        let binary = std::fs::canonicalize(binary)?;
+        match self.entries.get_mut(&binary) {
+            Some(entry) => entry.stamped = digest(data),
+            None => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("{} was not backed up", binary.display()),
+                ))
+            }
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        if self.entries.is_empty() {
+            return match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let binaries = self
+            .entries
+            .iter()
+            .map(|(binary, entry)| {
+                (
+                    binary.to_string_lossy().into_owned(),
+                    serde_json::json!({
+                        "backup": entry.backup,
+                        "stamped": entry.stamped,
+                    }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>();
+        let json = serde_json::json!({ "binaries": binaries });
+        cleanup::write(&self.path, &serde_json::to_vec_pretty(&json)?)
+    }
+}
+
+fn load(path: &Path) -> Result<Manifest> {
+    let mut manifest = Manifest {
+        path: path.to_owned(),
+        root: PathBuf::new(),
+        spec: Spec::Suffix(String::new()),
+        entries: BTreeMap::new(),
+    };
+    if !path.exists() {
+        return Ok(manifest);
+    }
+    let json: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)?;
+    for (binary, entry) in json["binaries"].as_object().into_iter().flatten() {
+        match (entry["backup"].as_str(), entry["stamped"].as_str()) {
+            (Some(backup), Some(stamped)) => {
+                manifest.entries.insert(
+                    PathBuf::from(binary),
+                    Entry {
+                        backup: PathBuf::from(backup),
+                        stamped: stamped.to_owned(),
+                    },
+                );
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Malformed entry for {} in {}", binary, path.display()),
+                ))
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+fn digest(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+// Put the backups in the manifest at path back, removing them and the
+// manifest. Binaries changed since they were stamped, like by a rebuild, are
+// left alone unless force is set. Returns the number of binaries not restored.
+pub fn restore(path: &Path, force: bool) -> Result<usize> {
+    // Loading one that doesn't exist starts an empty one
+    std::fs::metadata(path)?;
+    let mut manifest = load(path)?;
+    if manifest.entries.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} lists no backups", path.display()),
+        ));
+    }
+
+    let mut failed = 0;
+    for (binary, entry) in std::mem::take(&mut manifest.entries) {
+        // Backed up, but rewriting it failed
+        if entry.stamped.is_empty() {
+            debug!("{} was never stamped", binary.display());
+            let _ = std::fs::remove_file(&entry.backup);
+            continue;
+        }
+        let current = match std::fs::read(&binary) {
+            Ok(current) => Some(current),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let changed = current.is_some_and(|current| digest(&current) != entry.stamped);
+        if changed && !force {
+            error!(
+                "{} changed since it was stamped, restore it with --force",
+                binary.display()
+            );
+            manifest.entries.insert(binary, entry);
+            failed += 1;
+            continue;
+        }
+
+        let restored = std::fs::read(&entry.backup).and_then(|data| {
+            // Keeps the owner of a binary that still exists
+            let metadata_from = if binary.exists() {
+                &binary
+            } else {
+                &entry.backup
+            };
+            sink::replace(&binary, metadata_from, &data, None)
+        });
+        match restored {
+            Ok(()) => {
+                if let Err(e) = std::fs::remove_file(&entry.backup) {
+                    warn!("Failed to remove {}: {}", entry.backup.display(), e);
+                }
+                println!("Restored {}", binary.display());
+            }
+            Err(e) => {
+                error!(
+                    "Failed to restore {} from {}: {}",
+                    binary.display(),
+                    entry.backup.display(),
+                    e
+                );
+                manifest.entries.insert(binary, entry);
+                failed += 1;
+            }
+        }
+    }
+    manifest.save()?;
+    Ok(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knows_its_suffix_backups() {
+        let root = tempfile::tempdir().unwrap();
+        let binary = root.path().join("app");
+        std::fs::write(&binary, b"unstamped").unwrap();
+        let mut manifest = Manifest::open(&Spec::Suffix(".orig".to_owned()), root.path()).unwrap();
+        manifest.back_up(&binary, b"unstamped").unwrap();
+
+        assert!(!manifest.is_backup(&binary));
+        assert!(manifest.is_backup(&root.path().join("app.orig")));
+        assert!(manifest.is_backup(Path::new("app.orig")));
+    }
+
+    #[test]
+    fn knows_its_directory_backups() {
+        let root = tempfile::tempdir().unwrap();
+        let binary = root.path().join("app");
+        std::fs::write(&binary, b"unstamped").unwrap();
+        let spec = Spec::Dir(root.path().join("backups"));
+        let mut manifest = Manifest::open(&spec, root.path()).unwrap();
+        manifest.back_up(&binary, b"unstamped").unwrap();
+
+        let backup = root.path().join("backups").join("app");
+        assert!(backup.is_file());
+        assert!(!manifest.is_backup(&binary));
+        assert!(manifest.is_backup(&backup));
+        // Seen through another path, like the daemon's watch of a relative one
+        assert!(manifest.is_backup(&root.path().join("backups/../backups/app")));
+    }
+}
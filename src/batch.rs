@@ -0,0 +1,84 @@
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+// A root to scan with options of its own
+#[derive(Debug)]
+pub struct Root {
+    pub name: String,
+    pub path: PathBuf,
+    pub args: Vec<String>,
+}
+
+// The roots of a batch file, for indexing the artifacts of many teams in one
+// pass:
+//
+//   [profile.strict]
+//   require-pushed = true
+//
+//   [[root]]
+//   name = "team-a"
+//   path = "/artifacts/team-a"
+//   profile = "strict"
+//   options = { gitlab-host = ["gitlab.team-a.example"] }
+//
+// Profiles and options take the same keys as the configuration file. A root's
+// options override its profile, which overrides the command line.
+pub fn load(path: &Path, command: &clap::Command) -> Result<Vec<Root>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let table = text
+        .parse::<toml::Table>()
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    let invalid = |message: String| format!("{} in {}", message, path.display());
+
+    let empty = toml::Table::new();
+    let profiles = match table.get("profile") {
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => return Err(invalid("\"profile\" must be a table".to_owned())),
+        None => &empty,
+    };
+    let roots = match table.get("root") {
+        Some(toml::Value::Array(roots)) => roots,
+        _ => return Err(invalid("Expected [[root]] entries".to_owned())),
+    };
+
+    let mut batch = Vec::new();
+    for root in roots {
+        let field = |name: &str| root.get(name).and_then(toml::Value::as_str);
+        let root_path = field("path").ok_or_else(|| invalid("A root has no path".to_owned()))?;
+        let name = field("name").unwrap_or(root_path).to_owned();
+
+        let mut args = Vec::new();
+        if let Some(profile) = field("profile") {
+            let options = profiles
+                .get(profile)
+                .and_then(toml::Value::as_table)
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "Root {} uses unknown profile \"{}\"",
+                        name, profile
+                    ))
+                })?;
+            args.extend(config::table_args(options, path, command)?);
+        }
+        match root.get("options") {
+            Some(toml::Value::Table(options)) => {
+                args.extend(config::table_args(options, path, command)?)
+            }
+            Some(_) => {
+                return Err(invalid(format!(
+                    "The options of root {} must be a table",
+                    name
+                )))
+            }
+            None => {}
+        }
+        batch.push(Root {
+            name,
+            path: PathBuf::from(root_path),
+            args,
+        });
+    }
+    Ok(batch)
+}
@@ -0,0 +1,126 @@
+use log::trace;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::elf;
+
+// Proc macros are exported under this prefix. They are only ever loaded by
+// rustc, not shipped or debugged.
+const PROC_MACRO_DECLS: &str = "__rustc_proc_macro_decls_";
+
+// Cargo builds everything in target/<profile>/deps, named after the crate with
+// a hash of its metadata like libserde_derive-1a2b3c4d5e6f7a8b.so, and copies
+// what the build asked for up to target/<profile> without the hash. Rebuilds
+// with other features or compiler versions leave the old ones behind.
+#[derive(Default)]
+pub struct Artifacts {
+    // The newest of each library in a deps directory, by name without hash
+    newest: HashMap<PathBuf, HashMap<String, PathBuf>>,
+}
+
+impl Artifacts {
+    // Why path isn't worth processing, None when it is or isn't from cargo
+    pub fn skip_reason(&mut self, path: &Path) -> Option<String> {
+        let dir = path.parent()?;
+        let (profile, deps) = match dir.file_name() {
+            Some(name) if name == "deps" => (dir.parent()?, Some(dir)),
+            _ => (dir, None),
+        };
+        // Every profile directory cargo builds in has its fingerprints
+        if !profile.join(".fingerprint").is_dir() {
+            return None;
+        }
+        let hashed = unhashed_name(path);
+        let name = match &hashed {
+            Some(name) => name.as_str(),
+            None => path.file_name()?.to_str()?,
+        };
+
+        if deps.is_some() {
+            let uplifted = profile.join(name);
+            if same_content(path, &uplifted) {
+                return Some(format!("it is a copy of {}", uplifted.display()));
+            }
+        }
+
+        if !is_library(name) {
+            return None;
+        }
+        if elf::exports(path, PROC_MACRO_DECLS).unwrap_or(false) {
+            return Some("it is a proc macro".to_owned());
+        }
+        let (deps, name) = deps.zip(hashed)?;
+        let newest = self
+            .newest
+            .entry(deps.to_owned())
+            .or_insert_with(|| newest_libraries(deps))
+            .get(&name)?;
+        if newest != path {
+            return Some(format!("it is older than {}", newest.display()));
+        }
+        None
+    }
+}
+
+// The file name of a hashed artifact in a deps directory without the hash
+fn unhashed_name(path: &Path) -> Option<String> {
+    if path.parent()?.file_name()? != "deps" {
+        return None;
+    }
+    let name = path.file_name()?.to_str()?;
+    let (stem, extension) = name.split_at(name.find('.').unwrap_or(name.len()));
+    let (crate_name, hash) = stem.rsplit_once('-')?;
+    if hash.len() != 16 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(format!("{}{}", crate_name, extension))
+}
+
+// Test binaries share their crate's name, so only libraries can tell which
+// one is current
+fn is_library(name: &str) -> bool {
+    [".so", ".dylib", ".dll"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+fn newest_libraries(deps: &Path) -> HashMap<String, PathBuf> {
+    let mut newest = HashMap::<String, (SystemTime, PathBuf)>::new();
+    for entry in std::fs::read_dir(deps).into_iter().flatten().flatten() {
+        let path = entry.path();
+        let name = match unhashed_name(&path) {
+            Some(name) if is_library(&name) => name,
+            _ => continue,
+        };
+        let modified = match entry.metadata().and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(_) => continue,
+        };
+        match newest.get(&name) {
+            Some((newer, _)) if *newer >= modified => {}
+            _ => {
+                newest.insert(name, (modified, path));
+            }
+        }
+    }
+    trace!("{} has {} libraries", deps.display(), newest.len());
+    newest
+        .into_iter()
+        .map(|(name, (_, path))| (name, path))
+        .collect()
+}
+
+// Cargo hard links the copy when it can, and copies it otherwise
+fn same_content(path: &Path, other: &Path) -> bool {
+    let same_len = match (std::fs::metadata(path), std::fs::metadata(other)) {
+        (Ok(metadata), Ok(other)) => metadata.len() == other.len(),
+        _ => false,
+    };
+    same_len
+        && match (std::fs::read(path), std::fs::read(other)) {
+            (Ok(data), Ok(other)) => data == other,
+            _ => false,
+        }
+}
@@ -0,0 +1,30 @@
+use compiledfiles::FileCheckSum;
+use sha2::Digest;
+
+// Whether content is the file debug info recorded checksum for. Checkouts
+// with core.autocrlf compile files with the CRLF line endings their blobs
+// don't have, so those count too.
+pub fn matches(checksum: &FileCheckSum, content: &[u8]) -> bool {
+    if digest_matches(checksum, content) {
+        return true;
+    }
+    if !content.contains(&b'\n') || content.contains(&b'\r') {
+        return false;
+    }
+    let mut crlf = Vec::with_capacity(content.len() + content.len() / 32);
+    for &byte in content {
+        if byte == b'\n' {
+            crlf.push(b'\r');
+        }
+        crlf.push(byte);
+    }
+    digest_matches(checksum, &crlf)
+}
+
+fn digest_matches(checksum: &FileCheckSum, content: &[u8]) -> bool {
+    match checksum {
+        FileCheckSum::Md5(expected) => md5::Md5::digest(content)[..] == expected[..],
+        FileCheckSum::Sha1(expected) => sha1::Sha1::digest(content)[..] == expected[..],
+        FileCheckSum::Sha256(expected) => sha2::Sha256::digest(content)[..] == expected[..],
+    }
+}
@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use crate::hints::Hint;
+
+// The checkout a CI system describes in its environment, for builds whose
+// workspace has no git repo left to read, like pruned or moved workspaces
+pub fn detect() -> Option<Hint> {
+    github().or_else(gitlab).or_else(azure_pipelines)
+}
+
+fn github() -> Option<Hint> {
+    Some(Hint {
+        path: PathBuf::from(var("GITHUB_WORKSPACE")?),
+        remote: format!(
+            "{}/{}",
+            var("GITHUB_SERVER_URL")?,
+            var("GITHUB_REPOSITORY")?
+        ),
+        commit: var("GITHUB_SHA")?,
+    })
+}
+
+fn gitlab() -> Option<Hint> {
+    Some(Hint {
+        path: PathBuf::from(var("CI_PROJECT_DIR")?),
+        remote: var("CI_PROJECT_URL")?,
+        commit: var("CI_COMMIT_SHA")?,
+    })
+}
+
+fn azure_pipelines() -> Option<Hint> {
+    Some(Hint {
+        path: PathBuf::from(var("BUILD_SOURCESDIRECTORY")?),
+        remote: var("BUILD_REPOSITORY_URI")?,
+        commit: var("BUILD_SOURCEVERSION")?,
+    })
+}
+
+fn var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
@@ -0,0 +1,123 @@
+use log::debug;
+
+use std::io::{Result, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+// Temporary files that exist right now. Destructors don't run when a signal
+// ends the run, so the handler removes these itself.
+static TEMPORARIES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn temporaries() -> MutexGuard<'static, Vec<PathBuf>> {
+    // A panic elsewhere while holding the lock leaves the list as valid as ever
+    TEMPORARIES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// A temporary file at path, removed when dropped unless it was persisted
+pub struct Temporary {
+    path: PathBuf,
+    kept: bool,
+}
+
+pub fn track(path: &Path) -> Temporary {
+    temporaries().push(path.to_owned());
+    Temporary {
+        path: path.to_owned(),
+        kept: false,
+    }
+}
+
+impl Temporary {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Move the file to where it stays with rename. No signal can remove it
+    // once it got there.
+    pub fn persist(mut self, rename: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+        let mut temporaries = temporaries();
+        let result = rename(&self.path);
+        if result.is_ok() {
+            temporaries.retain(|path| *path != self.path);
+            self.kept = true;
+        }
+        // Dropping self takes the lock again
+        drop(temporaries);
+        result
+    }
+}
+
+impl Drop for Temporary {
+    fn drop(&mut self) {
+        let mut temporaries = temporaries();
+        temporaries.retain(|path| *path != self.path);
+        if !self.kept {
+            // Also removed by whatever created it, like tempfile
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+// Write data to path through a temporary file next to it, so readers never see
+// a partly written file and concurrent runs never write to the same temporary
+// one. The file is new and readable by everyone, unlike the binaries
+// sink::replace writes with the metadata of the original.
+pub fn write(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut output = tempfile::NamedTempFile::new_in(dir)?;
+    let temporary = track(output.path());
+    output.write_all(data)?;
+    set_readable(output.path())?;
+    temporary.persist(|_| output.persist(path).map(drop).map_err(|e| e.error))
+}
+
+// Temporary files are only readable by their owner
+#[cfg(unix)]
+fn set_readable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o644))
+}
+
+#[cfg(not(unix))]
+fn set_readable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+// Remove the temporary files when SIGINT, SIGTERM or SIGHUP end the run. The
+// signals are blocked and waited for on a thread of their own, which can take
+// the lock a signal handler couldn't. Threads started later inherit the mask,
+// so this must be called before any are.
+#[cfg(target_os = "linux")]
+pub fn on_signal() {
+    // All zeros is an empty set
+    let mut signals = unsafe { std::mem::zeroed::<libc::sigset_t>() };
+    unsafe {
+        libc::sigemptyset(&mut signals);
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            libc::sigaddset(&mut signals, signal);
+        }
+        if libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut()) != 0 {
+            return;
+        }
+    }
+    std::thread::spawn(move || {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            return;
+        }
+        // Held until exiting so nothing is persisted or created in between
+        let temporaries = temporaries();
+        for path in temporaries.iter() {
+            debug!("Removing {}", path.display());
+            let _ = std::fs::remove_file(path);
+        }
+        std::process::exit(128 + signal);
+    });
+}
+
+// Elsewhere a signal still ends the run without removing temporary files
+#[cfg(not(target_os = "linux"))]
+pub fn on_signal() {}
@@ -0,0 +1,73 @@
+use std::path::Path;
+
+// The configuration file looked for in the working directory when --config is
+// not given
+pub const FILE_NAME: &str = "sourcelynk.toml";
+
+// The configuration file holds defaults for the top level command line
+// options, keyed by their long name:
+//
+//   check-commits = true
+//   timeout = 30
+//   gitlab-host = ["gitlab.example.com"]
+//   exclude = ["**/test/**"]
+//
+//   [map]
+//   "/src/vendor/zlib" = "https://example.com/zlib/raw/v1.3/*"
+//
+// Arrays repeat the option and tables pass KEY=VALUE. The returned arguments go
+// before the real ones, so the command line overrides single valued options and
+// adds to repeatable ones.
+pub fn args(path: &Path, command: &clap::Command) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let table = text
+        .parse::<toml::Table>()
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    table_args(&table, path, command)
+}
+
+// The arguments for the options in table, read from path
+pub fn table_args(
+    table: &toml::Table,
+    path: &Path,
+    command: &clap::Command,
+) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let known = key != "config"
+            && key != "batch"
+            && command
+                .get_arguments()
+                .any(|arg| arg.get_long() == Some(key.as_str()));
+        if !known {
+            return Err(format!("Unknown option \"{}\" in {}", key, path.display()));
+        }
+
+        match value {
+            toml::Value::Boolean(true) => args.push(format!("--{key}")),
+            toml::Value::Boolean(false) => {}
+            toml::Value::Array(values) => {
+                for value in values {
+                    args.push(format!("--{key}={}", scalar(key, value)?));
+                }
+            }
+            toml::Value::Table(entries) => {
+                for (entry, value) in entries {
+                    args.push(format!("--{key}={entry}={}", scalar(key, value)?));
+                }
+            }
+            value => args.push(format!("--{key}={}", scalar(key, value)?)),
+        }
+    }
+    Ok(args)
+}
+
+fn scalar(key: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(value) => Ok(value.clone()),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        _ => Err(format!("Unsupported value for \"{}\": {}", key, value)),
+    }
+}
@@ -0,0 +1,82 @@
+use log::{debug, info, warn};
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+
+// Watch dirs and call process with batches of files created or modified in
+// them. Linkers write binaries in several steps so a file is only handed to
+// process once it has not changed for settle. process returns the files it
+// rewrote, the events caused by those rewrites are ignored, as are the events
+// of files ignore matches.
+pub fn run<I, F>(
+    dirs: &[PathBuf],
+    settle: Duration,
+    ignore: I,
+    mut process: F,
+) -> notify::Result<()>
+where
+    I: Fn(&Path) -> bool,
+    F: FnMut(&[PathBuf]) -> Vec<PathBuf>,
+{
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    for dir in dirs {
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+        info!("Watching {}", dir.display());
+    }
+
+    // Files with events, and when the last one was seen
+    let mut pending = HashMap::<PathBuf, Instant>::new();
+    // Modification times of files rewritten by process
+    let mut rewritten = HashMap::<PathBuf, SystemTime>::new();
+    loop {
+        match rx.recv_timeout(settle) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    for path in event.paths.into_iter().filter(|path| !ignore(path)) {
+                        pending.insert(path, Instant::now());
+                    }
+                }
+            }
+            Ok(Err(e)) => warn!("Error watching files: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        let now = Instant::now();
+        let settled = pending
+            .iter()
+            .filter(|(_, seen)| now.duration_since(**seen) >= settle)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        let mut batch = Vec::new();
+        for path in settled {
+            pending.remove(&path);
+            let modified = match std::fs::metadata(&path) {
+                Ok(metadata) if metadata.is_file() => metadata.modified().ok(),
+                _ => continue,
+            };
+            if modified.is_some() && rewritten.get(&path) == modified.as_ref() {
+                debug!("Ignoring our own update of {}", path.display());
+                continue;
+            }
+            rewritten.remove(&path);
+            batch.push(path);
+        }
+        if batch.is_empty() {
+            continue;
+        }
+
+        for path in process(&batch) {
+            if let Some(modified) = std::fs::metadata(&path)
+                .ok()
+                .and_then(|metadata| metadata.modified().ok())
+            {
+                rewritten.insert(path, modified);
+            }
+        }
+    }
+}
@@ -0,0 +1,456 @@
+use log::{debug, error, warn};
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+// Emit a diagnostic with the given code, formatted like the log macros. The
+// file the diagnostic is about can optionally be given first.
+macro_rules! diag {
+    ($code:expr, file: $file:expr, $($arg:tt)+) => {
+        $crate::diagnostics::emit($code, Some($file), &format!($($arg)+))
+    };
+    ($code:expr, $($arg:tt)+) => {
+        $crate::diagnostics::emit($code, None, &format!($($arg)+))
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Code {
+    UnknownDomain,
+    UntrackedFile,
+    NoOrigin,
+    MissingSource,
+    InvalidRemoteUrl,
+    ParseError,
+    NoSourceFiles,
+    MappingConflict,
+    SplitDirectory,
+    EmbedFailed,
+    CommitNotOnRemote,
+    Timeout,
+    SectionTooLarge,
+    PartialDebugInfo,
+    DirtySource,
+    HostNotAllowed,
+    EmptyMapping,
+    ChecksumMismatch,
+    SectionConflict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // Through the logger, so only visible with enough -v
+    Human,
+    // One JSON object per line on stderr, regardless of verbosity
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown error format \"{}\"", s)),
+        }
+    }
+}
+
+struct Settings {
+    levels: BTreeMap<Code, Level>,
+    format: Format,
+}
+
+impl Code {
+    pub const ALL: [Code; 19] = [
+        Code::UnknownDomain,
+        Code::UntrackedFile,
+        Code::NoOrigin,
+        Code::MissingSource,
+        Code::InvalidRemoteUrl,
+        Code::ParseError,
+        Code::NoSourceFiles,
+        Code::MappingConflict,
+        Code::SplitDirectory,
+        Code::EmbedFailed,
+        Code::CommitNotOnRemote,
+        Code::Timeout,
+        Code::SectionTooLarge,
+        Code::PartialDebugInfo,
+        Code::DirtySource,
+        Code::HostNotAllowed,
+        Code::EmptyMapping,
+        Code::ChecksumMismatch,
+        Code::SectionConflict,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Code::UnknownDomain => "W001",
+            Code::UntrackedFile => "W002",
+            Code::NoOrigin => "W003",
+            Code::MissingSource => "W004",
+            Code::InvalidRemoteUrl => "W005",
+            Code::ParseError => "W006",
+            Code::NoSourceFiles => "W007",
+            Code::MappingConflict => "W008",
+            Code::SplitDirectory => "W009",
+            Code::EmbedFailed => "W010",
+            Code::CommitNotOnRemote => "W011",
+            Code::Timeout => "W012",
+            Code::SectionTooLarge => "W013",
+            Code::PartialDebugInfo => "W014",
+            Code::DirtySource => "W015",
+            Code::HostNotAllowed => "W016",
+            Code::EmptyMapping => "W017",
+            Code::ChecksumMismatch => "W018",
+            Code::SectionConflict => "W019",
+        }
+    }
+
+    pub fn summary(self) -> &'static str {
+        match self {
+            Code::UnknownDomain => "remote is not on a known domain",
+            Code::UntrackedFile => "source file is not tracked by its repo",
+            Code::NoOrigin => "repo has no remote to link to",
+            Code::MissingSource => "source file does not exist on disk",
+            Code::InvalidRemoteUrl => "remote URL can not be parsed",
+            Code::ParseError => "unexpected error parsing a binary",
+            Code::NoSourceFiles => "binary contains no source files",
+            Code::MappingConflict => "two mappings claim the same directory",
+            Code::SplitDirectory => "one directory contains files from several repos",
+            Code::EmbedFailed => "embedding the section into a binary failed",
+            Code::CommitNotOnRemote => "commit does not exist on the remote",
+            Code::Timeout => "processing a binary took longer than --timeout",
+            Code::SectionTooLarge => "mapping is larger than --max-section-size",
+            Code::PartialDebugInfo => "part of a binary's debug info is corrupt",
+            Code::DirtySource => "source file differs from HEAD",
+            Code::HostNotAllowed => "mapping points at a host that is not allowed",
+            Code::EmptyMapping => "no source file of a binary could be mapped",
+            Code::ChecksumMismatch => "source file differs from what was compiled",
+            Code::SectionConflict => "existing section maps a key elsewhere",
+        }
+    }
+
+    // Long form help shown by `sourcelynk explain`
+    pub fn explanation(self) -> &'static str {
+        match self {
+            Code::UnknownDomain => {
+                "Source links are generated from the URL of the repo's remote, which \
+only works for hosts sourcelynk knows the URL layout of. Files from a repo \
+whose remote is on any other host are not mapped.
+
+To fix this either point the remote at a supported host, or map the repo's \
+directory by hand with `--map DIR=URL`."
+            }
+            Code::UntrackedFile => {
+                "A source file listed in the debug info is inside a git repo, but \
+neither that repo nor any repo enclosing it tracks the file at HEAD. This is \
+usually a generated file or a file that has not been committed yet.
+
+To fix this commit the file, or if it is generated, accept that a debugger \
+will not be able to fetch it."
+            }
+            Code::NoOrigin => {
+                "Sourcelynk builds URLs from the remote given with --remote, or \
+otherwise from upstream, origin or the first remote, in that order. Repos \
+without such a remote are skipped.
+
+To fix this add the remote with `git remote add origin <URL>`, or pass the \
+name of an existing one with --remote."
+            }
+            Code::MissingSource => {
+                "A source file listed in the debug info does not exist on disk, so \
+the repo it belongs to can not be found. This happens when the binary was \
+built on another machine or the sources were moved after the build.
+
+To fix this run sourcelynk on the build machine, right after the build."
+            }
+            Code::InvalidRemoteUrl => {
+                "The URL of the repo's remote could not be parsed as a URL. \
+Repos with such remotes are skipped.
+
+To fix this check the remote with `git remote -v` and correct it with \
+`git remote set-url <NAME> <URL>`."
+            }
+            Code::ParseError => {
+                "The file looked like a binary with debug info but reading the debug \
+info failed. The binary is skipped.
+
+To fix this check that the binary is not truncated or corrupt, for example by \
+running `readelf --debug-dump=info` on it."
+            }
+            Code::NoSourceFiles => {
+                "The binary has debug info but it does not list any source files, so \
+there is nothing to map.
+
+To fix this build the binary with full debug info (e.g. `-g`)."
+            }
+            Code::MappingConflict => {
+                "Two mappings were produced for exactly the same directory but with \
+different URLs. Mappings given with --map win over ones found from repos, \
+otherwise the first one found is kept.
+
+To fix this remove the --map option that is not wanted, or fix the repo layout \
+so each directory belongs to one repo."
+            }
+            Code::SplitDirectory => {
+                "Files in one directory are tracked by different repos, e.g. because \
+a vendored tree was copied into another checkout and committed there too. \
+The files in that directory are all mapped to the deepest repo.
+
+To fix this remove either the copied files from the outer repo or the nested \
+repo."
+            }
+            Code::EmbedFailed => {
+                "Writing the .debug_sourcelink section into the binary failed. The \
+binary is left unchanged.
+
+To fix this check that the binary is a well formed ELF and that it and its \
+directory are writable. The message says what went wrong."
+            }
+            Code::CommitNotOnRemote => {
+                "With --check-commits the host's API, or git ls-remote for hosts \
+without one, is asked whether the commit the mapping points at exists on \
+the remote. It does not, so every URL \
+generated for the repo would fail to resolve. This is usually a commit \
+that has not been pushed, or history that was rewritten by a force push.
+
+To fix this push the commit, or rebuild from a commit that exists on the \
+remote. --require-pushed turns this into an error, and also reports commits \
+that could not be checked.
+
+Crates from a registry published without a recorded commit are linked to \
+the commit of their version's tag, v<version> or <version>, which git \
+ls-remote looks up. They are skipped with this warning when the repository \
+has no such tag or can't be reached."
+            }
+            Code::Timeout => {
+                "Processing the binary did not finish within the time given with --timeout, \
+so it was abandoned and counted as a failure. Corrupt debug info or very \
+large binaries on slow network shares are the usual causes.
+
+To fix this check that the binary is intact, or raise --timeout if the \
+binary is simply large."
+            }
+            Code::SectionTooLarge => {
+                "The JSON generated for the binary is larger than the limit given with \
+--max-section-size. Depending on --on-oversize exact and narrowed keys \
+were collapsed into the wildcard of their repo (wildcards), the most \
+specific entries were dropped until it fits (truncate, and wildcards when \
+collapsing was not enough), or the full JSON was written to a sidecar file \
+next to the binary and only a reference to it embedded (sidecar).
+
+To fix this raise --max-section-size, or ship the sidecar file along with \
+the binary."
+            }
+            Code::PartialDebugInfo => {
+                "With --lenient, a binary whose debug info could not be parsed as a whole \
+is read again one compilation unit at a time. Units that could be read are \
+mapped, the corrupt ones are skipped, so some source files of the binary \
+will have no link.
+
+To fix this rebuild the binary, or check it with `readelf \
+--debug-dump=info` to find the corrupt units."
+            }
+            Code::DirtySource => {
+                "The binary was built from a source file that is modified or staged in its \
+repo. The mapping points at the file as committed at HEAD, so a debugger \
+would show different content than what was compiled.
+
+To fix this commit the changes and rebuild, or build from a clean \
+checkout. --fail-on-dirty turns this into an error for CI."
+            }
+            Code::HostNotAllowed => {
+                "A URL in the mapping of a binary points at a host that --allow-host does \
+not list, or that --deny-host lists. The binary was left unchanged so the \
+host name does not end up in it.
+
+To fix this map the sources to an allowed host with --map or --remote, or \
+allow the host."
+            }
+            Code::EmptyMapping => {
+                "The binary has debug info, but none of its source files are in a repo that \
+could be mapped, so no mapping was embedded. This is allowed by default. \
+Release pipelines that require every symbol file to be mapped can pass \
+--on-empty error, or --on-empty embed-empty to embed an empty mapping that \
+records the binary was processed."
+            }
+            Code::ChecksumMismatch => {
+                "The debug info records a checksum of each source file as it was compiled, \
+and the file at the linked commit doesn't match it. A debugger following \
+the link would show different code than the binary was built from, with \
+breakpoints and line numbers landing in the wrong places. This usually \
+means the binary was built from uncommitted changes, or the commit was \
+overridden with --commit. --fail-on-mismatch turns this into an error for \
+CI."
+            }
+            Code::SectionConflict => {
+                "With --on-existing merge the documents of the .debug_sourcelink section a \
+binary already has are kept, and the new mapping is added to them. Both \
+have the key, with different URLs, so one of them has to go. --on-conflict \
+decides which: keep the existing URL, replace it with the new one, or \
+fail, which leaves the binary unchanged.
+
+To fix this check which step of the pipeline stamped the key before, and \
+pick --on-conflict keep or replace."
+            }
+        }
+    }
+
+    pub fn suggestion(self) -> &'static str {
+        match self {
+            Code::UnknownDomain => "Use a supported host or add a mapping with --map",
+            Code::UntrackedFile => "Commit the file or stop compiling it into the binary",
+            Code::NoOrigin => "Add a remote named origin to the repo or use --remote",
+            Code::MissingSource => "Run sourcelynk on the machine that built the binary",
+            Code::InvalidRemoteUrl => "Check the remote with `git remote -v`",
+            Code::ParseError => "Check that the binary is not truncated or corrupt",
+            Code::NoSourceFiles => "Build the binary with debug info",
+            Code::MappingConflict => "Remove one of the conflicting --map options",
+            Code::SplitDirectory => "Remove the copied sources or the nested repo",
+            Code::EmbedFailed => "Check that the binary and its directory are writable",
+            Code::CommitNotOnRemote => "Push the commit before stamping the binary",
+            Code::Timeout => "Check the binary is intact or raise --timeout",
+            Code::SectionTooLarge => "Raise --max-section-size or use --on-oversize sidecar",
+            Code::PartialDebugInfo => "Rebuild the binary or inspect it with readelf",
+            Code::DirtySource => "Commit the change and rebuild from a clean checkout",
+            Code::HostNotAllowed => "Allow the host with --allow-host or map the sources elsewhere",
+            Code::EmptyMapping => "Check that the binary was built from a checkout, or pass --map for sources outside of one",
+            Code::ChecksumMismatch => "Rebuild from the commit being linked, or link the commit the binary was built from",
+            Code::SectionConflict => "Choose the URL to keep with --on-conflict keep or replace",
+        }
+    }
+
+    // Files that are not in source control or not on disk are common (system
+    // headers, generated code) so they are only reported at debug level unless
+    // asked for.
+    pub fn default_level(self) -> Level {
+        match self {
+            Code::UntrackedFile | Code::MissingSource | Code::EmptyMapping => Level::Allow,
+            Code::HostNotAllowed => Level::Deny,
+            _ => Level::Warn,
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+impl FromStr for Code {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Code::ALL
+            .iter()
+            .copied()
+            .find(|code| code.id().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown diagnostic code \"{}\"", s))
+    }
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+static DENIED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // The binary currently being processed on this thread
+    static BINARY: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    // When the work on this thread is given up on
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+// Set the levels overriding the defaults and the output format. Must be called
+// at most once, before any diagnostics are emitted.
+pub fn init(levels: BTreeMap<Code, Level>, format: Format) {
+    if SETTINGS.set(Settings { levels, format }).is_err() {
+        panic!("diagnostics initialized more than once");
+    }
+}
+
+pub fn level(code: Code) -> Level {
+    SETTINGS
+        .get()
+        .and_then(|settings| settings.levels.get(&code).copied())
+        .unwrap_or_else(|| code.default_level())
+}
+
+fn format() -> Format {
+    SETTINGS
+        .get()
+        .map(|settings| settings.format)
+        .unwrap_or(Format::Human)
+}
+
+// Attribute diagnostics emitted on this thread to binary
+pub fn set_binary(binary: Option<&Path>) {
+    BINARY.with(|current| *current.borrow_mut() = binary.map(Path::to_owned));
+}
+
+// Give up on the work on this thread at deadline
+pub fn set_deadline(deadline: Option<Instant>) {
+    DEADLINE.with(|current| current.set(deadline));
+}
+
+// Whether the work on this thread was given up on. Its result is thrown away,
+// so it reports nothing more and should stop at the next chance.
+pub fn abandoned() -> bool {
+    DEADLINE.with(|current| {
+        current
+            .get()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    })
+}
+
+// Whether any diagnostic escalated to deny was emitted
+pub fn denied() -> bool {
+    DENIED.load(Ordering::Relaxed)
+}
+
+pub fn emit(code: Code, file: Option<&Path>, message: &str) {
+    if abandoned() {
+        debug!("Dropping [{}] {} as the binary timed out", code, message);
+        return;
+    }
+    let level = level(code);
+    crate::metrics::diagnostic(code);
+    if level == Level::Deny {
+        DENIED.store(true, Ordering::Relaxed);
+    }
+
+    if format() == Format::Json && level != Level::Allow {
+        let binary = BINARY.with(|current| current.borrow().clone());
+        let json = serde_json::json!({
+            "code": code.id(),
+            "severity": if level == Level::Deny { "error" } else { "warning" },
+            "file": file,
+            "binary": binary,
+            "message": message,
+            "suggestion": code.suggestion(),
+        });
+        eprintln!("{}", json);
+        return;
+    }
+
+    match level {
+        Level::Allow => debug!("[{}] {}", code, message),
+        Level::Warn => warn!("[{}] {}", code, message),
+        Level::Deny => error!("[{}] {}", code, message),
+    }
+}
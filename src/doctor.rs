@@ -0,0 +1,214 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use crate::providers::{self, Hosts, Revision};
+use crate::vcs::Git;
+
+// What a check found. Warnings are things only some runs need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn print(&mut self, status: Status, what: &str, detail: &str, fix: Option<&str>) {
+        let label = match status {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+        };
+        println!("{:<5} {:<8} {}", label, what, detail);
+        if let (Some(fix), false) = (fix, status == Status::Ok) {
+            println!("{:<14} {}", "", fix);
+        }
+        if status == Status::Fail {
+            self.failures += 1;
+        }
+    }
+}
+
+// Check what a run over root needs before starting a long one, and print what
+// to fix. Returns how many checks failed.
+pub fn run(root: &Path, hosts: &Hosts, remote: Option<&str>, check_commits: bool) -> usize {
+    let mut report = Report { failures: 0 };
+
+    match (version("git"), check_commits) {
+        (Ok(version), _) => report.print(Status::Ok, "git", &version, None),
+        (Err(e), check_commits) => report.print(
+            if check_commits {
+                Status::Fail
+            } else {
+                Status::Warn
+            },
+            "git",
+            &e,
+            Some("Install git, --check-commits runs git ls-remote for hosts without an API"),
+        ),
+    }
+
+    check_root(&mut report, root);
+
+    let mut domains = BTreeSet::new();
+    check_repo(&mut report, root, hosts, remote, &mut domains);
+    for domain in hosts
+        .github
+        .iter()
+        .chain(&hosts.gitlab)
+        .chain(&hosts.bitbucket_server)
+        .chain(&hosts.gitea)
+    {
+        domains.insert(domain.clone());
+    }
+    for domain in &domains {
+        check_reachable(&mut report, domain);
+    }
+
+    report.failures
+}
+
+// The first line of tool --version
+fn version(tool: &str) -> Result<String, String> {
+    let output = Command::new(tool)
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("Unable to run {}: {}", tool, e))?;
+    if !output.status.success() {
+        return Err(format!("{} --version failed", tool));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().next().unwrap_or_default().trim().to_owned())
+}
+
+// Binaries are rewritten next to themselves and renamed over the original
+fn check_root(report: &mut Report, root: &Path) {
+    let dir = match std::fs::metadata(root) {
+        Ok(metadata) if metadata.is_dir() => root,
+        Ok(_) => root
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new(".")),
+        Err(e) => {
+            let detail = format!("{}: {}", root.display(), e);
+            report.print(
+                Status::Fail,
+                "root",
+                &detail,
+                Some("Pass the directory to scan"),
+            );
+            return;
+        }
+    };
+    match tempfile::Builder::new()
+        .prefix(".sourcelynk")
+        .tempfile_in(dir)
+    {
+        Ok(_) => {
+            let detail = format!("{} is writable", dir.display());
+            report.print(Status::Ok, "root", &detail, None);
+        }
+        Err(e) => {
+            let detail = format!("{} is not writable: {}", dir.display(), e);
+            let fix = "Run as a user who can write there, or use --dryrun";
+            report.print(Status::Fail, "root", &detail, Some(fix));
+        }
+    }
+}
+
+// The checkout root or the working directory is in, which is usually the one
+// the binaries were built from. Adds the domain its links point at.
+fn check_repo(
+    report: &mut Report,
+    root: &Path,
+    hosts: &Hosts,
+    remote: Option<&str>,
+    domains: &mut BTreeSet<String>,
+) {
+    let dir = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_owned());
+    let repo = match Git
+        .open(&dir)
+        .or_else(|| Git.open(&std::env::current_dir().ok()?))
+    {
+        Some(repo) => repo,
+        None => {
+            let detail = "No git checkout at the root or in the working directory";
+            let fix = "Sources in other checkouts are still found from the debug info";
+            report.print(Status::Warn, "repo", detail, Some(fix));
+            return;
+        }
+    };
+    let workdir = repo.workdir().unwrap().display().to_string();
+    let head = match Git.head(&repo) {
+        Ok(head) => head,
+        Err(e) => {
+            let detail = format!("{}: Unable to resolve HEAD: {}", workdir, e.message());
+            report.print(Status::Fail, "repo", &detail, Some("Check out a commit"));
+            return;
+        }
+    };
+    let remote_url = match Git.remote_url(&repo, remote) {
+        Ok(remote_url) => remote_url,
+        Err(e) => {
+            let detail = format!("{}: {}", workdir, e.message());
+            let fix = "Add the remote consumers fetch from, or pick one with --remote";
+            report.print(Status::Fail, "repo", &detail, Some(fix));
+            return;
+        }
+    };
+    let mut url = match providers::parse_remote(&remote_url) {
+        Ok(url) => url,
+        Err(e) => {
+            let detail = format!("{}: Invalid remote {}: {}", workdir, remote_url, e);
+            report.print(Status::Fail, "repo", &detail, None);
+            return;
+        }
+    };
+    providers::strip_credentials(&mut url);
+    match providers::generate_url(&url, &Revision::Commit(head), hosts) {
+        Some(link) => {
+            let detail = format!("{} at {} links to {}", workdir, head, link);
+            report.print(Status::Ok, "repo", &detail, None);
+            if let Some(domain) = link.domain() {
+                domains.insert(domain.to_owned());
+            }
+        }
+        None => {
+            let detail = format!("{}: No links can be made for {}", workdir, url);
+            let fix = "Configure the host with --github-host or the like, or --url-template";
+            report.print(Status::Fail, "repo", &detail, Some(fix));
+        }
+    }
+}
+
+// Any response means the host is reachable, whether or not the request was
+// allowed
+fn check_reachable(report: &mut Report, domain: &str) {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(10)))
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let url = format!("https://{}/", domain);
+    match agent
+        .head(&url)
+        .header("User-Agent", crate::APP_NAME)
+        .call()
+    {
+        Ok(response) => {
+            let detail = format!("{} answered {}", domain, response.status());
+            report.print(Status::Ok, "network", &detail, None);
+        }
+        Err(e) => {
+            let detail = format!("{} is unreachable: {}", domain, e);
+            let fix = "Check the proxy and firewall, or skip --check-commits on this machine";
+            report.print(Status::Warn, "network", &detail, Some(fix));
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use object::{Object, ObjectSection};
+
+use std::borrow::Cow;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+// Source files read from the DWARF line programs of path one unit at a time,
+// for binaries compiledfiles gives up on because part of their debug info is
+// corrupt. Returns the files of every unit that could be read and the number
+// of units that could not.
+pub fn source_files(path: &Path) -> Result<(Vec<PathBuf>, usize)> {
+    let data = std::fs::read(path)?;
+    let file = object::File::parse(&*data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let endian = if file.is_little_endian() {
+        gimli::RunTimeEndian::Little
+    } else {
+        gimli::RunTimeEndian::Big
+    };
+
+    let load_section = |id: gimli::SectionId| -> std::result::Result<Cow<[u8]>, gimli::Error> {
+        Ok(file
+            .section_by_name(id.name())
+            .and_then(|section| section.uncompressed_data().ok())
+            .unwrap_or(Cow::Borrowed(&[])))
+    };
+    let sections = gimli::DwarfSections::load(load_section)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let dwarf = sections.borrow(|section| gimli::EndianSlice::new(section, endian));
+
+    let mut files = Vec::new();
+    let mut corrupt = 0;
+    let mut headers = dwarf.units();
+    loop {
+        let header = match headers.next() {
+            Ok(Some(header)) => header,
+            Ok(None) => break,
+            // Without a valid header the next unit can't be found
+            Err(_) => {
+                corrupt += 1;
+                break;
+            }
+        };
+        match unit_source_files(&dwarf, header) {
+            Ok(unit_files) => files.extend(unit_files),
+            Err(_) => corrupt += 1,
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok((files, corrupt))
+}
+
+type Reader<'a> = gimli::EndianSlice<'a, gimli::RunTimeEndian>;
+
+fn unit_source_files<'a>(
+    dwarf: &gimli::Dwarf<Reader<'a>>,
+    header: gimli::UnitHeader<Reader<'a>>,
+) -> gimli::Result<Vec<PathBuf>> {
+    let unit = dwarf.unit(header)?;
+    let program = match &unit.line_program {
+        Some(program) => program.header(),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut files = Vec::new();
+    for file in program.file_names() {
+        // Later components replace earlier ones when they are absolute
+        let mut path = PathBuf::new();
+        if let Some(comp_dir) = &unit.comp_dir {
+            path.push(&*comp_dir.to_string_lossy());
+        }
+        if let Some(dir) = file.directory(program) {
+            path.push(&*dwarf.attr_string(&unit, dir)?.to_string_lossy());
+        }
+        path.push(
+            &*dwarf
+                .attr_string(&unit, file.path_name())?
+                .to_string_lossy(),
+        );
+        files.push(path);
+    }
+    Ok(files)
+}
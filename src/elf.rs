@@ -0,0 +1,57 @@
+use object::{CompressionFormat, Object, ObjectSection, ObjectSymbol};
+
+use std::borrow::Cow;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use crate::section::Compression;
+
+// Read the contents of the section called name, decompressed, None if the
+// binary has no such section.
+pub fn read_section(path: &Path, name: &str) -> Result<Option<Vec<u8>>> {
+    let data = std::fs::read(path)?;
+    let file = object::File::parse(&*data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    match file.section_by_name(name) {
+        Some(section) => {
+            let data = section
+                .uncompressed_data()
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            Ok(Some(data.into_owned()))
+        }
+        None => Ok(None),
+    }
+}
+
+// The decompressed contents of the section called name of the binary in data
+// and how they were compressed, None if it has no such section
+pub fn find_section<'d>(data: &'d [u8], name: &str) -> Option<(Cow<'d, [u8]>, Compression)> {
+    let file = object::File::parse(data).ok()?;
+    let section = file.section_by_name(name)?;
+    let compression = match section.compressed_file_range().ok()?.format {
+        CompressionFormat::Zlib => Compression::Zlib,
+        CompressionFormat::Zstandard => Compression::Zstd,
+        _ => Compression::None,
+    };
+    Some((section.uncompressed_data().ok()?, compression))
+}
+
+// The GNU build id note of the binary, None if it has none
+pub fn build_id(path: &Path) -> Result<Option<Vec<u8>>> {
+    let data = std::fs::read(path)?;
+    let file = object::File::parse(&*data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let build_id = file
+        .build_id()
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(build_id.map(<[u8]>::to_vec))
+}
+
+// Whether the binary exports a dynamic symbol whose name starts with prefix
+pub fn exports(path: &Path, prefix: &str) -> Result<bool> {
+    let data = std::fs::read(path)?;
+    let file = object::File::parse(&*data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let exported = file
+        .dynamic_symbols()
+        .filter(|symbol| symbol.is_definition())
+        .any(|symbol| symbol.name().is_ok_and(|name| name.starts_with(prefix)));
+    Ok(exported)
+}
@@ -0,0 +1,51 @@
+use object::{Object, ObjectKind, ObjectSection};
+
+use std::convert::TryFrom;
+use std::ops::Range;
+
+use crate::ELF_SOURCE_LINK_SECTION_NAME;
+
+const ELF_MAGIC: &[u8] = b"\x7FELF";
+
+// An ELF executable or shared object embedded in another file, like a firmware
+// payload or a bootloader
+#[derive(Debug)]
+pub struct Image {
+    // Where the image starts in the file
+    pub offset: usize,
+    // Where the image's sourcelink section is in the file. A mapping can only
+    // be written in place if the section was reserved when it was built.
+    pub section: Option<Range<usize>>,
+}
+
+// Every ELF image inside data, other than data itself
+pub fn find(data: &[u8]) -> Vec<Image> {
+    let mut images = Vec::new();
+    let mut start = 1;
+    while let Some(found) = data
+        .get(start..)
+        .and_then(|rest| rest.windows(ELF_MAGIC.len()).position(|w| w == ELF_MAGIC))
+    {
+        let offset = start + found;
+        start = offset + 1;
+
+        // Most matches are just bytes that happen to look like the magic
+        let file = match object::File::parse(&data[offset..]) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        if !matches!(file.kind(), ObjectKind::Executable | ObjectKind::Dynamic) {
+            continue;
+        }
+        let section = file
+            .section_by_name(ELF_SOURCE_LINK_SECTION_NAME)
+            .and_then(|section| section.file_range())
+            .and_then(|(section_offset, size)| {
+                let begin = offset.checked_add(usize::try_from(section_offset).ok()?)?;
+                let end = begin.checked_add(usize::try_from(size).ok()?)?;
+                Some(begin..end).filter(|range| range.end <= data.len())
+            });
+        images.push(Image { offset, section });
+    }
+    images
+}
@@ -0,0 +1,44 @@
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
+
+// Every age file starts with this line
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1";
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(AGE_MAGIC)
+}
+
+// Encrypt data so that any one of recipients can decrypt it
+pub fn encrypt(recipients: &[age::x25519::Recipient], data: &[u8]) -> Result<Vec<u8>> {
+    let encryptor = age::Encryptor::with_recipients(
+        recipients
+            .iter()
+            .map(|recipient| recipient as &dyn age::Recipient),
+    )
+    .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+
+    let mut encrypted = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(data)?;
+    writer.finish()?;
+    Ok(encrypted)
+}
+
+// Decrypt data with the identities in the age identity file at identity_path
+pub fn decrypt(identity_path: &Path, data: &[u8]) -> Result<Vec<u8>> {
+    let identities = age::IdentityFile::from_file(identity_path.to_string_lossy().into_owned())?
+        .into_identities()
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    let decryptor = age::Decryptor::new(data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let mut reader = decryptor
+        .decrypt(
+            identities
+                .iter()
+                .map(|identity| identity.as_ref() as &dyn age::Identity),
+        )
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+    let mut decrypted = Vec::new();
+    reader.read_to_end(&mut decrypted)?;
+    Ok(decrypted)
+}
@@ -0,0 +1,121 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::{Outcome, Output};
+
+// What happens to the binaries of a scan, in order for each binary
+#[derive(Debug, Clone)]
+pub enum Event {
+    // A file that may be a binary, about to be processed
+    FileFound {
+        binary: PathBuf,
+    },
+    // The mapping of binary, before it is embedded. Documents is the
+    // "documents" object of the Source Link JSON.
+    MappingComputed {
+        binary: PathBuf,
+        documents: serde_json::Value,
+    },
+    Skipped {
+        binary: PathBuf,
+        reason: SkipReason,
+    },
+    // The mapping was written into binary
+    Embedded {
+        binary: PathBuf,
+    },
+    // The diagnostics logged for binary say why
+    Failed {
+        binary: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    // Not a binary, or one without usable debug info
+    NoDebugInfo,
+    // No source file could be mapped
+    Unmapped,
+    // The scan is a dry run
+    DryRun,
+    // Its section already holds the mapping
+    Unchanged,
+}
+
+impl Event {
+    pub(crate) fn from_outcome(binary: &Path, outcome: Outcome) -> Event {
+        let binary = binary.to_owned();
+        match outcome {
+            Outcome::Skipped => Event::Skipped {
+                binary,
+                reason: SkipReason::NoDebugInfo,
+            },
+            Outcome::Unmapped => Event::Skipped {
+                binary,
+                reason: SkipReason::Unmapped,
+            },
+            Outcome::DryRun => Event::Skipped {
+                binary,
+                reason: SkipReason::DryRun,
+            },
+            Outcome::Unchanged => Event::Skipped {
+                binary,
+                reason: SkipReason::Unchanged,
+            },
+            Outcome::Updated => Event::Embedded { binary },
+            Outcome::Failed => Event::Failed { binary },
+        }
+    }
+}
+
+// The events of a scan running on another thread. Iterating ends when the scan
+// does.
+pub struct Events {
+    receiver: mpsc::Receiver<Event>,
+    scan: thread::JoinHandle<Result<(), String>>,
+}
+
+impl Iterator for Events {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Events {
+    // Wait for the scan to end, skipping the events not received yet. Fails
+    // when files the options ask for, like --aggregate, could not be written.
+    pub fn finish(self) -> Result<(), String> {
+        drop(self.receiver);
+        self.scan.join().map_err(|_| "Scan panicked".to_owned())?
+    }
+}
+
+// Scan like the command line tool does with args, like ["--dryrun", "/out"],
+// reporting what happens as events instead of printing it. Nothing is printed
+// to stdout, and diagnostics are logged through the log crate.
+pub fn scan<I, T>(args: I) -> Result<Events, String>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+{
+    let args =
+        std::iter::once(OsString::from(crate::APP_NAME)).chain(args.into_iter().map(Into::into));
+    let matches = crate::cli()
+        .try_get_matches_from(args)
+        .map_err(|e| e.to_string())?;
+    let mut options = crate::build_options(&matches, None)?;
+    let (sender, receiver) = mpsc::channel();
+    options.output = Output::Quiet;
+    options.events = Some(sender);
+
+    let scan = thread::spawn(move || {
+        let options = Arc::new(options);
+        crate::scan(&matches, &options)?;
+        crate::finish_sink(&options)
+    });
+    Ok(Events { receiver, scan })
+}
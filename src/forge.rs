@@ -0,0 +1,205 @@
+use log::debug;
+
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::providers::Hosts;
+
+// Asks a host's API whether a commit exists on the remote. A local repo can't
+// tell whether its commits were pushed, or were since lost to a force push.
+pub trait CommitCheck {
+    // Ok(false) when the host says the commit does not exist, Err when the
+    // host could not be asked.
+    fn commit_exists(&self, remote: &url::Url, hash: &git2::Oid) -> Result<bool, String>;
+}
+
+pub struct GitHub;
+
+impl CommitCheck for GitHub {
+    fn commit_exists(&self, remote: &url::Url, hash: &git2::Oid) -> Result<bool, String> {
+        let (owner, repo) = owner_and_repo(remote)?;
+        let api = match remote.domain().ok_or("remote has no domain")? {
+            "github.com" => "https://api.github.com".to_owned(),
+            // GitHub Enterprise Server serves the same API under /api/v3
+            domain => format!("https://{domain}/api/v3"),
+        };
+        let url = format!("{api}/repos/{owner}/{repo}/commits/{hash}");
+        let token = std::env::var("GITHUB_TOKEN")
+            .ok()
+            .map(|token| ("Authorization", format!("Bearer {token}")));
+        exists(&url, token)
+    }
+}
+
+pub struct GitLab;
+
+impl CommitCheck for GitLab {
+    fn commit_exists(&self, remote: &url::Url, hash: &git2::Oid) -> Result<bool, String> {
+        let domain = remote.domain().ok_or("remote has no domain")?;
+        // GitLab identifies projects by their URL encoded full path, which
+        // may include nested groups
+        let path = remote.path().trim_matches('/').trim_end_matches(".git");
+        let project = url::form_urlencoded::byte_serialize(path.as_bytes()).collect::<String>();
+        let url = format!("https://{domain}/api/v4/projects/{project}/repository/commits/{hash}");
+        let token = std::env::var("GITLAB_TOKEN")
+            .ok()
+            .map(|token| ("PRIVATE-TOKEN", token));
+        exists(&url, token)
+    }
+}
+
+// The commit check for the host of remote, if its API is known. hosts names
+// the self hosted instances.
+fn commit_check(remote: &url::Url, hosts: &Hosts) -> Option<Box<dyn CommitCheck>> {
+    let domain = remote.domain()?;
+    let is = |known: &str, instances: &[String]| {
+        domain == known || instances.iter().any(|host| host == domain)
+    };
+    if is("github.com", &hosts.github) {
+        Some(Box::new(GitHub))
+    } else if is("gitlab.com", &hosts.gitlab) {
+        Some(Box::new(GitLab))
+    } else {
+        None
+    }
+}
+
+// Whether hash exists on remote, the remote named name of repo, asking its
+// host's API or else git ls-remote. Asked once per remote and commit, since
+// every binary built from a checkout checks the same one and APIs are rate
+// limited.
+pub fn commit_exists(
+    repo: &git2::Repository,
+    name: &str,
+    remote: &url::Url,
+    hash: &git2::Oid,
+    hosts: &Hosts,
+) -> Result<bool, String> {
+    let key = format!("{} {}", remote, hash);
+    if let Some(exists) = COMMITS.lock().unwrap().get(&key) {
+        return exists.clone();
+    }
+    let exists = match commit_check(remote, hosts) {
+        Some(check) => check.commit_exists(remote, hash),
+        None => ls_remote_contains(repo, name, hash),
+    };
+    COMMITS.lock().unwrap().insert(key, exists.clone());
+    exists
+}
+
+static COMMITS: Mutex<BTreeMap<String, Result<bool, String>>> = Mutex::new(BTreeMap::new());
+
+// Whether hash is a ref of the remote named remote, or an ancestor of one, for
+// hosts without a known API. Only ancestors of refs fetched into repo can be
+// found.
+fn ls_remote_contains(
+    repo: &git2::Repository,
+    remote: &str,
+    hash: &git2::Oid,
+) -> Result<bool, String> {
+    let dir = repo.workdir().unwrap_or_else(|| repo.path());
+    debug!("Running git ls-remote {} in {}", remote, dir.display());
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg(remote)
+        .current_dir(dir)
+        // Fail instead of waiting for credentials nobody will type
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git ls-remote failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let tips = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| git2::Oid::from_str(line.split('\t').next()?).ok())
+        .collect::<Vec<_>>();
+    Ok(tips
+        .iter()
+        .any(|tip| tip == hash || repo.graph_descendant_of(*tip, *hash).unwrap_or(false)))
+}
+
+// The commit tag points to on remote, None when remote has no such tag. Asked
+// once per remote and tag, since many binaries share their dependencies.
+pub fn ls_remote_tag(remote: &url::Url, tag: &str) -> Result<Option<git2::Oid>, String> {
+    let key = format!("{} {}", remote, tag);
+    if let Some(commit) = REMOTE_TAGS.lock().unwrap().get(&key) {
+        return commit.clone();
+    }
+    let commit = ls_remote_peeled(remote, tag);
+    REMOTE_TAGS.lock().unwrap().insert(key, commit.clone());
+    commit
+}
+
+type RemoteTag = Result<Option<git2::Oid>, String>;
+static REMOTE_TAGS: Mutex<BTreeMap<String, RemoteTag>> = Mutex::new(BTreeMap::new());
+
+fn ls_remote_peeled(remote: &url::Url, tag: &str) -> Result<Option<git2::Oid>, String> {
+    debug!("Running git ls-remote {} for tag {}", remote, tag);
+    // Annotated tags are only listed peeled to their commit when asked for
+    let name = format!("refs/tags/{}", tag);
+    let peeled = format!("{}^{{}}", name);
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg("--tags")
+        .arg(remote.as_str())
+        .arg(&name)
+        .arg(&peeled)
+        // No local repo is needed, and none should configure the call
+        .current_dir(std::env::temp_dir())
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git ls-remote failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let mut commit = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let (hash, reference) = match line.split_once('\t') {
+            Some(fields) => fields,
+            None => continue,
+        };
+        let hash = git2::Oid::from_str(hash).map_err(|e| e.message().to_owned())?;
+        if reference == peeled {
+            return Ok(Some(hash));
+        }
+        if reference == name {
+            commit = Some(hash);
+        }
+    }
+    Ok(commit)
+}
+
+fn owner_and_repo(remote: &url::Url) -> Result<(&str, &str), String> {
+    let mut segments = remote
+        .path_segments()
+        .ok_or("remote has no path")?
+        .filter(|segment| !segment.is_empty());
+    match (segments.next(), segments.next()) {
+        (Some(owner), Some(repo)) => Ok((owner, repo.trim_end_matches(".git"))),
+        _ => Err(format!("can not find owner and repo in {}", remote)),
+    }
+}
+
+fn exists(url: &str, auth: Option<(&str, String)>) -> Result<bool, String> {
+    debug!("Checking {}", url);
+    let mut request = ureq::get(url).header("User-Agent", crate::APP_NAME);
+    if let Some((header, value)) = auth {
+        request = request.header(header, value);
+    }
+    match request.call() {
+        Ok(_) => Ok(true),
+        // GitHub answers 422 for hashes that are not a commit in the repo
+        Err(ureq::Error::StatusCode(404)) | Err(ureq::Error::StatusCode(422)) => Ok(false),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,70 @@
+// Entry points for the fuzz targets in fuzz/. Not a stable API.
+
+use crate::providers::{self, Hosts, Revision};
+
+pub use crate::magic::parse as file_type;
+
+// A host configured for each of the self-hosted generators
+fn hosts() -> Hosts {
+    Hosts {
+        github: vec!["github.example".to_owned()],
+        gitlab: vec!["gitlab.example".to_owned()],
+        bitbucket_server: vec!["bitbucket.example".to_owned()],
+        gitea: vec!["gitea.example".to_owned()],
+        templates: vec!["https://{host}/{org}/{repo}/raw/{commit}/{path}"
+            .parse()
+            .unwrap()],
+        ..Hosts::default()
+    }
+}
+
+// Everything a remote URL read from a repo's config or a hints file goes
+// through
+pub fn remote_url(remote: &str) {
+    let hosts = hosts();
+    let mut url = match providers::parse_remote(remote) {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    providers::strip_credentials(&mut url);
+    for rev in [
+        Revision::Commit(git2::Oid::zero()),
+        Revision::Tag("v1.0 #%&+".to_owned()),
+        Revision::Branch("feature/x".to_owned()),
+    ] {
+        providers::generate_url(&url, &rev, &hosts);
+    }
+    crate::submodule::resolve_url(remote, "../sibling.git");
+}
+
+// Whatever a directory in a repo is called, substituting it into the wildcard
+// of each provider's URL must leave the URL's parts where they were and a
+// single wildcard after it
+pub fn wildcard(rel_path: &str) {
+    let hosts = hosts();
+    let rev = Revision::Commit(git2::Oid::zero());
+    for remote in [
+        "https://github.com/org/repo",
+        "https://gitlab.example/group/repo",
+        "https://bitbucket.example/scm/KEY/repo",
+        "https://gitea.example/org/repo",
+        "https://dev.azure.com/org/project/_git/repo",
+        "https://chromium.googlesource.com/chromium/src",
+        "https://example.com/org/repo",
+    ] {
+        let url = providers::generate_url(&remote.parse().unwrap(), &rev, &hosts).unwrap();
+        let substituted = match providers::substitute(url.as_str(), rel_path, true) {
+            Ok(substituted) => substituted,
+            Err(_) => continue,
+        };
+        let parsed = url::Url::parse(&substituted).unwrap();
+        assert_eq!(substituted.matches('*').count(), 1, "{}", substituted);
+        assert_eq!(parsed.fragment(), url.fragment(), "{}", substituted);
+        assert_eq!(
+            parsed.query_pairs().count(),
+            url.query_pairs().count(),
+            "{}",
+            substituted
+        );
+    }
+}
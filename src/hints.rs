@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+// Where the sources of a stripped binary came from, as recorded by the build
+// system
+#[derive(Debug, Clone)]
+pub struct Hint {
+    // Directory the repo was checked out to during the build
+    pub path: PathBuf,
+    pub remote: String,
+    pub commit: String,
+}
+
+// Hints keyed by lower case hex build id:
+//
+//   {
+//     "<build id>": [
+//       {"path": "/build/app", "remote": "https://github.com/org/app", "commit": "<sha>"}
+//     ]
+//   }
+pub fn load(path: &Path) -> Result<BTreeMap<String, Vec<Hint>>> {
+    let json = serde_json::from_slice::<serde_json::Value>(&std::fs::read(path)?)?;
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_owned());
+
+    let mut hints = BTreeMap::new();
+    for (build_id, entries) in json
+        .as_object()
+        .ok_or_else(|| invalid("expected an object"))?
+    {
+        let entries = entries
+            .as_array()
+            .ok_or_else(|| invalid("expected an array of hints per build id"))?;
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                let field = |name| {
+                    entry[name]
+                        .as_str()
+                        .ok_or_else(|| invalid(&format!("hint for {} has no {}", build_id, name)))
+                };
+                Ok(Hint {
+                    path: PathBuf::from(field("path")?),
+                    remote: field("remote")?.to_owned(),
+                    commit: field("commit")?.to_owned(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        hints.insert(build_id.to_ascii_lowercase(), entries);
+    }
+    Ok(hints)
+}
@@ -0,0 +1,30 @@
+// Which hosts the URLs embedded in binaries may point at, so internal host
+// names don't leak into binaries that ship. Patterns are a host name or
+// *.DOMAIN for any host below DOMAIN.
+#[derive(Debug, Default)]
+pub struct HostPolicy {
+    // Every host is allowed when empty
+    pub allow: Vec<String>,
+    // Beats allow
+    pub deny: Vec<String>,
+}
+
+impl HostPolicy {
+    pub fn allows(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        if self.deny.iter().any(|pattern| matches(pattern, &host)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|pattern| matches(pattern, &host))
+    }
+}
+
+fn matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.ends_with('.')),
+        None => host == pattern,
+    }
+}
@@ -0,0 +1,3778 @@
+use clap::ArgAction;
+use diagnostics::Code;
+use log::{debug, error, trace, warn};
+use path_slash::PathExt;
+use sha2::{Digest, Sha256};
+use vcs::{Git, VcsBackend};
+use walkdir::WalkDir;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{Cursor, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+#[macro_use]
+mod diagnostics;
+mod audit;
+mod backup;
+mod batch;
+mod cargo;
+mod checksum;
+mod ci_env;
+mod cleanup;
+mod config;
+mod daemon;
+mod doctor;
+mod dwarf;
+mod elf;
+mod embedded;
+mod encryption;
+pub mod events;
+mod forge;
+#[doc(hidden)]
+pub mod fuzzing;
+mod hints;
+mod host_policy;
+mod magic;
+mod mapping;
+mod metrics;
+mod msf;
+mod p4;
+mod packages;
+mod payload;
+mod porcelain;
+mod prefix_map;
+mod providers;
+mod registry;
+mod scan_cache;
+pub mod section;
+mod service;
+mod sink;
+mod submodule;
+mod summary;
+mod svn;
+mod throttle;
+mod timestamp;
+mod trend;
+pub mod vcs;
+mod verify;
+mod webhook;
+mod xattr;
+
+const APP_AUTHOR: &str = "Matt Schulte <schultetwin1@gmail.com>";
+const APP_NAME: &str = "sourcelynk";
+
+const ELF_SOURCE_LINK_SECTION_NAME: &str = section::SOURCE_LINK;
+
+// The sourcelynk command line tool, run with the process's arguments
+pub fn run() -> Result<(), std::io::Error> {
+    let (matches, args) = parse_cli_args();
+    initialize_logger(&matches);
+    cleanup::on_signal();
+
+    if let Some(("explain", explain_matches)) = matches.subcommand() {
+        explain(explain_matches.get_one::<String>("CODE"));
+        return Ok(());
+    }
+
+    match parse_diagnostic_levels(&matches) {
+        Ok(levels) => diagnostics::init(
+            levels,
+            *matches
+                .get_one::<diagnostics::Format>("error-format")
+                .unwrap(),
+        ),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if matches.get_flag("io-nice") {
+        if let Err(e) = throttle::lower_priority() {
+            warn!("Not lowering the I/O priority: {}", e);
+        }
+    }
+    if let Some(rate) = matches.get_one::<throttle::Rate>("io-limit") {
+        throttle::limit(*rate);
+    }
+
+    let mut options = exit_on_error(build_options(&matches, None));
+    match matches.subcommand() {
+        Some(("embed", _)) => {
+            // stdout is the binary
+            options.output = Output::Quiet;
+            options.sink = Arc::new(sink::InPlace);
+            options.backup = None;
+        }
+        Some(("service", _)) => {
+            options.backup = None;
+        }
+        _ => {}
+    }
+    let options = Arc::new(options);
+
+    match matches.subcommand() {
+        Some(("embed", _)) => {
+            if let Err(e) = embed_stdin(&options) {
+                error!("Failed to embed into the binary on stdin: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(("extract", extract_matches)) => {
+            let binary = Path::new(extract_matches.get_one::<String>("BINARY").unwrap());
+            let identity = extract_matches.get_one::<String>("identity").map(Path::new);
+            match extract(binary, identity) {
+                Ok(json) => println!("{}", serde_json::to_string_pretty(&json).unwrap()),
+                Err(e) => {
+                    error!("Failed to extract from {}: {}", binary.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("verify", verify_matches)) => {
+            let binary = Path::new(verify_matches.get_one::<String>("BINARY").unwrap());
+            let identity = verify_matches.get_one::<String>("identity").map(Path::new);
+            let concurrency = *verify_matches.get_one::<u64>("concurrency").unwrap() as usize;
+            match extract(binary, identity)
+                .and_then(|json| verify::verify(binary, &json["documents"], concurrency))
+            {
+                Ok(0) => {}
+                Ok(_) => std::process::exit(1),
+                Err(e) => {
+                    error!("Failed to verify {}: {}", binary.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("doctor", doctor_matches)) => {
+            let root = Path::new(doctor_matches.get_one::<String>("PATH").unwrap());
+            let remote = options.remote.as_deref();
+            if doctor::run(root, &options.hosts, remote, options.check_commits) > 0 {
+                std::process::exit(1);
+            }
+        }
+        Some(("audit", audit_matches)) => {
+            let db = match &options.audit_db {
+                Some(db) => db.lock().unwrap(),
+                None => {
+                    error!("audit requires --audit-db and --audit-key");
+                    std::process::exit(1);
+                }
+            };
+            let root = audit_matches.get_one::<String>("PATH").unwrap();
+            if audit::audit(&db, Path::new(root)) > 0 {
+                std::process::exit(1);
+            }
+        }
+        Some(("restore", restore_matches)) => {
+            let manifest = Path::new(restore_matches.get_one::<String>("MANIFEST").unwrap());
+            match backup::restore(manifest, restore_matches.get_flag("force")) {
+                Ok(0) => {}
+                Ok(_) => std::process::exit(1),
+                Err(e) => {
+                    error!("Failed to restore from {}: {}", manifest.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("trend", _)) => {
+            let db = match matches.get_one::<String>("trend-db") {
+                Some(db) => db,
+                None => {
+                    error!("trend requires --trend-db");
+                    std::process::exit(1);
+                }
+            };
+            match trend::report(Path::new(db)) {
+                Ok(0) => {}
+                Ok(_) => std::process::exit(1),
+                Err(e) => {
+                    error!("Failed to read {}: {}", db, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("service", service_matches)) => {
+            let listen = service_matches.get_one::<String>("listen").unwrap();
+            // Allow ":8080" as a short hand for listening on every interface
+            let addr = if listen.starts_with(':') {
+                format!("0.0.0.0{}", listen)
+            } else {
+                listen.to_owned()
+            };
+            let mut roots = Vec::new();
+            for root in service_matches
+                .get_many::<String>("root")
+                .into_iter()
+                .flatten()
+            {
+                match std::fs::canonicalize(root) {
+                    Ok(root) => roots.push(root),
+                    Err(e) => {
+                        error!("Failed to open {}: {}", root, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            let limits = service::Limits {
+                roots,
+                max_body: *service_matches.get_one::<u64>("max-body").unwrap(),
+            };
+            let webhook = parse_webhook(service_matches);
+            if let Err(e) = service::run(&addr, &limits, &options, webhook.as_ref()) {
+                error!("Service failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(("daemon", daemon_matches)) => {
+            let dirs = daemon_matches
+                .get_many::<String>("watch")
+                .unwrap()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>();
+            let settle = Duration::from_secs(*daemon_matches.get_one::<u64>("settle").unwrap());
+            let webhook = parse_webhook(daemon_matches);
+            let metrics_textfile = daemon_matches.get_one::<String>("metrics-textfile");
+            let ignore = |path: &Path| is_backup(path, &options);
+            if let Err(e) = daemon::run(&dirs, settle, ignore, |paths| {
+                let mut summary = summary::Summary::default();
+                let mut rewritten = Vec::new();
+                for path in paths.iter().filter(|path| is_possible_symbol_file(path)) {
+                    let outcome = process_file(path, &options);
+                    summary.record(path, outcome);
+                    if outcome == Outcome::Updated {
+                        rewritten.push(path.clone());
+                    }
+                }
+                summary.origins = std::mem::take(&mut options.origins.lock().unwrap());
+                if let Some(webhook) = &webhook {
+                    if summary.processed() > 0 {
+                        webhook.post(&summary);
+                    }
+                }
+                if let Some(path) = metrics_textfile {
+                    if let Err(e) = metrics::write_textfile(Path::new(path)) {
+                        warn!("Failed to write metrics to {}: {}", path, e);
+                    }
+                }
+                rewritten
+            }) {
+                error!("Daemon failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        _ => match matches.get_one::<String>("batch") {
+            Some(path) => {
+                let roots = match batch::load(Path::new(path), &cli()) {
+                    Ok(roots) => roots,
+                    Err(e) => {
+                        error!("{}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let roots = roots
+                    .into_iter()
+                    .map(|root| {
+                        // The root's options come last so they override the
+                        // command line's
+                        let mut root_args = without_batch(&args);
+                        root_args.extend(root.args.into_iter().map(Into::into));
+                        root_args.push(root.path.into());
+                        (root.name, cli().get_matches_from(root_args))
+                    })
+                    .collect::<Vec<_>>();
+                exit_on_error(check_batch_outputs(&roots, &matches));
+                for (name, root_matches) in roots {
+                    // Opening the command line's sink again would start it
+                    // over, like truncating its tar file
+                    let shared_sink = (root_matches.get_one::<sink::Spec>("sink")
+                        == matches.get_one::<sink::Spec>("sink"))
+                    .then_some(&options.sink);
+                    let root_options =
+                        Arc::new(exit_on_error(build_options(&root_matches, shared_sink)));
+                    let summary = exit_on_error(scan(&root_matches, &root_options));
+                    if shared_sink.is_none() {
+                        exit_on_error(finish_sink(&root_options));
+                    }
+                    if options.output == Output::Human {
+                        println!("{}: {}", name, summary);
+                    }
+                    record_trend(&root_matches, &name, &summary);
+                }
+                exit_on_error(finish_sink(&options));
+            }
+            None => {
+                let summary = exit_on_error(scan(&matches, &options));
+                exit_on_error(finish_sink(&options));
+                if options.output == Output::Human {
+                    println!("{}", summary);
+                }
+                let root = matches.get_one::<String>("PATH").unwrap();
+                record_trend(&matches, root, &summary);
+            }
+        },
+    }
+
+    if diagnostics::denied() {
+        error!("Exiting with failure due to denied diagnostics");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// The options matches asks for. Binaries go to sink if given, rather than one
+// opened for them.
+fn build_options(
+    matches: &clap::ArgMatches,
+    sink: Option<&Arc<dyn sink::Sink>>,
+) -> Result<Options, String> {
+    let manual_mappings = parse_manual_mappings(matches)?;
+
+    let audit_db = match (
+        matches.get_one::<String>("audit-db"),
+        matches.get_one::<String>("audit-key"),
+    ) {
+        (Some(db), Some(key)) => match audit::Database::open(Path::new(db), Path::new(key)) {
+            Ok(db) => Some(Mutex::new(db)),
+            Err(e) => return Err(format!("Failed to open audit database: {}", e)),
+        },
+        (None, None) => None,
+        _ => return Err("--audit-db and --audit-key must be given together".to_owned()),
+    };
+
+    let hints = match matches.get_one::<String>("hints") {
+        Some(path) => match hints::load(Path::new(path)) {
+            Ok(hints) => Some(hints),
+            Err(e) => return Err(format!("Failed to load hints from {}: {}", path, e)),
+        },
+        None => None,
+    };
+
+    let sink_spec = matches.get_one::<sink::Spec>("sink").unwrap();
+    // Rewritten uploads are sent back, which other sinks would not write to
+    if matches.subcommand_name() == Some("service") && *sink_spec != sink::Spec::InPlace {
+        return Err("The service only supports --sink in-place".to_owned());
+    }
+    let root = Path::new(matches.get_one::<String>("PATH").unwrap());
+    let sink = match sink {
+        Some(sink) => Arc::clone(sink),
+        None => sink::open(sink_spec, root)
+            .map_err(|e| format!("Failed to open the sink: {}", e))?
+            .into(),
+    };
+
+    // The binary's own rewrite is put in the sink after its images' and would
+    // replace it
+    if matches.get_one::<EmbeddedElf>("embedded-elf") == Some(&EmbeddedElf::Process)
+        && *sink_spec != sink::Spec::InPlace
+    {
+        return Err("--embedded-elf process only applies to --sink in-place".to_owned());
+    }
+
+    let backup = match matches.get_one::<backup::Spec>("backup") {
+        // Other sinks leave the scanned binaries as they are
+        Some(_) if *sink_spec != sink::Spec::InPlace => {
+            return Err("--backup only applies to --sink in-place".to_owned())
+        }
+        Some(spec) => match backup::Manifest::open(spec, root) {
+            Ok(manifest) => Some(Mutex::new(manifest)),
+            Err(e) => return Err(format!("Failed to open the backup manifest: {}", e)),
+        },
+        None => None,
+    };
+
+    let dryrun_out = matches.get_one::<String>("dryrun-out");
+    Ok(Options {
+        dryrun: matches.get_flag("dryrun") || dryrun_out.is_some(),
+        dryrun_records: dryrun_out.map(|_| Mutex::new(Vec::new())),
+        aggregate: matches
+            .get_one::<String>("aggregate")
+            .map(|_| Mutex::new(mapping::Mapping::default())),
+        origins: Mutex::new(BTreeMap::new()),
+        manual_mappings,
+        audit_db,
+        backup,
+        check_commits: matches.get_flag("check-commits") || matches.get_flag("require-pushed"),
+        require_pushed: matches.get_flag("require-pushed"),
+        hosts: providers::Hosts {
+            github: string_values(matches, "github-host"),
+            github_style: *matches
+                .get_one::<providers::GitHubStyle>("github-url-style")
+                .unwrap(),
+            gitlab: string_values(matches, "gitlab-host"),
+            bitbucket_server: string_values(matches, "bitbucket-server-host"),
+            gitea: string_values(matches, "gitea-host"),
+            templates: matches
+                .get_many::<providers::Template>("url-template")
+                .map(|templates| templates.cloned().collect())
+                .unwrap_or_default(),
+        },
+        recipients: matches
+            .get_many::<age::x25519::Recipient>("encrypt-to")
+            .map(|recipients| recipients.cloned().collect())
+            .unwrap_or_default(),
+        timeout: matches
+            .get_one::<u64>("timeout")
+            .map(|secs| Duration::from_secs(*secs)),
+        max_section_size: matches.get_one::<usize>("max-section-size").copied(),
+        compress_section: *matches
+            .get_one::<section::Compression>("compress-section")
+            .unwrap(),
+        on_oversize: *matches.get_one::<Oversize>("on-oversize").unwrap(),
+        on_empty: *matches.get_one::<OnEmpty>("on-empty").unwrap(),
+        on_existing: *matches.get_one::<OnExisting>("on-existing").unwrap(),
+        on_conflict: *matches.get_one::<OnConflict>("on-conflict").unwrap(),
+        force: matches.get_flag("force"),
+        chmod: matches.get_one::<u32>("chmod").copied(),
+        missing_sources: matches
+            .get_one::<MissingSources>("missing-sources")
+            .copied(),
+        payload_format: *matches
+            .get_one::<payload::Format>("payload-format")
+            .unwrap(),
+        granularity: *matches.get_one::<Granularity>("granularity").unwrap(),
+        exact: matches.get_flag("exact"),
+        relative_documents: matches.get_flag("relative-documents"),
+        provenance: matches.get_flag("provenance"),
+        with_hashes: matches.get_flag("with-hashes"),
+        lenient: matches.get_flag("lenient"),
+        remote: matches.get_one::<String>("remote").cloned(),
+        commits: matches
+            .get_many::<CommitOverride>("commit")
+            .map(|commits| commits.cloned().collect())
+            .unwrap_or_default(),
+        ref_strategy: *matches.get_one::<RefStrategy>("ref-strategy").unwrap(),
+        hints,
+        embedded_elf: matches.get_one::<EmbeddedElf>("embedded-elf").copied(),
+        prefix_maps: matches
+            .get_many::<prefix_map::PrefixMap>("prefix-map")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .chain(
+                matches
+                    .get_many::<prefix_map::PathMap>("path-map")
+                    .into_iter()
+                    .flatten()
+                    .map(|map| map.0.clone()),
+            )
+            .chain(
+                matches
+                    .get_many::<prefix_map::BuildRoot>("build-root")
+                    .into_iter()
+                    .flatten()
+                    .map(|root| root.0.clone()),
+            )
+            .chain(
+                matches
+                    .get_many::<prefix_map::PathTranslate>("path-translate")
+                    .into_iter()
+                    .flatten()
+                    .map(|translate| translate.0.clone()),
+            )
+            .collect(),
+        ci_env_fallback: matches.get_flag("ci-env-fallback"),
+        exclude_sources: glob_values(matches, "exclude-source"),
+        perforce_url: matches.get_one::<p4::UrlTemplate>("perforce-url").cloned(),
+        host_policy: host_policy::HostPolicy {
+            allow: string_values(matches, "allow-host"),
+            deny: string_values(matches, "deny-host"),
+        },
+        sink,
+        output: if sink_spec.is_stdout() {
+            Output::Quiet
+        } else if matches.contains_id("porcelain") {
+            Output::Porcelain
+        } else {
+            Output::Human
+        },
+        events: None,
+    })
+}
+
+// Append the coverage of the scan of project to --trend-db if given
+fn record_trend(matches: &clap::ArgMatches, project: &str, summary: &summary::Summary) {
+    if let Some(db) = matches.get_one::<String>("trend-db") {
+        let root = Path::new(matches.get_one::<String>("PATH").unwrap());
+        if let Err(e) = trend::append(Path::new(db), project, root, summary) {
+            warn!("Failed to record coverage in {}: {}", db, e);
+        }
+    }
+}
+
+fn exit_on_error<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        error!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+// Fail when two roots of a batch would write the same file, each overwriting
+// what the other wrote. The command line's sink is opened once for the roots
+// that use it, so only sinks of their own can clash.
+fn check_batch_outputs(
+    roots: &[(String, clap::ArgMatches)],
+    matches: &clap::ArgMatches,
+) -> Result<(), String> {
+    let mut written = BTreeMap::<String, &str>::new();
+    for (name, root_matches) in roots {
+        let mut outputs = ["aggregate", "dryrun-out", "scan-cache"]
+            .iter()
+            .filter_map(|option| {
+                let path = root_matches.get_one::<String>(option)?;
+                Some(format!("--{} {}", option, path))
+            })
+            .collect::<Vec<_>>();
+        let sink = root_matches.get_one::<sink::Spec>("sink");
+        if let Some(sink::Spec::Tar(path)) = sink {
+            if sink != matches.get_one::<sink::Spec>("sink") && path != Path::new("-") {
+                outputs.push(format!("--sink tar:{}", path.display()));
+            }
+        }
+        for output in outputs {
+            if let Some(other) = written.insert(output.clone(), name) {
+                return Err(format!(
+                    "Roots {} and {} both write {}, give each root its own",
+                    other, name, output
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Write what the sink holds back after the last binary of a scan
+fn finish_sink(options: &Options) -> Result<(), String> {
+    options
+        .sink
+        .finish()
+        .map_err(|e| format!("Failed to finish writing binaries: {}", e))
+}
+
+// args without --batch, so a root's PATH doesn't conflict with it
+fn without_batch(args: &[std::ffi::OsString]) -> Vec<std::ffi::OsString> {
+    let mut stripped = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--batch" {
+            args.next();
+        } else if !arg.to_string_lossy().starts_with("--batch=") {
+            stripped.push(arg.clone());
+        }
+    }
+    stripped
+}
+
+// Process every binary below PATH, then write the files the options ask for
+fn scan(matches: &clap::ArgMatches, options: &Arc<Options>) -> Result<summary::Summary, String> {
+    let scan_cache = matches.get_one::<String>("scan-cache");
+    if let Some(path) = scan_cache {
+        scan_cache::load(Path::new(path))
+            .map_err(|e| format!("Failed to load scan cache {}: {}", path, e))?;
+    }
+
+    let mut summary = summary::Summary::default();
+    let root = Path::new(matches.get_one::<String>("PATH").unwrap());
+    let include = glob_values(matches, "include");
+    let exclude = glob_values(matches, "exclude");
+    let changed_since = matches.get_one::<SystemTime>("changed-since").copied();
+    let modified = |entry: &walkdir::DirEntry| entry.metadata().ok()?.modified().ok();
+    let mut binaries: Box<dyn Iterator<Item = walkdir::DirEntry>> = Box::new(
+        WalkDir::new(root)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            // Files whose time can't be read are scanned rather than missed
+            .filter(|e| changed_since.is_none_or(|since| modified(e).is_none_or(|t| t >= since)))
+            .filter(|e| is_included(e.path().strip_prefix(root).unwrap(), &include, &exclude))
+            .filter(|e| !is_backup(e.path(), options))
+            .filter(|e| is_possible_symbol_file(e.path())),
+    );
+    // The whole tree is walked before the first binary is processed
+    if matches.get_flag("newest-first") {
+        let mut entries = binaries.map(|e| (modified(&e), e)).collect::<Vec<_>>();
+        entries.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+        binaries = Box::new(entries.into_iter().map(|(_, e)| e));
+    }
+    let mut cargo_artifacts = cargo::Artifacts::default();
+    if !matches.get_flag("all-cargo-artifacts") {
+        binaries = Box::new(binaries.filter(
+            move |e| match cargo_artifacts.skip_reason(e.path()) {
+                Some(reason) => {
+                    debug!("Not processing {} as {}", e.path().display(), reason);
+                    false
+                }
+                None => true,
+            },
+        ));
+    }
+    let limit = matches.get_one::<usize>("limit").copied();
+    for entry in binaries.take(limit.unwrap_or(usize::MAX)) {
+        emit(
+            options,
+            events::Event::FileFound {
+                binary: entry.path().to_owned(),
+            },
+        );
+        summary.record(entry.path(), process_file(entry.path(), options));
+    }
+    summary.origins = std::mem::take(&mut options.origins.lock().unwrap());
+
+    if let Some(path) = scan_cache {
+        if let Err(e) = scan_cache::save() {
+            warn!("Failed to write scan cache {}: {}", path, e);
+        }
+    }
+
+    let dryrun_out = matches.get_one::<String>("dryrun-out");
+    if let (Some(path), Some(records)) = (dryrun_out, &options.dryrun_records) {
+        let records = records.lock().unwrap();
+        let json = serde_json::to_string_pretty(&*records).unwrap();
+        std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    }
+
+    if let (Some(path), Some(aggregate)) =
+        (matches.get_one::<String>("aggregate"), &options.aggregate)
+    {
+        let json = serde_json::json!({ "documents": aggregate.lock().unwrap().documents() });
+        let json = serde_json::to_string_pretty(&json).unwrap();
+        std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    }
+
+    Ok(summary)
+}
+
+struct Options {
+    dryrun: bool,
+    // Machine readable record of what a dry run would do
+    dryrun_records: Option<Mutex<Vec<serde_json::Value>>>,
+    // Mappings of every binary processed, merged
+    aggregate: Option<Mutex<mapping::Mapping>>,
+    // How many binaries were mapped to each repo and revision
+    origins: Mutex<BTreeMap<mapping::Origin, usize>>,
+    manual_mappings: Vec<(PathBuf, String)>,
+    // Where to record digests of embedded sections
+    audit_db: Option<Mutex<audit::Database>>,
+    backup: Option<Mutex<backup::Manifest>>,
+    // Ask the host's API whether commits exist before mapping them
+    check_commits: bool,
+    // Fail when a commit is not on the remote or could not be checked
+    require_pushed: bool,
+    // Self-hosted instances of the supported hosts
+    hosts: providers::Hosts,
+    // Encrypt the section to these recipients when not empty
+    recipients: Vec<age::x25519::Recipient>,
+    // Give up on a binary taking longer than this
+    timeout: Option<Duration>,
+    max_section_size: Option<usize>,
+    compress_section: section::Compression,
+    on_oversize: Oversize,
+    on_empty: OnEmpty,
+    on_existing: OnExisting,
+    on_conflict: OnConflict,
+    // Rewrite binaries whose section already holds the mapping
+    force: bool,
+    // Mode of rewritten binaries instead of the original's
+    chmod: Option<u32>,
+    // Where rewritten binaries go
+    sink: Arc<dyn sink::Sink>,
+    missing_sources: Option<MissingSources>,
+    payload_format: payload::Format,
+    granularity: Granularity,
+    // One key per source file instead of wildcards
+    exact: bool,
+    // Also embed the keys relative to their common prefix
+    relative_documents: bool,
+    // Also embed the repos and commits the mapping links to
+    provenance: bool,
+    // Also embed the SHA-256 of each mapped source file
+    with_hashes: bool,
+    // Map what can be read from binaries with partially corrupt debug info
+    lenient: bool,
+    // Remote to link to instead of the first of upstream, origin, any
+    remote: Option<String>,
+    // Commits to link to instead of HEAD
+    commits: Vec<CommitOverride>,
+    ref_strategy: RefStrategy,
+    // Where the sources of stripped binaries came from, by build id
+    hints: Option<BTreeMap<String, Vec<hints::Hint>>>,
+    embedded_elf: Option<EmbeddedElf>,
+    host_policy: host_policy::HostPolicy,
+    prefix_maps: Vec<prefix_map::PrefixMap>,
+    // Map from the CI system's environment variables when no repo is found
+    ci_env_fallback: bool,
+    // Source files never to map, relative to the root of their repo
+    exclude_sources: Vec<glob::Pattern>,
+    // Where files in Perforce workspaces are served, which also turns on
+    // looking for them
+    perforce_url: Option<p4::UrlTemplate>,
+    output: Output,
+    // Where events::scan gets what happens to each binary from
+    events: Option<mpsc::Sender<events::Event>>,
+}
+
+// What is printed to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Output {
+    Human,
+    // One stable line per binary for scripts
+    Porcelain,
+    // Nothing, for library users that get events instead
+    Quiet,
+}
+
+// How many keys are generated for a repo
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Granularity {
+    // One for the whole workdir
+    Repository,
+    // One for each directory directly below the workdir that has source files
+    Directory,
+}
+
+impl std::str::FromStr for Granularity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repo" => Ok(Granularity::Repository),
+            "directory" => Ok(Granularity::Directory),
+            _ => Err(format!("Unknown granularity \"{}\"", s)),
+        }
+    }
+}
+
+// How to treat source files listed in the debug info that are not on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MissingSources {
+    Warn,
+    Error,
+    // Map the file with the repo of the directory it would be in
+    MapAnyway,
+}
+
+impl std::str::FromStr for MissingSources {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(MissingSources::Warn),
+            "error" => Ok(MissingSources::Error),
+            "map-anyway" => Ok(MissingSources::MapAnyway),
+            _ => Err(format!("Unknown missing sources policy \"{}\"", s)),
+        }
+    }
+}
+
+// A commit to link to instead of HEAD, for the repo checked out at workdir or
+// for every repo. Given as SHA or DIR=SHA.
+#[derive(Debug, Clone)]
+struct CommitOverride {
+    workdir: Option<PathBuf>,
+    commit: git2::Oid,
+}
+
+impl std::str::FromStr for CommitOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (workdir, sha) = match s.rsplit_once('=') {
+            Some((dir, sha)) if !dir.is_empty() => (Some(PathBuf::from(dir)), sha),
+            Some(_) => return Err(format!("Invalid commit \"{}\", expected SHA or DIR=SHA", s)),
+            None => (None, s),
+        };
+        // Oid::from_str pads abbreviated hashes with zeros
+        if sha.len() != 40 || !sha.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("Invalid commit \"{}\", expected a full SHA", sha));
+        }
+        let commit = git2::Oid::from_str(sha).map_err(|e| e.message().to_owned())?;
+        Ok(CommitOverride { workdir, commit })
+    }
+}
+
+// The commit given for the repo at workdir, before one given for every repo
+fn commit_override(overrides: &[CommitOverride], workdir: &Path) -> Option<git2::Oid> {
+    overrides
+        .iter()
+        .find(|o| o.workdir.as_deref() == Some(workdir))
+        .or_else(|| overrides.iter().find(|o| o.workdir.is_none()))
+        .map(|o| o.commit)
+}
+
+// What generated URLs link to. Mirrors that garbage collect unreachable
+// commits keep tags, and branches are readable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RefStrategy {
+    Commit,
+    // A tag on the commit
+    Tag,
+    // The upstream branch the commit is checked out on
+    Branch,
+}
+
+impl std::str::FromStr for RefStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "commit" => Ok(RefStrategy::Commit),
+            "tag" => Ok(RefStrategy::Tag),
+            "branch" => Ok(RefStrategy::Branch),
+            _ => Err(format!("Unknown ref strategy \"{}\"", s)),
+        }
+    }
+}
+
+// What to do with ELF images embedded in binaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EmbeddedElf {
+    Report,
+    // Also write their mapping into a section reserved in them
+    Process,
+}
+
+impl std::str::FromStr for EmbeddedElf {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "report" => Ok(EmbeddedElf::Report),
+            "process" => Ok(EmbeddedElf::Process),
+            _ => Err(format!("Unknown embedded ELF mode \"{}\"", s)),
+        }
+    }
+}
+
+// What to do with a binary none of whose source files could be mapped. Warn
+// and Error only set the level of W017.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnEmpty {
+    Skip,
+    Warn,
+    Error,
+    // Embed a mapping without documents, recording the binary was processed
+    EmbedEmpty,
+}
+
+impl std::str::FromStr for OnEmpty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(OnEmpty::Skip),
+            "warn" => Ok(OnEmpty::Warn),
+            "error" => Ok(OnEmpty::Error),
+            "embed-empty" => Ok(OnEmpty::EmbedEmpty),
+            _ => Err(format!("Unknown empty mapping policy \"{}\"", s)),
+        }
+    }
+}
+
+// What to do with binaries that already have a section, like ones stamped by
+// an earlier run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnExisting {
+    Replace,
+    // Leave them as they are
+    Skip,
+    // Keep their documents and add the new ones, see OnConflict
+    Merge,
+}
+
+impl std::str::FromStr for OnExisting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "replace" => Ok(OnExisting::Replace),
+            "skip" => Ok(OnExisting::Skip),
+            "merge" => Ok(OnExisting::Merge),
+            _ => Err(format!("Unknown existing section policy \"{}\"", s)),
+        }
+    }
+}
+
+// What to do with a key an existing section maps to another URL than the new
+// mapping, for --on-existing merge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnConflict {
+    Keep,
+    Replace,
+    // Leave the binary as it is
+    Fail,
+}
+
+impl std::str::FromStr for OnConflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(OnConflict::Keep),
+            "replace" => Ok(OnConflict::Replace),
+            "fail" => Ok(OnConflict::Fail),
+            _ => Err(format!("Unknown conflict policy \"{}\"", s)),
+        }
+    }
+}
+
+// Appended to the name of a binary for the file --on-oversize sidecar writes
+const SIDECAR_SUFFIX: &str = ".sourcelink.json";
+
+// What to do with a mapping larger than --max-section-size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Oversize {
+    // Collapse exact and narrowed keys into the wildcard of their repo, then
+    // truncate if that isn't enough
+    Wildcards,
+    // Drop the most specific entries until it fits
+    Truncate,
+    // Write the JSON next to the binary and only embed a reference to it
+    Sidecar,
+}
+
+impl std::str::FromStr for Oversize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wildcards" => Ok(Oversize::Wildcards),
+            "truncate" => Ok(Oversize::Truncate),
+            "sidecar" => Ok(Oversize::Sidecar),
+            _ => Err(format!("Unknown oversize strategy \"{}\"", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    // Not a binary with usable debug info, or one whose section was kept
+    Skipped,
+    // No source file could be mapped
+    Unmapped,
+    DryRun,
+    Updated,
+    // Mapped, and the section already held the mapping
+    Unchanged,
+    Failed,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Skipped => "skipped",
+            Outcome::Unmapped => "unmapped",
+            Outcome::DryRun => "dryrun",
+            Outcome::Updated => "updated",
+            Outcome::Unchanged => "unchanged",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+fn process_file(entry: &Path, options: &Arc<Options>) -> Outcome {
+    // Stamping one would overwrite the copy of the unstamped binary
+    if is_backup(entry, options) {
+        debug!("Not processing the backup {}", entry.display());
+        return Outcome::Skipped;
+    }
+
+    // Before the binary itself is rewritten, which may move the images
+    if let Some(mode) = options.embedded_elf {
+        process_embedded_images(entry, mode, options);
+    }
+
+    let start = Instant::now();
+    let deadline = options.timeout.map(|timeout| start + timeout);
+    let outcome = match binary_mapping_until(entry, options, deadline)
+        .and_then(|mapping| check_hosts(entry, mapping, options))
+    {
+        Ok(mapping) => {
+            if let Some(aggregate) = &options.aggregate {
+                aggregate.lock().unwrap().extend(&mapping);
+            }
+            emit(
+                options,
+                events::Event::MappingComputed {
+                    binary: entry.to_owned(),
+                    documents: mapping.documents().into(),
+                },
+            );
+            let outcome = embed_mapping(entry, &mapping, options);
+            if matches!(
+                outcome,
+                Outcome::Updated | Outcome::Unchanged | Outcome::DryRun
+            ) {
+                let mut origins = options.origins.lock().unwrap();
+                for origin in mapping.origins() {
+                    *origins.entry(origin.clone()).or_default() += 1;
+                }
+            }
+            outcome
+        }
+        Err(Outcome::Unmapped) => {
+            diag!(
+                Code::EmptyMapping,
+                file: entry,
+                "No source file of {} could be mapped",
+                entry.display()
+            );
+            if options.on_empty == OnEmpty::EmbedEmpty {
+                embed_mapping(entry, &mapping::Mapping::default(), options)
+            } else {
+                record_dryrun(options, entry, serde_json::Value::Null, "skip");
+                Outcome::Unmapped
+            }
+        }
+        Err(outcome) => outcome,
+    };
+    metrics::processed(outcome, start.elapsed());
+    if options.output == Output::Porcelain {
+        // v1 has no status of its own for binaries that already held their
+        // mapping, which is what updated tells scripts
+        let status = match outcome {
+            Outcome::Unchanged => Outcome::Updated,
+            outcome => outcome,
+        };
+        porcelain::print(status.as_str(), entry, None);
+    }
+    emit(options, events::Event::from_outcome(entry, outcome));
+    outcome
+}
+
+fn emit(options: &Options, event: events::Event) {
+    if let Some(events) = &options.events {
+        // Nobody is listening once the receiver is dropped
+        let _ = events.send(event);
+    }
+}
+
+// Report the ELF images embedded in entry and, with EmbeddedElf::Process, write
+// their mapping into the sourcelink section reserved in them
+fn process_embedded_images(entry: &Path, mode: EmbeddedElf, options: &Options) {
+    let data = match std::fs::read(entry) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Failed to read {}: {}", entry.display(), e);
+            return;
+        }
+    };
+    throttle::io(data.len() as u64);
+
+    let images = embedded::find(&data);
+    let mut patched = match mode {
+        EmbeddedElf::Report => Vec::new(),
+        EmbeddedElf::Process => data.clone(),
+    };
+    let mut outcomes = Vec::new();
+    for image in &images {
+        if options.output == Output::Human {
+            println!(
+                "Embedded ELF image at offset {} in {}",
+                image.offset,
+                entry.display()
+            );
+        }
+        outcomes.push(match mode {
+            EmbeddedElf::Report => None,
+            EmbeddedElf::Process => Some(embed_image(entry, &data, image, &mut patched, options)),
+        });
+    }
+
+    // Every image is written in one rewrite of the binary, which goes the way
+    // the rewrite of a binary's own section does
+    if outcomes.contains(&Some(Outcome::Updated)) {
+        throttle::io(patched.len() as u64);
+        if let Err(e) = put_rewrite(entry, &data, &patched, options) {
+            diag!(
+                Code::EmbedFailed,
+                file: entry,
+                "Failed to write the images in {}: {}",
+                entry.display(),
+                e
+            );
+            for outcome in outcomes.iter_mut().flatten() {
+                if *outcome == Outcome::Updated {
+                    *outcome = Outcome::Failed;
+                }
+            }
+        }
+    }
+
+    for (image, outcome) in images.iter().zip(outcomes) {
+        if let (Some(Outcome::Updated | Outcome::Unchanged), Some(section)) =
+            (outcome, &image.section)
+        {
+            if outcome == Some(Outcome::Updated) && options.output == Output::Human {
+                println!(
+                    "Updated image at offset {} in {}",
+                    image.offset,
+                    entry.display()
+                );
+            }
+            record_image_audit(entry, image.offset, &patched[section.clone()], options);
+        }
+        if options.output == Output::Porcelain {
+            let status = outcome.map_or("image", Outcome::as_str);
+            porcelain::print(status, entry, Some(image.offset));
+        }
+    }
+}
+
+// Write the mapping of image, one of the images in data, the content of entry,
+// into the same place in patched. Updated means patched changed, or --force
+// asks for the binary to be rewritten anyway.
+fn embed_image(
+    entry: &Path,
+    data: &[u8],
+    image: &embedded::Image,
+    patched: &mut [u8],
+    options: &Options,
+) -> Outcome {
+    let source_files = match compiledfiles::parse(Cursor::new(&data[image.offset..])) {
+        Ok(source_files) if !source_files.is_empty() => source_files,
+        _ => {
+            debug!("Image at offset {} has no source files", image.offset);
+            return Outcome::Skipped;
+        }
+    };
+    let mapping = match source_files_mapping(entry, source_files, options)
+        .and_then(|mapping| check_hosts(entry, mapping, options))
+    {
+        Ok(mapping) => mapping,
+        Err(outcome) => return outcome,
+    };
+
+    // Sections can't be added to an image inside another file, only
+    // overwritten, and only with plain JSON since it may be padded
+    let section = match &image.section {
+        Some(section) => section.clone(),
+        None => {
+            warn!(
+                "Not embedding into the image at offset {} in {}, it has no {} section",
+                image.offset,
+                entry.display(),
+                ELF_SOURCE_LINK_SECTION_NAME
+            );
+            return Outcome::Failed;
+        }
+    };
+    if options.payload_format != payload::Format::Json || !options.recipients.is_empty() {
+        warn!(
+            "Not embedding into the image at offset {} in {}, only plain JSON can be written in place",
+            image.offset,
+            entry.display()
+        );
+        return Outcome::Failed;
+    }
+
+    // A section reserved at build time holds only padding
+    let existing = data[section.clone()].trim_ascii_end();
+    let existing = existing
+        .iter()
+        .rposition(|&b| b != 0)
+        .map(|end| existing[..=end].trim_ascii())
+        .filter(|existing| !existing.is_empty());
+    let mut json = section_json(&mapping, options);
+    if let Some(existing) = existing {
+        match options.on_existing {
+            OnExisting::Skip => {
+                if options.output == Output::Human {
+                    println!(
+                        "Kept the existing {} section of the image at offset {} in {}",
+                        ELF_SOURCE_LINK_SECTION_NAME,
+                        image.offset,
+                        entry.display()
+                    );
+                }
+                return Outcome::Skipped;
+            }
+            OnExisting::Merge => {
+                json = match merge_documents(entry, Ok(Some(existing.to_vec())), json, options) {
+                    Ok(json) => json,
+                    Err(outcome) => return outcome,
+                };
+            }
+            _ => {}
+        }
+    }
+    let mut bytes = serde_json::to_vec(&json).unwrap();
+    if bytes.len() > section.len() {
+        warn!(
+            "Not embedding into the image at offset {} in {}, the mapping needs {} bytes but {} are reserved",
+            image.offset,
+            entry.display(),
+            bytes.len(),
+            section.len()
+        );
+        return Outcome::Failed;
+    }
+    if existing == Some(&bytes[..]) && !options.force {
+        if options.output == Output::Human {
+            println!(
+                "Unchanged image at offset {} in {}",
+                image.offset,
+                entry.display()
+            );
+        }
+        return Outcome::Unchanged;
+    }
+    // JSON allows trailing whitespace
+    bytes.resize(section.len(), b' ');
+
+    if options.dryrun {
+        if options.output == Output::Human {
+            println!(
+                "Would update image at offset {} in {}",
+                image.offset,
+                entry.display()
+            );
+            println!("{}", serde_json::to_string_pretty(&json).unwrap());
+            println!();
+        }
+        return Outcome::DryRun;
+    }
+    patched[section].copy_from_slice(&bytes);
+    Outcome::Updated
+}
+
+// Fail the mapping if any of its URLs point at a host the policy rules out
+fn check_hosts(
+    entry: &Path,
+    mapping: mapping::Mapping,
+    options: &Options,
+) -> Result<mapping::Mapping, Outcome> {
+    let mut allowed = true;
+    for (dir, entry_url) in mapping.entries() {
+        let host = url::Url::parse(&entry_url.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+            .unwrap_or_default();
+        if !options.host_policy.allows(&host) {
+            diag!(
+                Code::HostNotAllowed,
+                file: dir,
+                "Not updating {}, {} would be mapped to host \"{}\"",
+                entry.display(),
+                dir.display(),
+                host
+            );
+            allowed = false;
+        }
+    }
+    if allowed {
+        Ok(mapping)
+    } else {
+        Err(Outcome::Failed)
+    }
+}
+
+fn record_dryrun(options: &Options, binary: &Path, mapping: serde_json::Value, action: &str) {
+    if let Some(records) = &options.dryrun_records {
+        records.lock().unwrap().push(serde_json::json!({
+            "binary": binary,
+            "mapping": mapping,
+            "action": action,
+        }));
+    }
+}
+
+// The JSON to embed for mapping
+fn section_json(mapping: &mapping::Mapping, options: &Options) -> serde_json::Value {
+    let mut json = serde_json::json!({ "documents": mapping.documents() });
+    if options.relative_documents {
+        if let Some(prefix) = mapping.common_prefix() {
+            json["buildRoot"] = serde_json::json!(prefix);
+            json["relativeDocuments"] =
+                serde_json::Value::Object(mapping.relative_documents(&prefix));
+        }
+    }
+    if options.with_hashes {
+        json["hashes"] = serde_json::Value::Object(mapping.hashes());
+    }
+    if options.provenance {
+        json["provenance"] = mapping
+            .origins()
+            .into_iter()
+            .map(mapping::Origin::to_json)
+            .collect();
+    }
+    json
+}
+
+// The JSON to embed for mapping, kept under max_bytes according to the
+// --on-oversize strategy
+fn limit_section_size(
+    entry: &Path,
+    mapping: &mapping::Mapping,
+    max_bytes: usize,
+    options: &Options,
+) -> Result<(serde_json::Value, Option<Vec<u8>>), Outcome> {
+    let json = section_json(mapping, options);
+    let size = serde_json::to_vec(&json).unwrap().len();
+    if size <= max_bytes {
+        return Ok((json, None));
+    }
+
+    if options.on_oversize == Oversize::Sidecar {
+        return sidecar(entry, &json, size);
+    }
+    let mut mapping = mapping.clone();
+    if options.on_oversize == Oversize::Wildcards {
+        let collapsed = mapping.collapse();
+        let json = section_json(&mapping, options);
+        let collapsed_size = serde_json::to_vec(&json).unwrap().len();
+        if collapsed_size <= max_bytes {
+            diag!(
+                Code::SectionTooLarge,
+                file: entry,
+                "Mapping of {} is {} bytes, collapsed {} entries into wildcards to fit in {} bytes",
+                entry.display(),
+                size,
+                collapsed,
+                max_bytes
+            );
+            return Ok((json, None));
+        }
+    }
+
+    // The hashes, provenance and relative documents only shrink with the
+    // documents, so their budget shrinks until the whole section fits
+    let mut budget = max_bytes;
+    let mut dropped = 0;
+    loop {
+        dropped += mapping.truncate(budget);
+        let json = section_json(&mapping, options);
+        let truncated_size = serde_json::to_vec(&json).unwrap().len();
+        if truncated_size > max_bytes && mapping.is_empty() {
+            diag!(
+                Code::SectionTooLarge,
+                file: entry,
+                "Mapping of {} is {} bytes, and doesn't fit in {} bytes even without documents",
+                entry.display(),
+                size,
+                max_bytes
+            );
+            return Err(Outcome::Failed);
+        }
+        if truncated_size <= max_bytes {
+            diag!(
+                Code::SectionTooLarge,
+                file: entry,
+                "Mapping of {} is {} bytes, dropped {} entries to fit in {} bytes",
+                entry.display(),
+                size,
+                dropped,
+                max_bytes
+            );
+            return Ok((json, None));
+        }
+        budget = (budget as u64 * max_bytes as u64 / truncated_size as u64) as usize;
+        budget = budget.saturating_sub(1);
+    }
+}
+
+// The reference to embed instead of json, size bytes, and the data of the
+// file next to the binary that holds it
+fn sidecar(
+    entry: &Path,
+    json: &serde_json::Value,
+    size: usize,
+) -> Result<(serde_json::Value, Option<Vec<u8>>), Outcome> {
+    let name = match entry.file_name() {
+        Some(name) => format!("{}{}", name.to_string_lossy(), SIDECAR_SUFFIX),
+        None => {
+            diag!(
+                Code::EmbedFailed,
+                "Failed to name the sidecar of {}",
+                entry.display()
+            );
+            return Err(Outcome::Failed);
+        }
+    };
+    diag!(
+        Code::SectionTooLarge,
+        file: entry,
+        "Mapping of {} is {} bytes, writing it to {} instead",
+        entry.display(),
+        size,
+        name
+    );
+    let reference = serde_json::json!({
+        "documents": {},
+        "sidecar": name,
+    });
+    Ok((reference, Some(serde_json::to_vec(json).unwrap())))
+}
+
+// Write data, the sidecar of the binary at entry, next to it
+fn put_sidecar(entry: &Path, data: &[u8], options: &Options) -> Result<(), Outcome> {
+    options
+        .sink
+        .put_beside(entry, SIDECAR_SUFFIX, data)
+        .map_err(|e| {
+            diag!(
+                Code::EmbedFailed,
+                "Failed to write the sidecar of {}: {}",
+                entry.display(),
+                e
+            );
+            Outcome::Failed
+        })
+}
+
+// The mapping of a stripped binary from the hints recorded for its build id
+fn hints_mapping(
+    entry: &Path,
+    hints: &BTreeMap<String, Vec<hints::Hint>>,
+    options: &Options,
+) -> Result<mapping::Mapping, Outcome> {
+    let build_id = match elf::build_id(entry) {
+        Ok(Some(build_id)) => hex::encode(build_id),
+        Ok(None) => {
+            debug!("{} has no build id", entry.display());
+            return Err(Outcome::Skipped);
+        }
+        Err(e) => {
+            warn!("Failed to read the build id of {}: {}", entry.display(), e);
+            return Err(Outcome::Skipped);
+        }
+    };
+    let hints = match hints.get(&build_id) {
+        Some(hints) => hints,
+        None => {
+            debug!("No hints for {} ({})", entry.display(), build_id);
+            return Err(Outcome::Skipped);
+        }
+    };
+
+    let mut mapping = hints_to_mapping(hints, options);
+    // Keys must be the names debug info has for the files, not where they are
+    // on this machine
+    mapping.rename(|dir| prefix_map::to_debug(&options.prefix_maps, dir));
+    if mapping.is_empty() {
+        return Err(Outcome::Unmapped);
+    }
+    Ok(mapping)
+}
+
+// Map the directory of each hint to its remote at its commit
+fn hints_to_mapping(hints: &[hints::Hint], options: &Options) -> mapping::Mapping {
+    // Hints record the remote as configured, repos found on disk get their
+    // insteadOf rewrites from libgit2 when the remote is looked up
+    let config = git2::Config::open_default().ok();
+
+    let mut mapping = mapping::Mapping::default();
+    for hint in hints {
+        let hint_remote = match &config {
+            Some(config) => apply_instead_of(config, &hint.remote),
+            None => hint.remote.clone(),
+        };
+        let mut remote = match providers::parse_remote(&hint_remote) {
+            Ok(remote) => remote,
+            Err(e) => {
+                diag!(
+                    Code::InvalidRemoteUrl,
+                    "Skipping hint for {}. Unable to parse url due to: {}",
+                    hint.path.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        providers::strip_credentials(&mut remote);
+        let hash = match git2::Oid::from_str(&hint.commit) {
+            Ok(hash) => hash,
+            Err(e) => {
+                warn!(
+                    "Skipping hint for {}. Invalid commit {}: {}",
+                    hint.path.display(),
+                    hint.commit,
+                    e
+                );
+                continue;
+            }
+        };
+        let rev = providers::Revision::Commit(hash);
+        if let Some(url) = providers::generate_url(&remote, &rev, &options.hosts) {
+            let origin = mapping::Origin::new(remote.to_string(), rev.to_string());
+            mapping.insert_repo(&hint.path, url.into(), origin);
+        }
+    }
+    mapping
+}
+
+// The source files of the units of entry that can still be read after
+// compiledfiles failed with error
+fn lenient_source_files(
+    entry: &Path,
+    error: &compiledfiles::Error,
+) -> Result<Vec<compiledfiles::FileInfo>, Outcome> {
+    match dwarf::source_files(entry) {
+        Ok((files, corrupt)) if !files.is_empty() => {
+            diag!(
+                Code::PartialDebugInfo,
+                file: entry,
+                "Mapping {} source files of {}, skipped {} corrupt units ({})",
+                files.len(),
+                entry.display(),
+                corrupt,
+                error
+            );
+            Ok(files
+                .into_iter()
+                .map(|path| compiledfiles::FileInfo {
+                    path,
+                    size: None,
+                    timestamp: None,
+                    checksum: None,
+                })
+                .collect())
+        }
+        _ => {
+            diag!(
+                Code::ParseError,
+                "Unexpected parsing error of known file \"{}\": {}",
+                entry.display(),
+                error
+            );
+            Err(Outcome::Skipped)
+        }
+    }
+}
+
+// binary_mapping, but giving up once deadline has passed. The mapping is
+// generated on another thread which is abandoned on timeout. That can't be
+// interrupted, so it checks the deadline between stages, and reports no
+// diagnostics or metrics once the binary was recorded as failed.
+fn binary_mapping_until(
+    entry: &Path,
+    options: &Arc<Options>,
+    deadline: Option<Instant>,
+) -> Result<mapping::Mapping, Outcome> {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return binary_mapping(entry, options),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let thread_entry = entry.to_owned();
+    let thread_options = Arc::clone(options);
+    std::thread::spawn(move || {
+        diagnostics::set_deadline(Some(deadline));
+        let _ = tx.send(binary_mapping(&thread_entry, &thread_options));
+    });
+    match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            diag!(
+                Code::Timeout,
+                file: entry,
+                "Gave up generating the mapping of {} after {}s",
+                entry.display(),
+                options.timeout.unwrap_or_default().as_secs()
+            );
+            Err(Outcome::Failed)
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            error!("Generating the mapping of {} panicked", entry.display());
+            Err(Outcome::Failed)
+        }
+    }
+}
+
+// Generate the mapping for the binary at entry. Fails with the outcome to
+// report when there is nothing to embed.
+fn binary_mapping(entry: &Path, options: &Options) -> Result<mapping::Mapping, Outcome> {
+    trace!("Checking {} for embedded sources", entry.display());
+    diagnostics::set_binary(Some(entry));
+    // It may be gone by now, like a binary the daemon saw that was removed
+    // after it settled
+    let file = match File::open(entry) {
+        Ok(file) => file,
+        Err(e) => {
+            diag!(
+                Code::ParseError,
+                "Failed to open {}: {}",
+                entry.display(),
+                e
+            );
+            return Err(Outcome::Failed);
+        }
+    };
+    throttle::io(file.metadata().map_or(0, |metadata| metadata.len()));
+
+    // get list of embedded source files
+    let source_files = match compiledfiles::parse(file) {
+        Ok(files) => files,
+        Err(e) => match e {
+            compiledfiles::Error::MissingDebugSymbols => {
+                debug!("{} is missing debug symbols", entry.display());
+                return match &options.hints {
+                    Some(hints) => hints_mapping(entry, hints, options),
+                    None => Err(Outcome::Skipped),
+                };
+            }
+            compiledfiles::Error::UnrecognizedFileFormat => {
+                debug!("{} is an unrecognized format", entry.display());
+                return Err(Outcome::Skipped);
+            }
+            _ if options.lenient => lenient_source_files(entry, &e)?,
+            _ => {
+                diag!(
+                    Code::ParseError,
+                    "Unexpected parsing error of known file \"{}\": {}",
+                    entry.display(),
+                    e
+                );
+                return Err(Outcome::Skipped);
+            }
+        },
+    };
+
+    if source_files.is_empty() {
+        diag!(
+            Code::NoSourceFiles,
+            "{} was parsed but contained no source files",
+            entry.display()
+        );
+        return Err(Outcome::Skipped);
+    }
+
+    trace!(
+        "{} contains {} source files",
+        entry.display(),
+        source_files.len()
+    );
+
+    check_deadline()?;
+    source_files_mapping(entry, source_files, options)
+}
+
+// Stop generating a mapping binary_mapping_until gave up on
+fn check_deadline() -> Result<(), Outcome> {
+    if diagnostics::abandoned() {
+        Err(Outcome::Failed)
+    } else {
+        Ok(())
+    }
+}
+
+fn source_files_mapping(
+    entry: &Path,
+    mut source_files: Vec<compiledfiles::FileInfo>,
+    options: &Options,
+) -> Result<mapping::Mapping, Outcome> {
+    // Look for files named by a prefix map where they are on this machine
+    let mut unmapped = None;
+    for file in &mut source_files {
+        if let Some(path) = prefix_map::to_local(&options.prefix_maps, &file.path) {
+            file.path = path;
+        } else if unmapped.is_none() {
+            unmapped = options
+                .prefix_maps
+                .iter()
+                .find(|map| file.path.starts_with(&map.old));
+        }
+    }
+    if let Some(map) = unmapped {
+        warn!(
+            "{} names files under {}, was it built with -fdebug-prefix-map={}={}?",
+            entry.display(),
+            map.old.display(),
+            map.old.display(),
+            map.new.display()
+        );
+    }
+    let (rust_std, source_files): (Vec<_>, Vec<_>) = source_files
+        .into_iter()
+        .partition(|file| rustc_commit(&file.path).is_some());
+    let (crates, source_files): (Vec<_>, Vec<_>) = source_files
+        .into_iter()
+        .partition(|file| registry::package_dir(&file.path).is_some());
+    let (packaged, source_files): (Vec<_>, Vec<_>) = source_files
+        .into_iter()
+        .partition(|file| packages::source_dir(&file.path).is_some());
+    let source_files = &source_files[..];
+
+    // Files in no git repo may be in a Subversion working copy or, when
+    // there is a URL for it, a Perforce workspace
+    let svn = svn::Svn::default();
+    let p4 = options.perforce_url.clone().map(p4::P4::new);
+    let mut backends: Vec<&dyn VcsBackend> = vec![&Git, &svn];
+    if let Some(p4) = &p4 {
+        backends.push(p4);
+    }
+    let checkouts = checkouts_from_source_files(&backends, source_files, options);
+    check_deadline()?;
+    trace!(
+        "Found {} checkouts for {}",
+        checkouts.found.len(),
+        entry.display()
+    );
+    // generate mapping of directories to urls
+    let mut mapping = generate_mapping(&checkouts, source_files, options);
+    mapping.extend(&rust_std_mapping(&rust_std, options));
+    mapping.extend(&registry_mapping(&crates, options));
+    mapping.extend(&packages_mapping(&packaged, options));
+    for submodule in &checkouts.pinned {
+        let mut remote = match providers::parse_remote(&submodule.remote) {
+            Ok(remote) => remote,
+            Err(e) => {
+                diag!(
+                    Code::InvalidRemoteUrl,
+                    "Skipping submodule {}. Unable to parse url due to: {}",
+                    submodule.workdir.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        providers::strip_credentials(&mut remote);
+        let commit = match commit_override(&options.commits, &submodule.workdir) {
+            Some(commit) => {
+                debug!(
+                    "Linking submodule {} to {} instead of {}",
+                    submodule.workdir.display(),
+                    commit,
+                    submodule.commit
+                );
+                commit
+            }
+            None => submodule.commit,
+        };
+        match providers::generate_url(
+            &remote,
+            &providers::Revision::Commit(commit),
+            &options.hosts,
+        ) {
+            Some(url) => {
+                let origin = mapping::Origin::new(remote.to_string(), commit.to_string());
+                mapping.insert_repo(&submodule.workdir, url.into(), origin);
+            }
+            None => warn!(
+                "Skipping submodule {}. Unable to generate url",
+                submodule.workdir.display()
+            ),
+        }
+    }
+
+    if checkouts.found.is_empty() && checkouts.pinned.is_empty() && options.ci_env_fallback {
+        match ci_env::detect() {
+            Some(checkout)
+                if source_files
+                    .iter()
+                    .any(|file| file.path.starts_with(&checkout.path)) =>
+            {
+                debug!(
+                    "No repo found for {}, mapping {} from the CI environment",
+                    entry.display(),
+                    checkout.path.display()
+                );
+                mapping.extend(&hints_to_mapping(&[checkout], options));
+            }
+            Some(checkout) => debug!(
+                "No source file of {} is in the CI workspace {}",
+                entry.display(),
+                checkout.path.display()
+            ),
+            None => debug!("No checkout described by the CI environment"),
+        }
+    }
+    for (dir, url) in &options.manual_mappings {
+        if source_files.iter().any(|file| file.path.starts_with(dir)) {
+            mapping.insert(dir, url.clone(), mapping::Priority::Manual);
+        }
+    }
+
+    if options.exact {
+        let files = source_files
+            .iter()
+            .chain(&rust_std)
+            .chain(&crates)
+            .chain(&packaged);
+        mapping = mapping.exact(files.map(|file| file.path.as_path()));
+    }
+    if options.with_hashes {
+        let files = source_files
+            .iter()
+            .chain(&rust_std)
+            .chain(&crates)
+            .chain(&packaged);
+        for file in files {
+            match std::fs::read(&file.path) {
+                Ok(content) => {
+                    throttle::io(content.len() as u64);
+                    let hash = hex::encode(Sha256::digest(&content));
+                    mapping.insert_hash(&file.path, hash);
+                }
+                Err(e) => trace!("Not hashing {}: {}", file.path.display(), e),
+            }
+        }
+    }
+
+    // Keys must be the names debug info has for the files, not where they are
+    // on this machine
+    mapping.rename(|dir| prefix_map::to_debug(&options.prefix_maps, dir));
+    if mapping.is_empty() {
+        return Err(Outcome::Unmapped);
+    }
+    Ok(mapping)
+}
+
+fn embed_mapping(entry: &Path, mapping: &mapping::Mapping, options: &Options) -> Outcome {
+    let (json, sidecar) = match options.max_section_size {
+        Some(max_bytes) => match limit_section_size(entry, mapping, max_bytes, options) {
+            Ok(limited) => limited,
+            Err(outcome) => return outcome,
+        },
+        None => (section_json(mapping, options), None),
+    };
+    let json = match options.on_existing {
+        OnExisting::Merge => match merge_existing(entry, json, options) {
+            Ok(json) => json,
+            Err(outcome) => return outcome,
+        },
+        _ => json,
+    };
+    if options.dryrun {
+        if options.output == Output::Human {
+            println!("Would update {}", entry.display());
+            println!("{}", serde_json::to_string_pretty(&json).unwrap());
+            println!();
+        }
+        record_dryrun(options, entry, json, "update");
+        return Outcome::DryRun;
+    }
+
+    // Where the binary is reported at, which fails if it was removed since
+    let path = match std::fs::canonicalize(entry) {
+        Ok(path) => path,
+        Err(e) => {
+            if options.output == Output::Human {
+                println!("Failed to update {}", entry.display());
+            }
+            diag!(
+                Code::EmbedFailed,
+                "Failed to update {}: {}",
+                entry.display(),
+                e
+            );
+            return Outcome::Failed;
+        }
+    };
+    let mut section = payload::encode(options.payload_format, &json);
+    if !options.recipients.is_empty() {
+        section = match encryption::encrypt(&options.recipients, &section) {
+            Ok(section) => section,
+            Err(e) => {
+                if options.output == Output::Human {
+                    println!("Failed to update {}", path.display());
+                }
+                diag!(
+                    Code::EmbedFailed,
+                    "Failed to encrypt the section of {}: {}",
+                    entry.display(),
+                    e
+                );
+                return Outcome::Failed;
+            }
+        };
+    }
+
+    let rewritten = rewrite(entry, &section, options);
+    // Only once the binary refers to it, so a failed rewrite leaves none behind
+    if let (Ok(rewrite), Some(data)) = (&rewritten, &sidecar) {
+        if *rewrite != Rewrite::Kept {
+            if let Err(outcome) = put_sidecar(entry, data, options) {
+                if options.output == Output::Human {
+                    println!("Failed to update {}", path.display());
+                }
+                return outcome;
+            }
+        }
+    }
+    match rewritten {
+        Ok(Rewrite::Kept) => {
+            if options.output == Output::Human {
+                println!(
+                    "Kept the existing {} of {}",
+                    sourcelink_container(entry),
+                    path.display()
+                );
+            }
+            Outcome::Skipped
+        }
+        Ok(Rewrite::Unchanged) => {
+            if options.output == Output::Human {
+                println!("Unchanged {}", path.display());
+            }
+            record_audit(entry, &section, options);
+            Outcome::Unchanged
+        }
+        Ok(rewrite) => {
+            if options.output == Output::Human {
+                let replaced = match rewrite {
+                    Rewrite::Replaced => {
+                        format!(", replacing its {}", sourcelink_container(entry))
+                    }
+                    Rewrite::Merged => {
+                        format!(", merging into its {}", sourcelink_container(entry))
+                    }
+                    _ => String::new(),
+                };
+                println!("Updated {}{}", path.display(), replaced);
+            }
+            record_audit(entry, &section, options);
+            Outcome::Updated
+        }
+        Err(e) => {
+            if options.output == Output::Human {
+                println!("Failed to update {}", path.display());
+            }
+            diag!(
+                Code::EmbedFailed,
+                "Failed to update {}: {}",
+                entry.display(),
+                e
+            );
+            Outcome::Failed
+        }
+    }
+}
+
+fn record_image_audit(entry: &Path, offset: usize, section: &[u8], options: &Options) {
+    if let Some(db) = &options.audit_db {
+        if let Err(e) = db.lock().unwrap().record_image(entry, offset, section) {
+            error!(
+                "Failed to record the image at offset {} in {} in audit database: {}",
+                offset,
+                entry.display(),
+                e
+            );
+        }
+    }
+}
+
+fn record_audit(entry: &Path, section: &[u8], options: &Options) {
+    if let Some(db) = &options.audit_db {
+        if let Err(e) = db.lock().unwrap().record(entry, section) {
+            error!(
+                "Failed to record {} in audit database: {}",
+                entry.display(),
+                e
+            );
+        }
+    }
+}
+
+// json with the documents of the section the binary at entry already has
+// merged in. Its other fields describe the new mapping, so they are the new
+// ones.
+fn merge_existing(
+    entry: &Path,
+    json: serde_json::Value,
+    options: &Options,
+) -> Result<serde_json::Value, Outcome> {
+    merge_documents(entry, read_sourcelink(entry), json, options)
+}
+
+// json with the documents of existing, the section read from entry or one of
+// the images in it, merged in
+fn merge_documents(
+    entry: &Path,
+    existing: Result<Option<Vec<u8>>, std::io::Error>,
+    mut json: serde_json::Value,
+    options: &Options,
+) -> Result<serde_json::Value, Outcome> {
+    let existing = match existing {
+        Ok(Some(section)) if encryption::is_encrypted(&section) => {
+            Err("the section is encrypted".to_owned())
+        }
+        Ok(Some(section)) => payload::decode(&section).map_err(|e| e.to_string()),
+        Ok(None) => return Ok(json),
+        Err(e) => Err(e.to_string()),
+    };
+    let existing = match existing {
+        Ok(existing) => existing,
+        Err(e) => {
+            diag!(
+                Code::EmbedFailed,
+                "Unable to merge into the section of {}: {}",
+                entry.display(),
+                e
+            );
+            return Err(Outcome::Failed);
+        }
+    };
+
+    let mut documents = existing["documents"]
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    let mut conflicts = 0;
+    for (key, url) in json["documents"].as_object().into_iter().flatten() {
+        match documents.get(key) {
+            Some(existing_url) if existing_url != url => {
+                diag!(
+                    Code::SectionConflict,
+                    file: entry,
+                    "The section of {} maps {} to {}, not {}",
+                    entry.display(),
+                    key,
+                    existing_url.as_str().unwrap_or_default(),
+                    url.as_str().unwrap_or_default()
+                );
+                conflicts += 1;
+                if options.on_conflict == OnConflict::Keep {
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        documents.insert(key.clone(), url.clone());
+    }
+    if conflicts > 0 && options.on_conflict == OnConflict::Fail {
+        return Err(Outcome::Failed);
+    }
+    json["documents"] = mapping::sort_documents(documents).into();
+    Ok(json)
+}
+
+// What rewrite did with the section already in a binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rewrite {
+    // There was none
+    Added,
+    Replaced,
+    Merged,
+    // The section already held the new one, so the binary wasn't rewritten
+    Unchanged,
+    // Left the binary as it was, for --on-existing skip
+    Kept,
+}
+
+// Put a copy of the binary at entry that has section embedded in the sink
+fn rewrite(entry: &Path, section: &[u8], options: &Options) -> Result<Rewrite, std::io::Error> {
+    let binary = std::fs::read(entry)?;
+    throttle::io(binary.len() as u64);
+    let is_pdb = msf::is_pdb(&binary);
+    // PDB streams can't be compressed
+    let compression = if is_pdb {
+        section::Compression::None
+    } else {
+        options.compress_section
+    };
+    let rewrite = match find_sourcelink(&binary) {
+        None => Rewrite::Added,
+        // Rewriting would only change its time, invalidating build caches
+        Some((existing, existing_compression))
+            if *existing == *section
+                && existing_compression == compression
+                && !options.force
+                && options.sink.is_in_place() =>
+        {
+            return Ok(Rewrite::Unchanged)
+        }
+        Some(_) if options.on_existing == OnExisting::Skip => return Ok(Rewrite::Kept),
+        // Its documents were merged into section
+        Some(_) if options.on_existing == OnExisting::Merge => Rewrite::Merged,
+        Some(_) => Rewrite::Replaced,
+    };
+    let rewritten = if is_pdb {
+        msf::write_stream(&binary, msf::SOURCE_LINK, section)?
+    } else {
+        section::write_compressed(&binary, ELF_SOURCE_LINK_SECTION_NAME, section, compression)?
+    };
+    throttle::io(rewritten.len() as u64);
+    put_rewrite(entry, &binary, &rewritten, options)?;
+    Ok(rewrite)
+}
+
+// Whether path is one of the backups --backup takes
+fn is_backup(path: &Path, options: &Options) -> bool {
+    let backup = options.backup.as_ref();
+    backup.is_some_and(|manifest| manifest.lock().unwrap().is_backup(path))
+}
+
+// Put rewritten, the new content of the binary at entry, in the sink. original
+// is its content before.
+fn put_rewrite(
+    entry: &Path,
+    original: &[u8],
+    rewritten: &[u8],
+    options: &Options,
+) -> Result<(), std::io::Error> {
+    // Taken before the binary changes, so a restore can undo this run
+    if let Some(manifest) = &options.backup {
+        manifest.lock().unwrap().back_up(entry, original)?;
+    }
+    options.sink.put(entry, rewritten, options.chmod)?;
+    if let Some(manifest) = &options.backup {
+        manifest.lock().unwrap().record(entry, rewritten)?;
+    }
+    Ok(())
+}
+
+// The Source Link JSON embedded in the binary in data and how it was
+// compressed: the .debug_sourcelink section of an ELF or the sourcelink stream
+// of a PDB. None if it has none.
+fn find_sourcelink(data: &[u8]) -> Option<(Cow<'_, [u8]>, section::Compression)> {
+    if msf::is_pdb(data) {
+        let stream = msf::read_stream(data, msf::SOURCE_LINK).ok()??;
+        return Some((Cow::Owned(stream), section::Compression::None));
+    }
+    elf::find_section(data, ELF_SOURCE_LINK_SECTION_NAME)
+}
+
+// Read the Source Link JSON embedded in the binary at path, decompressed, None
+// if it has none
+fn read_sourcelink(path: &Path) -> Result<Option<Vec<u8>>, std::io::Error> {
+    if is_pdb(path) {
+        return msf::read_stream(&std::fs::read(path)?, msf::SOURCE_LINK);
+    }
+    elf::read_section(path, ELF_SOURCE_LINK_SECTION_NAME)
+}
+
+fn is_pdb(path: &Path) -> bool {
+    File::open(path)
+        .and_then(|mut file| magic::file_type(&mut file))
+        .is_ok_and(|file_type| file_type == magic::FileType::Pdb)
+}
+
+// What holds the Source Link JSON of the binary at path, for messages
+fn sourcelink_container(path: &Path) -> String {
+    if is_pdb(path) {
+        format!("{} stream", msf::SOURCE_LINK)
+    } else {
+        format!("{} section", ELF_SOURCE_LINK_SECTION_NAME)
+    }
+}
+
+// Give the rewritten binary at output the owner, permissions and extended
+// attributes of the original, with the mode set to mode if given. Temporary
+// files are owned by whoever runs this and only readable by them. Changing
+// the owner clears setuid bits and file capabilities, so it goes first.
+fn copy_metadata(original: &Path, output: &Path, mode: Option<u32>) -> Result<(), std::io::Error> {
+    let metadata = std::fs::metadata(original)?;
+    // Only root may give files away
+    if let Err(e) = copy_owner(&metadata, output) {
+        warn!("Failed to keep the owner of {}: {}", original.display(), e);
+    }
+    let mut permissions = metadata.permissions();
+    if let Some(mode) = mode {
+        set_mode(&mut permissions, mode);
+    }
+    std::fs::set_permissions(output, permissions)?;
+    match xattr::copy(original, output) {
+        Ok(failed) => {
+            for (name, e) in failed {
+                warn!("Failed to keep {} of {}: {}", name, original.display(), e);
+            }
+        }
+        Err(e) => warn!(
+            "Failed to read the extended attributes of {}: {}",
+            original.display(),
+            e
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_owner(metadata: &std::fs::Metadata, output: &Path) -> Result<(), std::io::Error> {
+    use std::os::unix::fs::MetadataExt;
+    let current = std::fs::metadata(output)?;
+    if (current.uid(), current.gid()) == (metadata.uid(), metadata.gid()) {
+        return Ok(());
+    }
+    std::os::unix::fs::chown(output, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn copy_owner(_metadata: &std::fs::Metadata, _output: &Path) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(permissions: &mut std::fs::Permissions, mode: u32) {
+    std::os::unix::fs::PermissionsExt::set_mode(permissions, mode);
+}
+
+// Only whether the file is writable carries over
+#[cfg(not(unix))]
+fn set_mode(permissions: &mut std::fs::Permissions, mode: u32) {
+    permissions.set_readonly(mode & 0o222 == 0);
+}
+
+fn parse_mode(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("Invalid mode \"{}\", expected octal like 755", s))
+}
+
+// Process the binary read from stdin and write it to stdout, with the section
+// added when it was mapped and untouched otherwise. The debug info parser
+// needs a file, so it is kept in a temporary one in between.
+fn embed_stdin(options: &Arc<Options>) -> Result<(), std::io::Error> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    let _temporary = cleanup::track(file.path());
+    std::io::copy(&mut std::io::stdin().lock(), &mut file)?;
+    if process_file(file.path(), options) == Outcome::Failed {
+        return Err(std::io::Error::other("the section could not be embedded"));
+    }
+    let mut stdout = std::io::stdout().lock();
+    std::io::copy(&mut File::open(file.path())?, &mut stdout)?;
+    stdout.flush()
+}
+
+// Read the embedded sourcelink JSON of binary, decrypting it with identity if
+// it was encrypted.
+fn extract(binary: &Path, identity: Option<&Path>) -> Result<serde_json::Value, std::io::Error> {
+    let mut section = match read_sourcelink(binary)? {
+        Some(section) => section,
+        None => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no {}", sourcelink_container(binary)),
+            ))
+        }
+    };
+    if encryption::is_encrypted(&section) {
+        match identity {
+            Some(identity) => section = encryption::decrypt(identity, &section)?,
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "the section is encrypted, pass --identity",
+                ))
+            }
+        }
+    }
+    payload::decode(&section)
+}
+
+// The checkouts that track source files, found by the first of backends that
+// has a checkout around each file
+struct Checkouts {
+    found: Vec<Box<dyn vcs::Checkout>>,
+    // Submodules with no repo on disk
+    pinned: Vec<submodule::Pinned>,
+    // The workdir of every checkout that tracks a file in a given directory.
+    // Copied source trees (e.g. a vendor directory rsync'd into another
+    // checkout) can leave one directory split across several checkouts.
+    claims: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+}
+
+fn checkouts_from_source_files(
+    backends: &[&dyn VcsBackend],
+    source_files: &[compiledfiles::FileInfo],
+    options: &Options,
+) -> Checkouts {
+    let missing_sources = options.missing_sources;
+    let mut checkouts = Checkouts {
+        found: Vec::new(),
+        pinned: Vec::new(),
+        claims: BTreeMap::new(),
+    };
+    let mut missing = Vec::new();
+    for file in source_files {
+        if diagnostics::abandoned() {
+            break;
+        }
+        trace!("Searching for a checkout for {}", file.path.display());
+        if !file.path.is_file() {
+            missing.push(&file.path);
+            continue;
+        }
+        let checkout = match backends
+            .iter()
+            .find_map(|backend| checkout_tracking(*backend, &file.path, options))
+        {
+            Some(Some(vcs::Tracked::Checkout(checkout))) => checkout,
+            Some(Some(vcs::Tracked::Pinned(pinned))) => {
+                trace!(
+                    "Found submodule {} for {}",
+                    pinned.workdir.display(),
+                    file.path.display()
+                );
+                if !checkouts.pinned.iter().any(|x| x.workdir == pinned.workdir) {
+                    checkouts.pinned.push(pinned);
+                }
+                continue;
+            }
+            Some(None) | None => continue,
+        };
+        let workdir = checkout.workdir().to_owned();
+        trace!(
+            "Found {} {} for {}",
+            checkout.kind(),
+            workdir.display(),
+            file.path.display()
+        );
+        let rel_path = file.path.strip_prefix(&workdir).unwrap();
+        if !checkout.is_clean(rel_path) {
+            diag!(
+                Code::DirtySource,
+                file: &file.path,
+                "{} differs from {} of {} {}, its links will not match what was built",
+                file.path.display(),
+                checkout.head_name(),
+                checkout.kind(),
+                workdir.display()
+            );
+        }
+        if let Some(dir) = file.path.parent() {
+            checkouts
+                .claims
+                .entry(dir.to_owned())
+                .or_default()
+                .insert(workdir.clone());
+        }
+        if !checkouts.found.iter().any(|x| x.workdir() == workdir) {
+            checkouts.found.push(checkout);
+        }
+    }
+
+    // In partial checkouts only some of a checkout's files are on disk. One
+    // file that resolves is enough to know the checkout, and its wildcard
+    // covers the missing files under it too.
+    for path in missing {
+        if checkouts
+            .pinned
+            .iter()
+            .any(|submodule| path.starts_with(&submodule.workdir))
+        {
+            continue;
+        }
+        if let Some(checkout) = checkouts
+            .found
+            .iter()
+            .find(|checkout| path.starts_with(checkout.workdir()))
+        {
+            trace!(
+                "{} does not exist on disk but is covered by {} {}",
+                path.display(),
+                checkout.kind(),
+                checkout.workdir().display()
+            );
+            continue;
+        }
+
+        // Tracking can not be checked, so trust the checkout the file's
+        // siblings are in
+        let checkout = if missing_sources == Some(MissingSources::MapAnyway) {
+            path.parent()
+                .filter(|dir| dir.is_dir())
+                .and_then(|dir| backends.iter().find_map(|backend| backend.discover(dir)))
+        } else {
+            None
+        };
+        match checkout {
+            Some(checkout) => {
+                diag!(
+                    Code::MissingSource,
+                    file: path,
+                    "{} does not exist on disk, mapping it with {} {}",
+                    path.display(),
+                    checkout.kind(),
+                    checkout.workdir().display()
+                );
+                checkouts.found.push(checkout);
+            }
+            None => diag!(
+                Code::MissingSource,
+                file: path,
+                "Not indexing {} as it does not exists on disk",
+                path.display()
+            ),
+        }
+    }
+
+    for (dir, workdirs) in checkouts
+        .claims
+        .iter()
+        .filter(|(_, workdirs)| workdirs.len() > 1)
+    {
+        // The deepest checkout wins since its mapping is the most specific
+        // prefix
+        let deepest = workdirs
+            .iter()
+            .max_by_key(|workdir| workdir.components().count())
+            .unwrap();
+        let candidates = workdirs
+            .iter()
+            .map(|workdir| workdir.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        diag!(
+            Code::SplitDirectory,
+            file: dir,
+            "{} contains files tracked by different checkouts ({}). Using {}",
+            dir.display(),
+            candidates,
+            deepest.display()
+        );
+    }
+    checkouts
+}
+
+// What of backend tracks path, looking past checkouts that don't track it to
+// the ones they are in. None when backend has no checkout around path, and
+// Some(None) when none of its checkouts track it.
+fn checkout_tracking(
+    backend: &dyn VcsBackend,
+    path: &Path,
+    options: &Options,
+) -> Option<Option<vcs::Tracked>> {
+    let mut checkout = backend.discover(path)?;
+    loop {
+        let workdir = checkout.workdir().to_owned();
+        let rel_path = path.strip_prefix(&workdir).unwrap();
+        let tracked = match checkout.is_tracked(rel_path) {
+            Ok(tracked) => tracked,
+            Err(e) => {
+                warn!(
+                    "Not indexing {} as its status in {} {} can not be read: {}",
+                    path.display(),
+                    checkout.kind(),
+                    workdir.display(),
+                    e
+                );
+                return Some(None);
+            }
+        };
+        if tracked {
+            return Some(Some(vcs::Tracked::Checkout(checkout)));
+        }
+        if let Some(tracked) = checkout.nested(rel_path, options.remote.as_deref()) {
+            return Some(Some(tracked));
+        }
+
+        diag!(
+            Code::UntrackedFile,
+            file: path,
+            "{} not tracked in {} {}",
+            path.display(),
+            checkout.kind(),
+            workdir.display()
+        );
+        checkout = match checkout.enclosing(path) {
+            Some(enclosing) => enclosing,
+            None => return Some(None),
+        };
+    }
+}
+
+// Rust binaries name the files of the standard library
+// /rustc/<commit>/library/..., after the commit of the compiler that built
+// them. They are never on disk, but rust-lang/rust has them at that commit.
+fn rustc_commit(path: &Path) -> Option<&str> {
+    let mut components = path.components();
+    if components.next()? != Component::RootDir || components.next()?.as_os_str() != "rustc" {
+        return None;
+    }
+    let commit = components.next()?.as_os_str().to_str()?;
+    if commit.len() != 40 || !commit.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(commit)
+}
+
+// The mapping of the standard library files of every compiler the binary's
+// Rust code was built with
+fn rust_std_mapping(
+    source_files: &[compiledfiles::FileInfo],
+    options: &Options,
+) -> mapping::Mapping {
+    let mut mapping = mapping::Mapping::default();
+    let remote = url::Url::parse("https://github.com/rust-lang/rust").unwrap();
+    for file in source_files {
+        let commit = match rustc_commit(&file.path).map(git2::Oid::from_str) {
+            Some(Ok(commit)) => commit,
+            _ => continue,
+        };
+        let rev = providers::Revision::Commit(commit);
+        let url = match providers::generate_url(&remote, &rev, &options.hosts) {
+            Some(url) => url,
+            None => {
+                warn!(
+                    "Skipping the standard library at {}. Unable to generate url",
+                    commit
+                );
+                continue;
+            }
+        };
+        let origin = mapping::Origin::new(remote.to_string(), commit.to_string());
+        // /rustc/<commit>, spelled the way the debug info does
+        let dir = file.path.components().take(3).collect::<PathBuf>();
+        mapping.insert_repo(&dir, url.into(), origin);
+    }
+    mapping
+}
+
+// The mapping of crates cargo unpacked from a registry to the repos they were
+// published from, at the commit cargo recorded or else the one the version's
+// tag is at
+fn registry_mapping(
+    source_files: &[compiledfiles::FileInfo],
+    options: &Options,
+) -> mapping::Mapping {
+    let mut mapping = mapping::Mapping::default();
+    let dirs = source_files
+        .iter()
+        .filter_map(|file| registry::package_dir(&file.path))
+        .collect::<BTreeSet<_>>();
+    for dir in dirs {
+        let package = match registry::load(dir) {
+            Ok(package) => package,
+            Err(e) => {
+                warn!("Skipping crate {}. {}", dir.display(), e);
+                continue;
+            }
+        };
+        let repository = match &package.repository {
+            Some(repository) => repository,
+            None => {
+                diag!(
+                    Code::NoOrigin,
+                    "Skipping crate {} {}. Its Cargo.toml has no repository",
+                    package.name,
+                    package.version
+                );
+                continue;
+            }
+        };
+        let mut remote = match providers::parse_remote(repository) {
+            Ok(remote) => remote,
+            Err(e) => {
+                diag!(
+                    Code::InvalidRemoteUrl,
+                    "Skipping crate {} {}. Unable to parse repository {}: {}",
+                    package.name,
+                    package.version,
+                    repository,
+                    e
+                );
+                continue;
+            }
+        };
+        providers::strip_credentials(&mut remote);
+        let commit = match package.commit.as_deref().map(git2::Oid::from_str) {
+            Some(Ok(commit)) => commit,
+            _ => match version_tag_commit(&package, &remote) {
+                Ok(commit) => commit,
+                Err(e) => {
+                    diag!(
+                        Code::CommitNotOnRemote,
+                        "Skipping crate {} {}. No commit was recorded when it was published and {}",
+                        package.name,
+                        package.version,
+                        e
+                    );
+                    continue;
+                }
+            },
+        };
+        let rev = providers::Revision::Commit(commit);
+        let url = match providers::generate_url(&remote, &rev, &options.hosts) {
+            Some(url) => providers::substitute(url.as_str(), &package.path_in_vcs, true),
+            None => Err("Unable to generate url".to_owned()),
+        };
+        let url = match url {
+            Ok(url) => url,
+            Err(e) => {
+                warn!("Skipping crate {} {}. {}", package.name, package.version, e);
+                continue;
+            }
+        };
+        let origin = mapping::Origin::new(remote.to_string(), rev.to_string());
+        mapping.insert_repo(&package.dir, url, origin);
+    }
+    mapping
+}
+
+// The commit of the tag of package's version on remote, like v1.2.3, which is
+// what most crates tag their releases with
+fn version_tag_commit(package: &registry::Package, remote: &url::Url) -> Result<git2::Oid, String> {
+    let mut tags = Vec::new();
+    for tag in [format!("v{}", package.version), package.version.clone()] {
+        match forge::ls_remote_tag(remote, &tag) {
+            Ok(Some(commit)) => {
+                debug!(
+                    "No commit recorded for crate {} {}, linking to tag {} at {}",
+                    package.name, package.version, tag, commit
+                );
+                return Ok(commit);
+            }
+            Ok(None) => tags.push(tag),
+            Err(e) => return Err(format!("the tags of {} could not be listed: {}", remote, e)),
+        }
+    }
+    Err(format!("{} has no tag {}", remote, tags.join(" or ")))
+}
+
+// The mapping of vcpkg and Conan packages to the repos their source archives
+// were made from, at the ref the port or recipe names
+fn packages_mapping(
+    source_files: &[compiledfiles::FileInfo],
+    options: &Options,
+) -> mapping::Mapping {
+    let mut mapping = mapping::Mapping::default();
+    let dirs = source_files
+        .iter()
+        .filter_map(|file| packages::source_dir(&file.path))
+        .collect::<BTreeSet<_>>();
+    for (manager, dir) in dirs {
+        let upstream = match packages::load(manager, &dir) {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                warn!("Skipping package {}. {}", dir.display(), e);
+                continue;
+            }
+        };
+        let mut remote = match providers::parse_remote(&upstream.remote) {
+            Ok(remote) => remote,
+            Err(e) => {
+                diag!(
+                    Code::InvalidRemoteUrl,
+                    "Skipping package {}. Unable to parse url {}: {}",
+                    dir.display(),
+                    upstream.remote,
+                    e
+                );
+                continue;
+            }
+        };
+        providers::strip_credentials(&mut remote);
+        let is_commit = upstream.reference.len() == 40
+            && upstream.reference.bytes().all(|b| b.is_ascii_hexdigit());
+        let rev = match git2::Oid::from_str(&upstream.reference) {
+            Ok(commit) if is_commit => providers::Revision::Commit(commit),
+            _ => providers::Revision::Tag(upstream.reference.clone()),
+        };
+        match providers::generate_url(&remote, &rev, &options.hosts) {
+            Some(url) => {
+                let origin = mapping::Origin::new(remote.to_string(), upstream.reference);
+                mapping.insert_repo(&upstream.dir, url.into(), origin);
+            }
+            None => warn!("Skipping package {}. Unable to generate url", dir.display()),
+        }
+    }
+    mapping
+}
+
+// url with its longest url.<base>.insteadOf prefix replaced by base, the way
+// git resolves the URL a remote is actually fetched from
+fn apply_instead_of(config: &git2::Config, url: &str) -> String {
+    let mut rewrite: Option<(String, String)> = None;
+    let entries = match config.entries(Some(r"^url\..*\.insteadof$")) {
+        Ok(entries) => entries,
+        Err(_) => return url.to_owned(),
+    };
+    let found = entries.for_each(|entry| {
+        let (name, prefix) = match (entry.name(), entry.value()) {
+            (Some(name), Some(prefix)) => (name, prefix),
+            _ => return,
+        };
+        let longer = rewrite
+            .as_ref()
+            .is_none_or(|(longest, _)| prefix.len() > longest.len());
+        if url.starts_with(prefix) && longer {
+            let base = &name["url.".len()..name.len() - ".insteadof".len()];
+            rewrite = Some((prefix.to_owned(), base.to_owned()));
+        }
+    });
+    match (found, rewrite) {
+        (Ok(()), Some((prefix, base))) => format!("{}{}", base, &url[prefix.len()..]),
+        _ => url.to_owned(),
+    }
+}
+
+// The globs, relative to workdir, of source files that must never be mapped:
+// --exclude-source and the lines of the repo's .sourcelynkignore
+fn source_exclusions(workdir: &Path, options: &Options) -> Vec<glob::Pattern> {
+    let mut exclusions = options.exclude_sources.clone();
+    let ignore_path = workdir.join(".sourcelynkignore");
+    let text = match std::fs::read_to_string(&ignore_path) {
+        Ok(text) => text,
+        Err(_) => return exclusions,
+    };
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match glob::Pattern::new(line) {
+            Ok(pattern) => exclusions.push(pattern),
+            Err(e) => warn!("Ignoring \"{}\" in {}: {}", line, ignore_path.display(), e),
+        }
+    }
+    exclusions
+}
+
+// The paths, relative to workdir, of the source files of the repo at workdir
+// that may be mapped and of those that are excluded. Files of repos nested in
+// workdir are neither, they are mapped by their own repo.
+fn split_excluded(
+    workdir: &Path,
+    nested: &[&Path],
+    source_files: &[compiledfiles::FileInfo],
+    exclusions: &[glob::Pattern],
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let (mut included, mut excluded) = (Vec::new(), Vec::new());
+    for file in source_files {
+        if nested.iter().any(|other| file.path.starts_with(other)) {
+            continue;
+        }
+        if let Ok(rel_path) = file.path.strip_prefix(workdir) {
+            if exclusions.iter().any(|glob| glob.matches_path(rel_path)) {
+                excluded.push(rel_path.to_owned());
+            } else {
+                included.push(rel_path.to_owned());
+            }
+        }
+    }
+    (included, excluded)
+}
+
+// The workdirs of the checkouts inside workdir, like vendored repos
+fn nested_workdirs<'c>(workdir: &Path, checkouts: &'c [Box<dyn vcs::Checkout>]) -> Vec<&'c Path> {
+    checkouts
+        .iter()
+        .map(|checkout| checkout.workdir())
+        .filter(|other| *other != workdir && other.starts_with(workdir))
+        .collect()
+}
+
+// Keys, relative to the repo, that cover every included file but no excluded
+// one: the shallowest directory of each file with no excluded file below it,
+// or the file itself (true) when there is none
+fn narrowed_keys(included: &[PathBuf], excluded: &[PathBuf]) -> BTreeSet<(PathBuf, bool)> {
+    let tainted = excluded
+        .iter()
+        .flat_map(|path| path.ancestors().skip(1))
+        .collect::<BTreeSet<_>>();
+    included
+        .iter()
+        .map(|file| {
+            let dirs = file.ancestors().skip(1).collect::<Vec<_>>();
+            match dirs.into_iter().rev().find(|dir| !tainted.contains(dir)) {
+                Some(dir) => (dir.to_owned(), false),
+                None => (file.to_owned(), true),
+            }
+        })
+        .collect()
+}
+
+// The immediate subdirectories of workdir that contain source files, None
+// when files sit directly in workdir. Files of repos nested in workdir are
+// left to their own repo's key.
+fn referenced_subdirectories(
+    workdir: &Path,
+    nested: &[&Path],
+    source_files: &[compiledfiles::FileInfo],
+) -> BTreeSet<Option<PathBuf>> {
+    source_files
+        .iter()
+        .filter(|file| !nested.iter().any(|other| file.path.starts_with(other)))
+        .filter_map(|file| file.path.strip_prefix(workdir).ok())
+        .map(|rel_path| {
+            let mut components = rel_path.components();
+            let first = components.next()?;
+            components.next()?;
+            Some(PathBuf::from(first.as_os_str()))
+        })
+        .collect()
+}
+
+fn generate_mapping(
+    checkouts: &Checkouts,
+    source_files: &[compiledfiles::FileInfo],
+    options: &Options,
+) -> mapping::Mapping {
+    let mut map = mapping::Mapping::default();
+    for checkout in &checkouts.found {
+        if diagnostics::abandoned() {
+            break;
+        }
+        let workdir = checkout.workdir();
+        let nested = nested_workdirs(workdir, &checkouts.found);
+        let context = vcs::LinkContext {
+            options,
+            source_files,
+            nested: &nested,
+        };
+        let link = match checkout.link(&context) {
+            Some(link) => link,
+            None => continue,
+        };
+        let (url, origin) = (link.url, link.origin);
+        let exclusions = source_exclusions(workdir, options);
+        let (included, excluded) = split_excluded(workdir, &nested, source_files, &exclusions);
+        if !excluded.is_empty() {
+            debug!(
+                "Excluded {} source files of {} {} from the mapping",
+                excluded.len(),
+                checkout.kind(),
+                workdir.display()
+            );
+            metrics::excluded_sources(excluded.len());
+            let wildcard = (workdir.to_owned(), url.to_string());
+            for (key, exact) in narrowed_keys(&included, &excluded) {
+                let rel_key = key.to_slash().unwrap();
+                let url = match providers::substitute(url.as_str(), &rel_key, !exact) {
+                    Ok(url) => url,
+                    Err(e) => {
+                        warn!(
+                            "Leaving {} out of the mapping. {}",
+                            workdir.join(&key).display(),
+                            e
+                        );
+                        continue;
+                    }
+                };
+                map.insert_repo_part(
+                    &workdir.join(&key),
+                    url,
+                    exact,
+                    origin.clone(),
+                    wildcard.clone(),
+                );
+            }
+        } else {
+            match options.granularity {
+                Granularity::Repository => {
+                    map.insert_repo(workdir, url.into(), origin);
+                }
+                Granularity::Directory => {
+                    let wildcard = (workdir.to_owned(), url.to_string());
+                    for dir in referenced_subdirectories(workdir, &nested, source_files) {
+                        let url = match &dir {
+                            Some(dir) => {
+                                let rel_dir = dir.to_slash().unwrap();
+                                match providers::substitute(url.as_str(), &rel_dir, true) {
+                                    Ok(url) => url,
+                                    Err(e) => {
+                                        warn!(
+                                            "Leaving {} out of the mapping. {}",
+                                            workdir.join(dir).display(),
+                                            e
+                                        );
+                                        continue;
+                                    }
+                                }
+                            }
+                            None => url.to_string(),
+                        };
+                        let dir = dir.map_or(workdir.to_owned(), |dir| workdir.join(dir));
+                        map.insert_repo_part(&dir, url, false, origin.clone(), wildcard.clone());
+                    }
+                }
+            }
+        }
+
+        // Directories of the checkout's files that link elsewhere get keys of
+        // their own
+        let dirs = checkouts
+            .claims
+            .iter()
+            .filter(|(_, workdirs)| workdirs.contains(workdir))
+            .map(|(dir, _)| dir);
+        for dir in dirs {
+            if let Some(link) = checkout.link_dir(dir) {
+                map.insert_repo(dir, link.url.into(), link.origin);
+            }
+        }
+    }
+    map
+}
+
+fn string_values(matches: &clap::ArgMatches, id: &str) -> Vec<String> {
+    matches
+        .get_many::<String>(id)
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default()
+}
+
+// Manual mappings are given as DIR=URL, where URL contains the "*" wildcard
+// the same way the generated ones do.
+fn parse_manual_mappings(matches: &clap::ArgMatches) -> Result<Vec<(PathBuf, String)>, String> {
+    let mut mappings = Vec::new();
+    if let Some(values) = matches.get_many::<String>("map") {
+        for value in values {
+            match value.split_once('=') {
+                Some((dir, url)) if !dir.is_empty() && url.contains('*') => {
+                    mappings.push((PathBuf::from(dir), url.to_owned()));
+                }
+                _ => {
+                    return Err(format!(
+                        "Invalid mapping \"{}\", expected DIR=URL with a \"*\" in the URL",
+                        value
+                    ))
+                }
+            }
+        }
+    }
+    Ok(mappings)
+}
+
+fn explain(code: Option<&String>) {
+    match code {
+        Some(code) => match code.parse::<Code>() {
+            Ok(code) => {
+                println!("{}: {}", code, code.summary());
+                println!();
+                println!("{}", code.explanation());
+            }
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            for code in Code::ALL.iter() {
+                println!("{}  {}", code, code.summary());
+            }
+        }
+    }
+}
+
+fn parse_webhook(matches: &clap::ArgMatches) -> Option<webhook::Webhook> {
+    matches
+        .get_one::<String>("webhook")
+        .map(|url| webhook::Webhook {
+            url: url.to_owned(),
+            format: *matches
+                .get_one::<webhook::Format>("webhook-format")
+                .unwrap(),
+        })
+}
+
+fn parse_diagnostic_levels(
+    matches: &clap::ArgMatches,
+) -> Result<BTreeMap<Code, diagnostics::Level>, String> {
+    let mut levels = BTreeMap::new();
+    // --allow and --deny below take precedence
+    if matches.get_flag("require-pushed") {
+        levels.insert(Code::CommitNotOnRemote, diagnostics::Level::Deny);
+    }
+    if matches.get_flag("fail-on-dirty") {
+        levels.insert(Code::DirtySource, diagnostics::Level::Deny);
+    }
+    if matches.get_flag("fail-on-mismatch") {
+        levels.insert(Code::ChecksumMismatch, diagnostics::Level::Deny);
+    }
+    match matches.get_one::<OnEmpty>("on-empty") {
+        Some(OnEmpty::Warn) => {
+            levels.insert(Code::EmptyMapping, diagnostics::Level::Warn);
+        }
+        Some(OnEmpty::Error) => {
+            levels.insert(Code::EmptyMapping, diagnostics::Level::Deny);
+        }
+        _ => {}
+    }
+    match matches.get_one::<MissingSources>("missing-sources") {
+        Some(MissingSources::Warn) | Some(MissingSources::MapAnyway) => {
+            levels.insert(Code::MissingSource, diagnostics::Level::Warn);
+        }
+        Some(MissingSources::Error) => {
+            levels.insert(Code::MissingSource, diagnostics::Level::Deny);
+        }
+        None => {}
+    }
+    for (id, level) in [
+        ("allow", diagnostics::Level::Allow),
+        ("deny", diagnostics::Level::Deny),
+    ] {
+        if let Some(values) = matches.get_many::<String>(id) {
+            for value in values {
+                levels.insert(value.parse::<Code>()?, level);
+            }
+        }
+    }
+    Ok(levels)
+}
+
+fn initialize_logger(matches: &clap::ArgMatches) {
+    // Vary the output based on how many times the user used the "verbose" flag
+    // (i.e. 'myprog -v -v -v' or 'myprog -vvv' vs 'myprog -v'
+    let mut logger = pretty_env_logger::formatted_builder();
+    let logger = match matches.get_count("v") {
+        0 => logger.filter_level(log::LevelFilter::Error),
+        1 => logger.filter_level(log::LevelFilter::Warn),
+        2 => logger.filter_level(log::LevelFilter::Info),
+        3 => logger.filter_level(log::LevelFilter::Debug),
+        _ => logger.filter_level(log::LevelFilter::Trace),
+    };
+    logger.init();
+    trace!("logger initialized");
+}
+
+fn is_possible_symbol_file(path: &Path) -> bool {
+    metrics::file_scanned();
+    let metadata = std::fs::metadata(path).ok();
+    if let Some(symbol) = metadata
+        .as_ref()
+        .and_then(|metadata| scan_cache::lookup(path, metadata))
+    {
+        return symbol;
+    }
+
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Failed to open {} due to {}", path.display(), e);
+            return false;
+        }
+    };
+    let symbol = is_symbol_file_type(path, &mut file);
+    if let Some(metadata) = &metadata {
+        scan_cache::store(path, metadata, symbol);
+    }
+    symbol
+}
+
+fn is_symbol_file_type(path: &Path, file: &mut File) -> bool {
+    match magic::file_type(file).unwrap_or(magic::FileType::Unknown) {
+        magic::FileType::Elf(magic::ElfType::Exec)
+        | magic::FileType::Elf(magic::ElfType::Dyn)
+        | magic::FileType::Pdb => true,
+
+        magic::FileType::Elf(magic::ElfType::None)
+        | magic::FileType::Elf(magic::ElfType::Core)
+        | magic::FileType::Elf(magic::ElfType::Rel)
+        | magic::FileType::Elf(magic::ElfType::Unknown)
+        | magic::FileType::MachO
+        | magic::FileType::PE
+        | magic::FileType::Unknown => {
+            trace!("File type not usabled for {}", path.display());
+            false
+        }
+    }
+}
+
+// Options shared by the long running modes
+fn webhook_args() -> [clap::Arg; 2] {
+    [
+        clap::Arg::new("webhook")
+            .long("webhook")
+            .value_name("URL")
+            .help("Post a summary to URL after each batch of binaries"),
+        clap::Arg::new("webhook-format")
+            .long("webhook-format")
+            .value_name("FORMAT")
+            .default_value("generic")
+            .value_parser(clap::builder::ValueParser::new(
+                str::parse::<webhook::Format>,
+            ))
+            .help("Body of webhook posts, generic (summary JSON) or slack"),
+    ]
+}
+
+// Whether rel_path, relative to the scanned root, matches any of include (or
+// include is empty) and none of exclude
+fn is_included(rel_path: &Path, include: &[glob::Pattern], exclude: &[glob::Pattern]) -> bool {
+    (include.is_empty() || include.iter().any(|glob| glob.matches_path(rel_path)))
+        && !exclude.iter().any(|glob| glob.matches_path(rel_path))
+}
+
+fn glob_values(matches: &clap::ArgMatches, id: &str) -> Vec<glob::Pattern> {
+    matches
+        .get_many::<glob::Pattern>(id)
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default()
+}
+
+// Parse the command line, with the options in the configuration file as
+// defaults
+fn parse_cli_args() -> (clap::ArgMatches, Vec<std::ffi::OsString>) {
+    let args = std::env::args_os().collect::<Vec<_>>();
+    let matches = cli().get_matches_from(&args);
+
+    let config = match matches.get_one::<String>("config") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let path = PathBuf::from(config::FILE_NAME);
+            if !path.is_file() {
+                return (matches, args);
+            }
+            path
+        }
+    };
+    let config_args = match config::args(&config, &cli()) {
+        Ok(config_args) => config_args,
+        Err(e) => cli().error(clap::error::ErrorKind::InvalidValue, e).exit(),
+    };
+
+    let mut merged = vec![args[0].clone()];
+    merged.extend(config_args.into_iter().map(Into::into));
+    merged.extend(args.into_iter().skip(1));
+    (cli().get_matches_from(&merged), merged)
+}
+
+fn cli() -> clap::Command {
+    clap::Command::new(APP_NAME)
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("CLI tool for dbgsrv")
+        .author(APP_AUTHOR)
+        // Options from the configuration file come first and are overridden
+        .args_override_self(true)
+        .arg(
+            clap::Arg::new("config")
+                .long("config")
+                .value_name("FILE")
+                .help("Read default options from FILE instead of ./sourcelynk.toml"),
+        )
+        .arg(
+            clap::Arg::new("batch")
+                .long("batch")
+                .value_name("FILE")
+                .conflicts_with("PATH")
+                .help("Process the roots listed in FILE, each with its own options"),
+        )
+        .arg(
+            clap::Arg::new("include")
+                .long("include")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(glob::Pattern::new))
+                .help("Only scan files whose path below PATH matches GLOB"),
+        )
+        .arg(
+            clap::Arg::new("all-cargo-artifacts")
+                .long("all-cargo-artifacts")
+                .action(ArgAction::SetTrue)
+                .help("Also process proc macros, and stale or duplicate artifacts in cargo's deps directories"),
+        )
+        .arg(
+            clap::Arg::new("limit")
+                .long("limit")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Stop after processing N binaries"),
+        )
+        .arg(
+            clap::Arg::new("newest-first")
+                .long("newest-first")
+                .action(ArgAction::SetTrue)
+                .help("Process the most recently modified binaries first"),
+        )
+        .arg(
+            clap::Arg::new("changed-since")
+                .long("changed-since")
+                .value_name("TIMESTAMP")
+                .value_parser(clap::builder::ValueParser::new(timestamp::parse))
+                .help("Only scan files modified at or after TIMESTAMP, in seconds since the epoch or RFC 3339"),
+        )
+        .arg(
+            clap::Arg::new("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(glob::Pattern::new))
+                .help("Skip files whose path below PATH matches GLOB"),
+        )
+        .arg(
+            clap::Arg::new("ci-env-fallback")
+                .long("ci-env-fallback")
+                .action(ArgAction::SetTrue)
+                .help("Map from GitHub Actions, GitLab CI or Azure Pipelines variables when no repo is found"),
+        )
+        .arg(
+            clap::Arg::new("exclude-source")
+                .long("exclude-source")
+                .value_name("GLOB")
+                .action(ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(glob::Pattern::new))
+                .help("Never map source files whose path in their repo matches GLOB"),
+        )
+        .arg(
+            clap::Arg::new("v")
+                .short('v')
+                .action(ArgAction::Count)
+                .help("Sets the level of verbosity"),
+        )
+        .arg(
+            clap::Arg::new("dryrun")
+                .short('n')
+                .long("dryrun")
+                .action(ArgAction::SetTrue)
+                .help("Run without modifying the binaries"),
+        )
+        .arg(
+            clap::Arg::new("dryrun-out")
+                .long("dryrun-out")
+                .value_name("FILE")
+                .help("Write what a dry run would do to FILE as JSON, implies --dryrun"),
+        )
+        .arg(
+            clap::Arg::new("timeout")
+                .long("timeout")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .help("Give up on a binary, counting it as failed, after SECS seconds"),
+        )
+        .arg(
+            clap::Arg::new("io-nice")
+                .long("io-nice")
+                .action(ArgAction::SetTrue)
+                .help("Only read and write binaries while the disk is otherwise idle (Linux)"),
+        )
+        .arg(
+            clap::Arg::new("io-limit")
+                .long("io-limit")
+                .value_name("RATE")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<throttle::Rate>))
+                .help("Read and write at most RATE bytes per second, like 50M"),
+        )
+        .arg(
+            clap::Arg::new("sink")
+                .long("sink")
+                .value_name("SINK")
+                .default_value("in-place")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<sink::Spec>))
+                .help("Where rewritten binaries go: in-place, dir:PATH, tar:PATH (- for stdout) or put:URL"),
+        )
+        .arg(
+            clap::Arg::new("compress-section")
+                .long("compress-section")
+                .value_name("FORMAT")
+                .default_value("none")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<section::Compression>))
+                .help("Compress the section like -gz does .debug_* sections: none, zlib or zstd"),
+        )
+        .arg(
+            clap::Arg::new("max-section-size")
+                .long("max-section-size")
+                .value_name("BYTES")
+                .value_parser(clap::value_parser!(usize))
+                .help("Largest JSON to embed, see --on-oversize"),
+        )
+        .arg(
+            clap::Arg::new("on-oversize")
+                .long("on-oversize")
+                .value_name("STRATEGY")
+                .default_value("truncate")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<Oversize>))
+                .help("Handle JSON over --max-section-size: wildcards, truncate or sidecar"),
+        )
+        .arg(
+            clap::Arg::new("on-empty")
+                .long("on-empty")
+                .value_name("POLICY")
+                .default_value("skip")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<OnEmpty>))
+                .help("Handle binaries with nothing mapped: skip, warn, error or embed-empty"),
+        )
+        .arg(
+            clap::Arg::new("on-existing")
+                .long("on-existing")
+                .value_name("POLICY")
+                .default_value("replace")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<OnExisting>))
+                .help("Handle binaries that already have a section: replace, skip or merge"),
+        )
+        .arg(
+            clap::Arg::new("backup")
+                .long("backup")
+                .value_name("SUFFIX|DIR")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value(".orig")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<backup::Spec>))
+                .help("Copy binaries aside before rewriting them, next to them with SUFFIX (.orig) or below DIR, see restore"),
+        )
+        .arg(
+            clap::Arg::new("force")
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Rewrite binaries even when their section already holds the mapping"),
+        )
+        .arg(
+            clap::Arg::new("on-conflict")
+                .long("on-conflict")
+                .value_name("POLICY")
+                .default_value("fail")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<OnConflict>))
+                .help("Handle keys --on-existing merge finds mapped elsewhere: keep, replace or fail"),
+        )
+        .arg(
+            clap::Arg::new("chmod")
+                .long("chmod")
+                .value_name("MODE")
+                .value_parser(clap::builder::ValueParser::new(parse_mode))
+                .help("Octal mode of rewritten binaries, instead of the original's"),
+        )
+        .arg(
+            clap::Arg::new("missing-sources")
+                .long("missing-sources")
+                .value_name("POLICY")
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<MissingSources>,
+                ))
+                .help("Handle source files not on disk: warn, error or map-anyway"),
+        )
+        .arg(
+            clap::Arg::new("prefix-map")
+                .long("prefix-map")
+                .value_name("OLD=NEW")
+                .action(ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<prefix_map::PrefixMap>,
+                ))
+                .help("Binaries were built with -fdebug-prefix-map=OLD=NEW, key the mapping by NEW"),
+        )
+        .arg(
+            clap::Arg::new("path-map")
+                .long("path-map")
+                .value_name("REMAPPED=LOCAL")
+                .action(ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<prefix_map::PathMap>,
+                ))
+                .help("Look for files debug info names under REMAPPED in LOCAL, applied before repo discovery"),
+        )
+        .arg(
+            clap::Arg::new("build-root")
+                .long("build-root")
+                .value_name("OLD:NEW")
+                .action(ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<prefix_map::BuildRoot>,
+                ))
+                .help("Sources built under OLD, like a container's /src, are in NEW on this machine"),
+        )
+        .arg(
+            clap::Arg::new("path-translate")
+                .long("path-translate")
+                .value_name("host=OLD,container=NEW")
+                .action(ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<prefix_map::PathTranslate>,
+                ))
+                .help("Running in a container that has the host's OLD, where sources were built, mounted at NEW"),
+        )
+        .arg(
+            clap::Arg::new("allow-host")
+                .long("allow-host")
+                .value_name("PATTERN")
+                .action(ArgAction::Append)
+                .help("Only allow mappings to hosts matching PATTERN (host or *.domain)"),
+        )
+        .arg(
+            clap::Arg::new("deny-host")
+                .long("deny-host")
+                .value_name("PATTERN")
+                .action(ArgAction::Append)
+                .help("Fail binaries whose mapping points at a host matching PATTERN"),
+        )
+        .arg(
+            clap::Arg::new("porcelain")
+                .long("porcelain")
+                .value_name("VERSION")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("v1")
+                .value_parser(["v1"])
+                .help("Print one stable, tab separated status line per binary for scripts"),
+        )
+        .arg(
+            clap::Arg::new("embedded-elf")
+                .long("embedded-elf")
+                .value_name("MODE")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<EmbeddedElf>))
+                .help("Look for ELF images inside binaries: report or process"),
+        )
+        .arg(
+            clap::Arg::new("hints")
+                .long("hints")
+                .value_name("FILE")
+                .help("Map stripped binaries from the repos and commits FILE lists by build id"),
+        )
+        .arg(
+            clap::Arg::new("lenient")
+                .long("lenient")
+                .action(ArgAction::SetTrue)
+                .help("Map the readable parts of binaries with corrupt debug info"),
+        )
+        .arg(
+            clap::Arg::new("scan-cache")
+                .long("scan-cache")
+                .value_name("FILE")
+                .help("Remember which files are binaries in FILE to speed up re-scans"),
+        )
+        .arg(
+            clap::Arg::new("relative-documents")
+                .long("relative-documents")
+                .action(ArgAction::SetTrue)
+                .help("Also embed the keys relative to the build root"),
+        )
+        .arg(
+            clap::Arg::new("provenance")
+                .long("provenance")
+                .action(ArgAction::SetTrue)
+                .help("Also embed the repos, commits and whether their checkouts were dirty"),
+        )
+        .arg(
+            clap::Arg::new("with-hashes")
+                .long("with-hashes")
+                .action(ArgAction::SetTrue)
+                .help("Also embed the SHA-256 of each mapped source file"),
+        )
+        .arg(
+            clap::Arg::new("granularity")
+                .long("granularity")
+                .value_name("LEVEL")
+                .default_value("repo")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<Granularity>))
+                .help("Generate one key per repo, or per directory directly below it"),
+        )
+        .arg(
+            clap::Arg::new("exact")
+                .long("exact")
+                .action(ArgAction::SetTrue)
+                .help("Generate a key for every source file instead of wildcards"),
+        )
+        .arg(
+            clap::Arg::new("payload-format")
+                .long("payload-format")
+                .value_name("FORMAT")
+                .default_value("json")
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<payload::Format>,
+                ))
+                .help("Encoding of the embedded section, json or cbor"),
+        )
+        .arg(
+            clap::Arg::new("aggregate")
+                .long("aggregate")
+                .value_name("FILE")
+                .help("Write the merged mappings of all binaries to FILE as JSON"),
+        )
+        .arg(
+            clap::Arg::new("map")
+                .short('m')
+                .long("map")
+                .value_name("DIR=URL")
+                .action(ArgAction::Append)
+                .help("Map DIR to URL, taking priority over mappings found from repositories"),
+        )
+        .arg(
+            clap::Arg::new("deny")
+                .long("deny")
+                .value_name("CODE")
+                .action(ArgAction::Append)
+                .help("Treat the diagnostic CODE as an error and fail the run"),
+        )
+        .arg(
+            clap::Arg::new("allow")
+                .long("allow")
+                .value_name("CODE")
+                .action(ArgAction::Append)
+                .help("Only report the diagnostic CODE at debug level"),
+        )
+        .arg(
+            clap::Arg::new("error-format")
+                .long("error-format")
+                .value_name("FORMAT")
+                .default_value("human")
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<diagnostics::Format>,
+                ))
+                .help("Format of diagnostics, json writes one object per line to stderr"),
+        )
+        .arg(
+            clap::Arg::new("audit-db")
+                .long("audit-db")
+                .value_name("FILE")
+                .global(true)
+                .help(
+                    "Record digests of embedded sections in FILE, or check against it with audit",
+                ),
+        )
+        .arg(
+            clap::Arg::new("audit-key")
+                .long("audit-key")
+                .value_name("FILE")
+                .global(true)
+                .help("Key used to sign the audit database"),
+        )
+        .arg(
+            clap::Arg::new("trend-db")
+                .long("trend-db")
+                .value_name("FILE")
+                .global(true)
+                .help("Append the coverage of each run to FILE, see the trend subcommand"),
+        )
+        .arg(
+            clap::Arg::new("check-commits")
+                .long("check-commits")
+                .action(ArgAction::SetTrue)
+                .help("Ask the host's API whether HEAD exists on the remote before mapping a repo"),
+        )
+        .arg(
+            clap::Arg::new("fail-on-dirty")
+                .long("fail-on-dirty")
+                .action(ArgAction::SetTrue)
+                .help("Exit with failure when a source file is modified or staged (W015)"),
+        )
+        .arg(
+            clap::Arg::new("fail-on-mismatch")
+                .long("fail-on-mismatch")
+                .action(ArgAction::SetTrue)
+                .help("Exit with failure when a source file doesn't match the checksum in the debug info (W018)"),
+        )
+        .arg(
+            clap::Arg::new("require-pushed")
+                .long("require-pushed")
+                .action(ArgAction::SetTrue)
+                .help("Like --check-commits, but exit with failure when HEAD is not on the remote"),
+        )
+        .arg(
+            clap::Arg::new("remote")
+                .long("remote")
+                .value_name("NAME")
+                .help("Link to the remote NAME instead of upstream or origin"),
+        )
+        .arg(
+            clap::Arg::new("commit")
+                .long("commit")
+                .value_name("[DIR=]SHA")
+                .action(ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<CommitOverride>,
+                ))
+                .help("Link to SHA instead of HEAD, for the repo checked out at DIR or every repo"),
+        )
+        .arg(
+            clap::Arg::new("ref-strategy")
+                .long("ref-strategy")
+                .value_name("STRATEGY")
+                .default_value("commit")
+                .value_parser(clap::builder::ValueParser::new(str::parse::<RefStrategy>))
+                .help("Link to the commit, a tag on it or its upstream branch"),
+        )
+        .arg(
+            clap::Arg::new("github-host")
+                .long("github-host")
+                .value_name("DOMAIN")
+                .action(ArgAction::Append)
+                .help("Treat remotes on DOMAIN as a GitHub Enterprise Server instance"),
+        )
+        .arg(
+            clap::Arg::new("github-url-style")
+                .long("github-url-style")
+                .value_name("STYLE")
+                .default_value("api")
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<providers::GitHubStyle>,
+                ))
+                .help("Point GitHub documents at the contents api or at raw files"),
+        )
+        .arg(
+            clap::Arg::new("gitlab-host")
+                .long("gitlab-host")
+                .value_name("DOMAIN")
+                .action(ArgAction::Append)
+                .help("Treat remotes on DOMAIN as a self-hosted GitLab instance"),
+        )
+        .arg(
+            clap::Arg::new("bitbucket-server-host")
+                .long("bitbucket-server-host")
+                .value_name("DOMAIN")
+                .action(ArgAction::Append)
+                .help("Treat remotes on DOMAIN as a Bitbucket Server or Data Center instance"),
+        )
+        .arg(
+            clap::Arg::new("gitea-host")
+                .long("gitea-host")
+                .value_name("DOMAIN")
+                .action(ArgAction::Append)
+                .help("Treat remotes on DOMAIN as a self-hosted Gitea or Forgejo instance"),
+        )
+        .arg(
+            clap::Arg::new("url-template")
+                .long("url-template")
+                .value_name("[DOMAIN=]TEMPLATE")
+                .action(ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<providers::Template>,
+                ))
+                .help("URL layout for remotes on unknown hosts, e.g. https://{host}/{org}/{repo}/raw/{commit}/{path}"),
+        )
+        .arg(
+            clap::Arg::new("perforce-url")
+                .long("perforce-url")
+                .value_name("URL")
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<p4::UrlTemplate>,
+                ))
+                .help("Map files in Perforce workspaces to the Helix Swarm instance at URL, or a template with {path} and {change}"),
+        )
+        .arg(
+            clap::Arg::new("encrypt-to")
+                .long("encrypt-to")
+                .value_name("RECIPIENT")
+                .action(ArgAction::Append)
+                .value_parser(clap::builder::ValueParser::new(
+                    str::parse::<age::x25519::Recipient>,
+                ))
+                .help("Encrypt the embedded JSON to the age RECIPIENT (age1...)"),
+        )
+        .arg(
+            clap::Arg::new("PATH")
+                .help("Path to search for debug info files")
+                .default_value(".")
+                .index(1),
+        )
+        .subcommand(
+            clap::Command::new("explain")
+                .about("Explain a diagnostic code, or list all codes")
+                .arg(clap::Arg::new("CODE").help("Code to explain, e.g. W002")),
+        )
+        .subcommand(
+            clap::Command::new("embed")
+                .about("Embed the mapping of a binary read from a pipe")
+                .arg(
+                    clap::Arg::new("stdin")
+                        .long("stdin")
+                        .action(ArgAction::SetTrue)
+                        .required(true)
+                        .help("Read the binary from stdin and write it to stdout"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("extract")
+                .about("Print the sourcelink JSON embedded in a binary")
+                .arg(
+                    clap::Arg::new("BINARY")
+                        .help("Binary to read")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::new("identity")
+                        .long("identity")
+                        .value_name("FILE")
+                        .help("age identity file to decrypt an encrypted section with"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("verify")
+                .about("Check that the URLs embedded in a binary resolve")
+                .arg(
+                    clap::Arg::new("BINARY")
+                        .help("Binary to verify")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::new("identity")
+                        .long("identity")
+                        .value_name("FILE")
+                        .help("age identity file to decrypt an encrypted section with"),
+                )
+                .arg(
+                    clap::Arg::new("concurrency")
+                        .long("concurrency")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .default_value("8")
+                        .help("Requests in flight at once per host"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("doctor")
+                .about("Check the tools, permissions, repo and network a run needs")
+                .arg(
+                    clap::Arg::new("PATH")
+                        .help("Path that will be scanned")
+                        .default_value("."),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("audit")
+                .about("Flag binaries whose section was changed outside of sourcelynk")
+                .arg(
+                    clap::Arg::new("PATH")
+                        .help("Path to search for binaries")
+                        .default_value("."),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("restore")
+                .about("Put back the binaries a run with --backup rewrote")
+                .arg(
+                    clap::Arg::new("MANIFEST")
+                        .required(true)
+                        .help("The sourcelynk-backup.json that run wrote"),
+                )
+                .arg(
+                    clap::Arg::new("force")
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("Also restore binaries changed since they were stamped"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("trend").about(
+                "Compare the coverage of each project's last run in --trend-db with the one before",
+            ),
+        )
+        .subcommand(
+            clap::Command::new("service")
+                .about("Serve an HTTP API for computing and embedding mappings")
+                .arg(
+                    clap::Arg::new("listen")
+                        .long("listen")
+                        .value_name("ADDR")
+                        .default_value("127.0.0.1:8080")
+                        .help("Address to listen on, e.g. 127.0.0.1:8080 or :8080 for every interface"),
+                )
+                .arg(
+                    clap::Arg::new("root")
+                        .long("root")
+                        .value_name("DIR")
+                        .action(ArgAction::Append)
+                        .help("Directory that paths submitted as JSON must be under, may be repeated"),
+                )
+                .arg(
+                    clap::Arg::new("max-body")
+                        .long("max-body")
+                        .value_name("BYTES")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("1073741824")
+                        .help("Largest request body, and so uploaded binary, to accept"),
+                )
+                .args(webhook_args()),
+        )
+        .subcommand(
+            clap::Command::new("daemon")
+                .about("Watch directories and process binaries as they are written")
+                .arg(
+                    clap::Arg::new("watch")
+                        .long("watch")
+                        .value_name("DIR")
+                        .action(ArgAction::Append)
+                        .required(true)
+                        .help("Directory to watch recursively"),
+                )
+                .arg(
+                    clap::Arg::new("settle")
+                        .long("settle")
+                        .value_name("SECS")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .default_value("2")
+                        .help("Seconds a file must be unchanged before it is processed"),
+                )
+                .arg(
+                    clap::Arg::new("metrics-textfile")
+                        .long("metrics-textfile")
+                        .value_name("FILE")
+                        .help("Write Prometheus metrics to FILE after each batch"),
+                )
+                .args(webhook_args()),
+        )
+}
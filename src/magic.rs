@@ -63,64 +63,45 @@ pub enum FileType {
     MachO,
 }
 
+// Only the first bytes are read, so a file that is truncated or changes while
+// it is read is Unknown rather than an error
 pub fn file_type(file: &mut File) -> Result<FileType> {
-    if file.metadata()?.len() < 32 {
-        Ok(FileType::Unknown)
-    } else {
-        let mut buf: [u8; 32] = [0; 32];
-        file.read_exact(&mut buf)?;
-        match buf[0] {
-            0x7F => {
-                if &buf[1..4] == b"ELF" {
-                    let endianness: ElfEndianess = buf[5].into();
-                    let (lower_byte, upper_byte) = match endianness {
-                        ElfEndianess::Little => (buf[16], buf[17]),
-                        ElfEndianess::Big => (buf[17], buf[16]),
-                        ElfEndianess::Unknown => return Ok(FileType::Unknown),
-                    };
-                    let elf_type: ElfType =
-                        (((upper_byte as u16) << 8) | (lower_byte as u16)).into();
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    file.take(HEADER_SIZE as u64).read_to_end(&mut header)?;
+    Ok(parse(&header))
+}
 
-                    Ok(FileType::Elf(elf_type))
-                } else {
-                    Ok(FileType::Unknown)
-                }
-            }
-            // 'M'
-            0x4D => {
-                match buf[1] {
-                    // 'Z'
-                    0x5A => Ok(FileType::PE),
+// The number of bytes parse looks at
+pub const HEADER_SIZE: usize = 32;
 
-                    // 'i'
-                    0x69 => {
-                        if &buf[2..29] == b"crosoft C/C++ MSF 7.00\r\n\x1a\x44\x53" {
-                            Ok(FileType::Pdb)
-                        } else {
-                            Ok(FileType::Unknown)
-                        }
-                    }
+const PDB_MAGIC: &[u8] = b"Microsoft C/C++ MSF 7.00\r\n\x1a\x44\x53";
+const MACHO_MAGICS: [[u8; 4]; 4] = [
+    [0xFE, 0xED, 0xFA, 0xCE],
+    [0xFE, 0xED, 0xFA, 0xCF],
+    [0xCE, 0xFA, 0xED, 0xFE],
+    [0xCF, 0xFA, 0xED, 0xFE],
+];
 
-                    _ => Ok(FileType::Unknown),
-                }
-            }
-            0xFE => {
-                if (buf[1..4] == [0xEDu8, 0xFAu8, 0xCEu8])
-                    || (buf[1..4] == [0xEDu8, 0xFAu8, 0xCFu8])
-                {
-                    Ok(FileType::MachO)
-                } else {
-                    Ok(FileType::Unknown)
-                }
-            }
-            0xCE | 0xCF => {
-                if buf[1..4] == [0xFAu8, 0xEDu8, 0xFEu8] {
-                    Ok(FileType::MachO)
-                } else {
-                    Ok(FileType::Unknown)
-                }
-            }
-            _ => Ok(FileType::Unknown),
-        }
+// The type of a file from its first HEADER_SIZE bytes. Shorter files are
+// never binaries worth indexing.
+pub fn parse(header: &[u8]) -> FileType {
+    if header.len() < HEADER_SIZE {
+        FileType::Unknown
+    } else if header.starts_with(b"\x7FELF") {
+        let endianness = header.get(5).map(|&byte| ElfEndianess::from(byte));
+        let elf_type = match (endianness, header.get(16..18)) {
+            (Some(ElfEndianess::Little), Some(&[low, high])) => u16::from_le_bytes([low, high]),
+            (Some(ElfEndianess::Big), Some(&[high, low])) => u16::from_be_bytes([high, low]),
+            _ => return FileType::Unknown,
+        };
+        FileType::Elf(elf_type.into())
+    } else if header.starts_with(PDB_MAGIC) {
+        FileType::Pdb
+    } else if header.starts_with(b"MZ") {
+        FileType::PE
+    } else if MACHO_MAGICS.iter().any(|magic| header.starts_with(magic)) {
+        FileType::MachO
+    } else {
+        FileType::Unknown
     }
 }
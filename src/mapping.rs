@@ -0,0 +1,454 @@
+use crate::diagnostics::Code;
+use crate::providers;
+use log::{info, warn};
+use path_slash::PathExt;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+// When two entries claim the same directory the one with the higher priority
+// wins. Mappings given explicitly on the command line always beat mappings
+// discovered from a repository. Entries of equal priority keep whichever was
+// inserted first, which is stable because source files are visited in the
+// order the debug info lists them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Repository,
+    Manual,
+}
+
+// The repo and revision an entry links to, for reporting and --provenance
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Origin {
+    pub repo: String,
+    pub revision: String,
+    // git describe --tags --always of the revision
+    pub describe: Option<String>,
+    // Whether the checkout had changes, when known
+    pub dirty: Option<bool>,
+}
+
+impl Origin {
+    pub fn new(repo: String, revision: String) -> Origin {
+        Origin {
+            repo,
+            revision,
+            describe: None,
+            dirty: None,
+        }
+    }
+
+    // Only what is known
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "repo": self.repo,
+            "revision": self.revision,
+        });
+        if let Some(describe) = &self.describe {
+            json["describe"] = describe.as_str().into();
+        }
+        if let Some(dirty) = self.dirty {
+            json["dirty"] = dirty.into();
+        }
+        json
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub url: String,
+    pub priority: Priority,
+    // Only maps the file its key names rather than everything below it
+    pub exact: bool,
+    // None for mappings given on the command line
+    pub origin: Option<Origin>,
+    // The directory and URL of the broader entry this one was narrowed from,
+    // which collapse goes back to
+    pub wildcard: Option<(PathBuf, String)>,
+}
+
+impl Entry {
+    fn key(&self, dir: &Path) -> PathBuf {
+        if self.exact {
+            return dir.to_owned();
+        }
+        // Keys of binaries built on Windows keep its separators, whatever
+        // machine they are stamped on
+        match dir.to_str() {
+            Some(name) if name.contains('\\') && !name.contains('/') => {
+                PathBuf::from(format!("{}\\*", name.trim_end_matches('\\')))
+            }
+            _ => dir.join("*"),
+        }
+    }
+}
+
+// How many directories deep dir is, counting both separators since keys can
+// be Windows paths whatever this machine is
+fn depth(dir: &Path) -> usize {
+    dir.components()
+        .map(|component| {
+            let name = component.as_os_str().to_string_lossy();
+            name.split('\\')
+                .filter(|name| !name.is_empty())
+                .count()
+                .max(1)
+        })
+        .sum()
+}
+
+// documents, like those merged from an embedded section, in the order
+// Mapping::documents writes its own
+pub fn sort_documents(
+    documents: serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut documents = documents.into_iter().collect::<Vec<_>>();
+    documents.sort_by_cached_key(|(key, _)| {
+        let (dir, exact) = match key.strip_suffix('*') {
+            Some(dir) => (dir, false),
+            None => (key.as_str(), true),
+        };
+        (
+            std::cmp::Reverse(depth(Path::new(dir))),
+            !exact,
+            dir.to_owned(),
+        )
+    });
+    documents.into_iter().collect()
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Mapping {
+    // Keyed by directory, the "*" wildcard is only appended when the
+    // documents are serialized.
+    entries: BTreeMap<PathBuf, Entry>,
+    // SHA-256 of the content of mapped source files, for --with-hashes
+    hashes: BTreeMap<PathBuf, String>,
+}
+
+impl Mapping {
+    pub fn insert(&mut self, dir: &Path, url: String, priority: Priority) {
+        let entry = Entry {
+            url,
+            priority,
+            exact: false,
+            origin: None,
+            wildcard: None,
+        };
+        self.insert_entry(dir, entry);
+    }
+
+    // Map dir to url of a repo discovered on disk
+    pub fn insert_repo(&mut self, dir: &Path, url: String, origin: Origin) {
+        let entry = Entry {
+            url,
+            priority: Priority::Repository,
+            exact: false,
+            origin: Some(origin),
+            wildcard: None,
+        };
+        self.insert_entry(dir, entry);
+    }
+
+    // Map dir, part of a repo discovered on disk, or the single file it names
+    // when exact, in which case url has no wildcard. wildcard is the directory
+    // and URL of the entry for the whole repo.
+    pub fn insert_repo_part(
+        &mut self,
+        dir: &Path,
+        url: String,
+        exact: bool,
+        origin: Origin,
+        wildcard: (PathBuf, String),
+    ) {
+        let entry = Entry {
+            url,
+            priority: Priority::Repository,
+            exact,
+            origin: Some(origin),
+            wildcard: Some(wildcard),
+        };
+        self.insert_entry(dir, entry);
+    }
+
+    fn insert_entry(&mut self, dir: &Path, entry: Entry) {
+        if let Some(existing) = self.entries.get(dir) {
+            if existing.url != entry.url {
+                let (winner, loser) = if entry.priority > existing.priority {
+                    (&entry.url, &existing.url)
+                } else {
+                    (&existing.url, &entry.url)
+                };
+                diag!(
+                    Code::MappingConflict,
+                    file: dir,
+                    "Conflicting mappings for {}: using {} over {}",
+                    entry.key(dir).display(),
+                    winner,
+                    loser
+                );
+            }
+            if entry.priority <= existing.priority {
+                return;
+            }
+        } else {
+            self.report_overlaps(dir);
+        }
+        self.entries.insert(dir.to_owned(), entry);
+    }
+
+    // Merge the entries of other, resolving conflicts like insert
+    pub fn extend(&mut self, other: &Mapping) {
+        for (dir, entry) in &other.entries {
+            self.insert_entry(dir, entry.clone());
+        }
+        for (path, hash) in &other.hashes {
+            self.hashes
+                .entry(path.clone())
+                .or_insert_with(|| hash.clone());
+        }
+    }
+
+    // Record the hash of the source file at path, if an entry maps it
+    pub fn insert_hash(&mut self, path: &Path, sha256: String) {
+        if self.covers(path) {
+            self.hashes.insert(path.to_owned(), sha256);
+        }
+    }
+
+    fn covers(&self, path: &Path) -> bool {
+        self.entries.iter().any(|(dir, entry)| match entry.exact {
+            true => path == dir.as_path(),
+            false => path.starts_with(dir),
+        })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&Path, &Entry)> {
+        self.entries
+            .iter()
+            .map(|(dir, entry)| (dir.as_path(), entry))
+    }
+
+    // Move every entry rename returns a new directory for
+    pub fn rename(&mut self, rename: impl Fn(&Path) -> Option<PathBuf>) {
+        self.entries = std::mem::take(&mut self.entries)
+            .into_iter()
+            .map(|(dir, mut entry)| {
+                if let Some((wildcard, _)) = &mut entry.wildcard {
+                    if let Some(renamed) = rename(wildcard) {
+                        *wildcard = renamed;
+                    }
+                }
+                (rename(&dir).unwrap_or(dir), entry)
+            })
+            .collect();
+        self.hashes = std::mem::take(&mut self.hashes)
+            .into_iter()
+            .map(|(path, hash)| (rename(&path).unwrap_or(path), hash))
+            .collect();
+    }
+
+    // A mapping with an exact entry for each of files, resolved from the
+    // most specific entry covering it. Files nothing covers are left out.
+    pub fn exact<'a>(&self, files: impl Iterator<Item = &'a Path>) -> Mapping {
+        let mut exact = Mapping::default();
+        for file in files {
+            let covering = self
+                .entries
+                .iter()
+                .filter(|(dir, entry)| match entry.exact {
+                    true => file == dir.as_path(),
+                    false => file.starts_with(dir),
+                })
+                .max_by_key(|(dir, _)| dir.components().count());
+            let (dir, entry) = match covering {
+                Some(covering) => covering,
+                None => continue,
+            };
+            let url = match entry.exact {
+                true => entry.url.clone(),
+                false => {
+                    let rel_path = file.strip_prefix(dir).unwrap().to_slash_lossy();
+                    match providers::substitute(&entry.url, &rel_path, false) {
+                        Ok(url) => url,
+                        Err(e) => {
+                            warn!("Leaving {} out of the mapping. {}", file.display(), e);
+                            continue;
+                        }
+                    }
+                }
+            };
+            // Collapsing goes back to what the file was resolved from
+            let wildcard = match &entry.wildcard {
+                Some(wildcard) => Some(wildcard.clone()),
+                None if entry.exact => None,
+                None => Some((dir.clone(), entry.url.clone())),
+            };
+            let entry = Entry {
+                url,
+                exact: true,
+                wildcard,
+                ..entry.clone()
+            };
+            exact.insert_entry(file, entry);
+        }
+        exact
+    }
+
+    // Every repo and revision the entries link to
+    pub fn origins(&self) -> BTreeSet<&Origin> {
+        self.entries
+            .values()
+            .filter_map(|entry| entry.origin.as_ref())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Entries from the most specific to the least, so the keys of nested
+    // repos come before the key of the repo they are in. Consumers that take
+    // the first matching key rather than the longest resolve them correctly.
+    fn by_specificity(&self) -> Vec<(&PathBuf, &Entry)> {
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(dir, entry)| (std::cmp::Reverse(depth(dir)), !entry.exact, *dir));
+        entries
+    }
+
+    pub fn documents(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.by_specificity()
+            .into_iter()
+            .map(|(dir, entry)| {
+                (
+                    entry.key(dir).to_string_lossy().into_owned(),
+                    serde_json::Value::String(entry.url.clone()),
+                )
+            })
+            .collect()
+    }
+
+    pub fn hashes(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.hashes
+            .iter()
+            .map(|(path, hash)| {
+                (
+                    path.to_string_lossy().into_owned(),
+                    serde_json::Value::String(hash.clone()),
+                )
+            })
+            .collect()
+    }
+
+    // The deepest directory containing every key
+    pub fn common_prefix(&self) -> Option<PathBuf> {
+        // Exact entries name a file, their directory is the prefix
+        let mut dirs = self.entries.iter().map(|(dir, entry)| match entry.exact {
+            true => dir.parent().unwrap_or(dir),
+            false => dir.as_path(),
+        });
+        let mut prefix = dirs.next()?.to_owned();
+        for dir in dirs {
+            while !dir.starts_with(&prefix) {
+                prefix = prefix.parent()?.to_owned();
+            }
+        }
+        Some(prefix)
+    }
+
+    // documents with the keys relative to prefix, for consumers on machines
+    // where the absolute build path means nothing
+    pub fn relative_documents(&self, prefix: &Path) -> serde_json::Map<String, serde_json::Value> {
+        self.by_specificity()
+            .into_iter()
+            .filter_map(|(dir, entry)| {
+                let dir = dir.strip_prefix(prefix).ok()?;
+                Some((
+                    entry.key(dir).to_slash()?.into_owned(),
+                    serde_json::Value::String(entry.url.clone()),
+                ))
+            })
+            .collect()
+    }
+
+    // Replace the entries narrowed from a broader one, like exact entries and
+    // the keys of directories, with that entry again. Fewer entries cover the
+    // same files, and more. Returns the number of entries that went away.
+    pub fn collapse(&mut self) -> usize {
+        let before = self.entries.len();
+        for (dir, entry) in std::mem::take(&mut self.entries) {
+            let (dir, entry) = match entry.wildcard.clone() {
+                Some((wildcard, url)) => (
+                    wildcard,
+                    Entry {
+                        url,
+                        exact: false,
+                        wildcard: None,
+                        ..entry
+                    },
+                ),
+                None => (dir, entry),
+            };
+            // Entries of the same repo collapse into the same one
+            match self.entries.get(&dir) {
+                Some(existing) if existing.priority >= entry.priority => {}
+                _ => {
+                    self.entries.insert(dir, entry);
+                }
+            }
+        }
+        before - self.entries.len()
+    }
+
+    // Drop the most specific entries until the serialized documents fit in
+    // max_bytes. Broad entries cover the most files so they are kept longest.
+    // Returns the number of entries dropped.
+    pub fn truncate(&mut self, max_bytes: usize) -> usize {
+        let mut dirs = self.entries.keys().cloned().collect::<Vec<_>>();
+        dirs.sort_by_key(|dir| depth(dir));
+
+        // {"documents":{}} plus "key":"value", per entry
+        let mut size = 16;
+        let mut dropped = 0;
+        for dir in dirs {
+            let entry = &self.entries[&dir];
+            let entry_size = serde_json::to_string(&entry.key(&dir)).unwrap().len()
+                + serde_json::to_string(&entry.url).unwrap().len()
+                + 2;
+            if size + entry_size > max_bytes {
+                self.entries.remove(&dir);
+                dropped += 1;
+            } else {
+                size += entry_size;
+            }
+        }
+        let hashes = std::mem::take(&mut self.hashes);
+        self.hashes = hashes
+            .into_iter()
+            .filter(|(path, _)| self.covers(path))
+            .collect();
+        dropped
+    }
+
+    // Nested keys are legal, consumers pick the longest matching prefix, but
+    // they are worth pointing out since they usually mean a vendored repo.
+    fn report_overlaps(&self, dir: &Path) {
+        for other in self.entries.keys() {
+            if dir.starts_with(other) {
+                info!(
+                    "{} overlaps {}, files under {} use the more specific mapping",
+                    dir.join("*").display(),
+                    other.join("*").display(),
+                    dir.display()
+                );
+            } else if other.starts_with(dir) {
+                info!(
+                    "{} overlaps {}, files under {} use the more specific mapping",
+                    other.join("*").display(),
+                    dir.join("*").display(),
+                    other.display()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::cleanup;
+use crate::diagnostics::Code;
+use crate::Outcome;
+
+// Upper bounds, in seconds, of the processing latency histogram buckets
+const LATENCY_BUCKETS: [f64; 10] = [0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+// Counters for the long running modes, exposed in the Prometheus text format
+#[derive(Debug, Default)]
+struct Metrics {
+    files_scanned: u64,
+    excluded_sources: u64,
+    outcomes: BTreeMap<&'static str, u64>,
+    diagnostics: BTreeMap<Code, u64>,
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+    latency_count: u64,
+}
+
+static METRICS: Mutex<Option<Metrics>> = Mutex::new(None);
+
+fn with<F: FnOnce(&mut Metrics)>(f: F) {
+    // The binary was already counted as failed
+    if crate::diagnostics::abandoned() {
+        return;
+    }
+    let mut metrics = METRICS.lock().unwrap();
+    f(metrics.get_or_insert_with(Metrics::default))
+}
+
+pub fn file_scanned() {
+    with(|metrics| metrics.files_scanned += 1);
+}
+
+pub fn excluded_sources(count: usize) {
+    with(|metrics| metrics.excluded_sources += count as u64);
+}
+
+pub fn diagnostic(code: Code) {
+    with(|metrics| *metrics.diagnostics.entry(code).or_default() += 1);
+}
+
+pub fn processed(outcome: Outcome, elapsed: Duration) {
+    with(|metrics| {
+        *metrics.outcomes.entry(outcome.as_str()).or_default() += 1;
+        let seconds = elapsed.as_secs_f64();
+        for (bucket, le) in metrics.latency_buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= le {
+                *bucket += 1;
+            }
+        }
+        metrics.latency_sum += seconds;
+        metrics.latency_count += 1;
+    });
+}
+
+pub fn render() -> String {
+    let mut out = String::new();
+    with(|metrics| {
+        writeln!(
+            out,
+            "# HELP sourcelynk_files_scanned_total Files checked for debug info"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE sourcelynk_files_scanned_total counter").unwrap();
+        writeln!(
+            out,
+            "sourcelynk_files_scanned_total {}",
+            metrics.files_scanned
+        )
+        .unwrap();
+
+        writeln!(
+            out,
+            "# HELP sourcelynk_excluded_sources_total Source files left out of mappings by --exclude-source"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE sourcelynk_excluded_sources_total counter").unwrap();
+        writeln!(
+            out,
+            "sourcelynk_excluded_sources_total {}",
+            metrics.excluded_sources
+        )
+        .unwrap();
+
+        writeln!(
+            out,
+            "# HELP sourcelynk_binaries_total Binaries processed by outcome"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE sourcelynk_binaries_total counter").unwrap();
+        for (outcome, count) in &metrics.outcomes {
+            writeln!(
+                out,
+                "sourcelynk_binaries_total{{outcome=\"{}\"}} {}",
+                outcome, count
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "# HELP sourcelynk_diagnostics_total Diagnostics emitted by code"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE sourcelynk_diagnostics_total counter").unwrap();
+        for (code, count) in &metrics.diagnostics {
+            writeln!(
+                out,
+                "sourcelynk_diagnostics_total{{code=\"{}\"}} {}",
+                code, count
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "# HELP sourcelynk_processing_seconds Time taken to process a binary"
+        )
+        .unwrap();
+        writeln!(out, "# TYPE sourcelynk_processing_seconds histogram").unwrap();
+        for (count, le) in metrics.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+            writeln!(
+                out,
+                "sourcelynk_processing_seconds_bucket{{le=\"{}\"}} {}",
+                le, count
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "sourcelynk_processing_seconds_bucket{{le=\"+Inf\"}} {}",
+            metrics.latency_count
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "sourcelynk_processing_seconds_sum {}",
+            metrics.latency_sum
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "sourcelynk_processing_seconds_count {}",
+            metrics.latency_count
+        )
+        .unwrap();
+    });
+    out
+}
+
+// Write the metrics for node_exporter's textfile collector. The file is
+// replaced atomically so the collector never reads a partial file.
+pub fn write_textfile(path: &Path) -> std::io::Result<()> {
+    cleanup::write(path, render().as_bytes())
+}
@@ -0,0 +1,499 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Range;
+
+// The named stream debuggers look for the Source Link JSON of a PDB in
+pub const SOURCE_LINK: &str = "sourcelink";
+
+// Followed by the padding up to the block size field
+const MAGIC: &[u8] = b"Microsoft C/C++ MSF 7.00\r\n\x1a\x44\x53\0\0\0";
+
+// The PDB info stream, which names streams
+const INFO_STREAM: usize = 1;
+
+// The size of a deleted stream, which has no blocks
+const NIL_STREAM: u32 = u32::MAX;
+
+// The name and index of each named stream
+type Named = Vec<(Vec<u8>, u32)>;
+
+pub fn is_pdb(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+// The contents of the stream called name of the PDB in data, None if it has
+// no such stream
+pub fn read_stream(data: &[u8], name: &str) -> Result<Option<Vec<u8>>> {
+    let mut msf = Msf::parse(data)?;
+    let (_, named) = named_streams(msf.info()?)?;
+    match named.iter().find(|(n, _)| n == name.as_bytes()) {
+        Some((_, index)) => match msf.streams.get_mut(*index as usize) {
+            Some(stream) => Ok(stream.take()),
+            None => Err(invalid("Named stream is out of bounds")),
+        },
+        None => Ok(None),
+    }
+}
+
+// The PDB in data with the stream called name holding contents, named in the
+// info stream if it is new. The whole file is laid out again, which drops the
+// blocks the old contents held.
+pub fn write_stream(data: &[u8], name: &str, contents: &[u8]) -> Result<Vec<u8>> {
+    let mut msf = Msf::parse(data)?;
+    let info = msf.info()?.to_vec();
+    let (map, mut named) = named_streams(&info)?;
+    let existing = named
+        .iter()
+        .find(|(n, _)| n == name.as_bytes())
+        .map(|(_, index)| *index as usize);
+    match existing {
+        Some(index) => match msf.streams.get_mut(index) {
+            Some(stream) => *stream = Some(contents.to_vec()),
+            None => return Err(invalid("Named stream is out of bounds")),
+        },
+        None => {
+            let index =
+                u32::try_from(msf.streams.len()).map_err(|_| invalid("Too many streams"))?;
+            msf.streams.push(Some(contents.to_vec()));
+            named.push((name.as_bytes().to_vec(), index));
+            let mut new_info = info[..map.start].to_vec();
+            new_info.extend_from_slice(&named_stream_map(&named));
+            new_info.extend_from_slice(&info[map.end..]);
+            msf.streams[INFO_STREAM] = Some(new_info);
+        }
+    }
+    msf.write()
+}
+
+// A PDB is a multi-stream file: a small file system of fixed size blocks
+// holding numbered streams. The superblock in the first block points at the
+// block map, which lists the blocks of the directory, which lists the size
+// and blocks of each stream. Blocks 1 and 2 of every block size blocks hold
+// the free block maps.
+struct Msf {
+    block_size: usize,
+    // None for deleted streams
+    streams: Vec<Option<Vec<u8>>>,
+}
+
+impl Msf {
+    fn parse(data: &[u8]) -> Result<Msf> {
+        if !is_pdb(data) {
+            return Err(invalid("Not a PDB"));
+        }
+        let block_size = u32_at(data, 32)? as usize;
+        if !matches!(block_size, 512 | 1024 | 2048 | 4096) {
+            return Err(invalid("Unexpected block size"));
+        }
+        let num_blocks = u32_at(data, 40)? as usize;
+        let directory_size = u32_at(data, 44)? as usize;
+        let block_map = u32_at(data, 52)? as usize;
+        let block = |index: usize| {
+            let start = index * block_size;
+            match data.get(start..start + block_size) {
+                Some(block) if index < num_blocks => Ok(block),
+                _ => Err(invalid("Block is out of bounds")),
+            }
+        };
+
+        let directory_blocks = directory_size.div_ceil(block_size);
+        if directory_blocks * 4 > block_size {
+            return Err(invalid("Stream directory is too large"));
+        }
+        let block_map = block(block_map)?;
+        let mut directory = Vec::with_capacity(directory_blocks * block_size);
+        for i in 0..directory_blocks {
+            directory.extend_from_slice(block(u32_at(block_map, i * 4)? as usize)?);
+        }
+        directory.truncate(directory_size);
+
+        let num_streams = u32_at(&directory, 0)? as usize;
+        let mut at = num_streams
+            .checked_mul(4)
+            .and_then(|sizes| sizes.checked_add(4))
+            .filter(|&at| at <= directory.len())
+            .ok_or_else(|| invalid("Stream directory is truncated"))?;
+        let mut streams = Vec::with_capacity(num_streams);
+        for i in 0..num_streams {
+            let size = u32_at(&directory, 4 + i * 4)?;
+            if size == NIL_STREAM {
+                streams.push(None);
+                continue;
+            }
+            let size = size as usize;
+            let mut stream = Vec::with_capacity(size);
+            for _ in 0..size.div_ceil(block_size) {
+                stream.extend_from_slice(block(u32_at(&directory, at)? as usize)?);
+                at += 4;
+            }
+            stream.truncate(size);
+            streams.push(Some(stream));
+        }
+        if streams.len() <= INFO_STREAM {
+            return Err(invalid("No PDB info stream"));
+        }
+        Ok(Msf {
+            block_size,
+            streams,
+        })
+    }
+
+    fn info(&self) -> Result<&[u8]> {
+        self.streams[INFO_STREAM]
+            .as_deref()
+            .ok_or_else(|| invalid("No PDB info stream"))
+    }
+
+    // Lay the streams out one after another, followed by the directory and the
+    // block map, with every block in use
+    fn write(&self) -> Result<Vec<u8>> {
+        let mut out = Blocks {
+            size: self.block_size,
+            data: vec![0; self.block_size],
+        };
+
+        let mut directory = Vec::new();
+        let mut stream_blocks = Vec::new();
+        push_u32(&mut directory, self.streams.len() as u32);
+        for stream in &self.streams {
+            match stream {
+                Some(stream) => {
+                    let size =
+                        u32::try_from(stream.len()).map_err(|_| invalid("Stream is too large"))?;
+                    push_u32(&mut directory, size);
+                    for block in stream.chunks(self.block_size) {
+                        stream_blocks.push(out.push(block)?);
+                    }
+                }
+                None => push_u32(&mut directory, NIL_STREAM),
+            }
+        }
+        for block in stream_blocks {
+            push_u32(&mut directory, block);
+        }
+
+        let mut block_map = Vec::new();
+        for block in directory.chunks(self.block_size) {
+            push_u32(&mut block_map, out.push(block)?);
+        }
+        if block_map.len() > self.block_size {
+            return Err(invalid("Stream directory is too large"));
+        }
+        let block_map = out.push(&block_map)?;
+        out.finish();
+
+        let num_blocks = u32::try_from(out.data.len() / self.block_size)
+            .map_err(|_| invalid("Too many blocks"))?;
+        let mut superblock = MAGIC.to_vec();
+        push_u32(&mut superblock, self.block_size as u32);
+        // The first free block map is the current one
+        push_u32(&mut superblock, 1);
+        push_u32(&mut superblock, num_blocks);
+        push_u32(&mut superblock, directory.len() as u32);
+        push_u32(&mut superblock, 0);
+        push_u32(&mut superblock, block_map);
+        out.data[..superblock.len()].copy_from_slice(&superblock);
+        Ok(out.data)
+    }
+}
+
+// The blocks of an MSF being written
+struct Blocks {
+    size: usize,
+    data: Vec<u8>,
+}
+
+impl Blocks {
+    // Add a block holding data, returning its index
+    fn push(&mut self, data: &[u8]) -> Result<u32> {
+        if self.data.len() / self.size % self.size == 1 {
+            self.push_free_block_maps();
+        }
+        let index =
+            u32::try_from(self.data.len() / self.size).map_err(|_| invalid("Too many blocks"))?;
+        self.data.extend_from_slice(data);
+        self.data
+            .resize(self.data.len().next_multiple_of(self.size), 0);
+        Ok(index)
+    }
+
+    fn push_free_block_maps(&mut self) {
+        self.data.resize(self.data.len() + 2 * self.size, 0xFF);
+    }
+
+    // Every block is in use. A set bit in the free block maps is a free
+    // block, and the bits for block i are at byte i / 8 of the maps' blocks
+    // of each interval strung together.
+    fn finish(&mut self) {
+        if self.data.len() / self.size % self.size == 1 {
+            self.push_free_block_maps();
+        }
+        let num_blocks = self.data.len() / self.size;
+        for byte in 0..num_blocks.div_ceil(8) {
+            let used = (num_blocks - byte * 8).min(8);
+            let bits = 0xFFu8.checked_shl(used as u32).unwrap_or(0);
+            let interval = byte / self.size;
+            for map in 1..=2 {
+                let block = interval * self.size + map;
+                self.data[block * self.size + byte % self.size] = bits;
+            }
+        }
+    }
+}
+
+// The named stream map in the info stream at the range it takes up and the
+// name and index of each stream. After the version, signature, age and GUID
+// come the NUL terminated names, then a hash table of their offsets to
+// stream indices.
+fn named_streams(info: &[u8]) -> Result<(Range<usize>, Named)> {
+    let start = 28;
+    let names_size = u32_at(info, start)? as usize;
+    let names = info
+        .get(start + 4..)
+        .and_then(|rest| rest.get(..names_size))
+        .ok_or_else(|| invalid("Named stream map is truncated"))?;
+    let mut at = start + 4 + names_size;
+    let size = u32_at(info, at)? as usize;
+    let capacity = u32_at(info, at + 4)? as usize;
+    if size > capacity {
+        return Err(invalid("Named stream map is inconsistent"));
+    }
+    at += 8;
+    let present_words = u32_at(info, at)? as usize;
+    let present = (0..present_words)
+        .map(|i| u32_at(info, at + 4 + i * 4))
+        .collect::<Result<Vec<_>>>()?;
+    at += 4 + present_words * 4;
+    let deleted_words = u32_at(info, at)? as usize;
+    at += 4 + deleted_words * 4;
+
+    // Only as many streams as there are present buckets can be named, however
+    // large size claims to be
+    let buckets = capacity.min(present_words * 32);
+    let mut named = Vec::with_capacity(size.min(buckets));
+    for bucket in 0..buckets {
+        if present[bucket / 32] & 1 << (bucket % 32) == 0 {
+            continue;
+        }
+        let offset = u32_at(info, at)? as usize;
+        let index = u32_at(info, at + 4)?;
+        at += 8;
+        let name = names
+            .get(offset..)
+            .and_then(|name| name.split(|&b| b == 0).next())
+            .ok_or_else(|| invalid("Stream name is out of bounds"))?;
+        named.push((name.to_vec(), index));
+    }
+    if named.len() != size {
+        return Err(invalid("Named stream map is inconsistent"));
+    }
+    Ok((start..at, named))
+}
+
+// The named stream map of named, kept under two thirds full
+fn named_stream_map(named: &[(Vec<u8>, u32)]) -> Vec<u8> {
+    let mut names = Vec::new();
+    let mut offsets = Vec::new();
+    for (name, _) in named {
+        offsets.push(names.len() as u32);
+        names.extend_from_slice(name);
+        names.push(0);
+    }
+    let mut capacity = 8;
+    while named.len() * 3 >= capacity * 2 {
+        capacity *= 2;
+    }
+    let mut buckets = vec![None; capacity];
+    for (i, (name, _)) in named.iter().enumerate() {
+        let mut bucket = usize::from(hash(name)) % capacity;
+        while buckets[bucket].is_some() {
+            bucket = (bucket + 1) % capacity;
+        }
+        buckets[bucket] = Some(i);
+    }
+
+    let mut map = Vec::new();
+    push_u32(&mut map, names.len() as u32);
+    map.extend_from_slice(&names);
+    push_u32(&mut map, named.len() as u32);
+    push_u32(&mut map, capacity as u32);
+    let mut present = vec![0u32; capacity.div_ceil(32)];
+    for (bucket, _) in buckets.iter().enumerate().filter(|(_, i)| i.is_some()) {
+        present[bucket / 32] |= 1 << (bucket % 32);
+    }
+    // Words after the last present bucket are left out
+    while present.last() == Some(&0) {
+        present.pop();
+    }
+    push_u32(&mut map, present.len() as u32);
+    for word in present {
+        push_u32(&mut map, word);
+    }
+    // No deleted buckets
+    push_u32(&mut map, 0);
+    for i in buckets.into_iter().flatten() {
+        push_u32(&mut map, offsets[i]);
+        push_u32(&mut map, named[i].1);
+    }
+    map
+}
+
+// The hash the PDB reference implementation looks stream names up by,
+// truncated to 16 bits as it does
+fn hash(name: &[u8]) -> u16 {
+    let mut result = 0u32;
+    let mut words = name.chunks_exact(4);
+    for word in &mut words {
+        result ^= u32::from_le_bytes(<[u8; 4]>::try_from(word).unwrap());
+    }
+    let mut rest = words.remainder();
+    if rest.len() >= 2 {
+        result ^= u32::from(u16::from_le_bytes([rest[0], rest[1]]));
+        rest = &rest[2..];
+    }
+    if let Some(&byte) = rest.first() {
+        result ^= u32::from(byte);
+    }
+    result |= 0x2020_2020;
+    result ^= result >> 11;
+    (result ^ (result >> 16)) as u16
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32> {
+    data.get(at..at + 4)
+        .map(|bytes| u32::from_le_bytes(<[u8; 4]>::try_from(bytes).unwrap()))
+        .ok_or_else(|| invalid("PDB is truncated"))
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The PDB info stream's version, signature, age and GUID
+    const INFO_HEADER: [u8; 28] = [7; 28];
+    // What follows the named stream map, like the feature codes
+    const INFO_TRAILER: [u8; 8] = [0, 0, 0, 0, 0x4D, 0x49, 0x43, 0x4D];
+
+    // A minimal PDB: the old directory, the info stream naming named, and
+    // a stream for each of them
+    fn pdb(block_size: usize, named: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut streams = vec![Some(Vec::new()), None];
+        let mut names = Vec::new();
+        for (name, contents) in named {
+            names.push((name.as_bytes().to_vec(), streams.len() as u32));
+            streams.push(Some(contents.to_vec()));
+        }
+        let mut info = INFO_HEADER.to_vec();
+        info.extend_from_slice(&named_stream_map(&names));
+        info.extend_from_slice(&INFO_TRAILER);
+        streams[INFO_STREAM] = Some(info);
+        Msf {
+            block_size,
+            streams,
+        }
+        .write()
+        .unwrap()
+    }
+
+    // The info stream keeps what is around its named stream map
+    fn check_info(data: &[u8]) -> Named {
+        let msf = Msf::parse(data).unwrap();
+        let info = msf.info().unwrap();
+        let (map, named) = named_streams(info).unwrap();
+        assert_eq!(info[..map.start], INFO_HEADER);
+        assert_eq!(info[map.end..], INFO_TRAILER);
+        named
+    }
+
+    #[test]
+    fn inserts_a_stream() {
+        for block_size in [512, 4096] {
+            let data = pdb(block_size, &[("/names", b"names"), ("/LinkInfo", b"")]);
+            assert_eq!(read_stream(&data, SOURCE_LINK).unwrap(), None);
+
+            let json = br#"{"documents":{"C:\\src\\*":"https://example.com/*"}}"#;
+            let out = write_stream(&data, SOURCE_LINK, json).unwrap();
+            assert_eq!(
+                read_stream(&out, SOURCE_LINK).unwrap().as_deref(),
+                Some(&json[..])
+            );
+            assert_eq!(
+                read_stream(&out, "/names").unwrap().as_deref(),
+                Some(&b"names"[..])
+            );
+            assert_eq!(
+                read_stream(&out, "/LinkInfo").unwrap().as_deref(),
+                Some(&b""[..])
+            );
+            assert_eq!(check_info(&out).len(), 3);
+        }
+    }
+
+    #[test]
+    fn updates_a_stream() {
+        let data = pdb(512, &[("/names", b"names"), (SOURCE_LINK, b"{}")]);
+        let streams = Msf::parse(&data).unwrap().streams.len();
+        // Smaller and larger than a block, and back
+        for json in [vec![b'x'; 3000], b"{\"documents\":{}}".to_vec(), Vec::new()] {
+            let out = write_stream(&data, SOURCE_LINK, &json).unwrap();
+            assert_eq!(read_stream(&out, SOURCE_LINK).unwrap(), Some(json));
+            assert_eq!(
+                read_stream(&out, "/names").unwrap().as_deref(),
+                Some(&b"names"[..])
+            );
+            assert_eq!(Msf::parse(&out).unwrap().streams.len(), streams);
+            assert_eq!(check_info(&out).len(), 2);
+        }
+    }
+
+    // The map grows its hash table as streams are named
+    #[test]
+    fn inserts_into_a_full_map() {
+        let names = (0..20).map(|i| format!("/stream{}", i)).collect::<Vec<_>>();
+        let named = names
+            .iter()
+            .map(|name| (name.as_str(), name.as_bytes()))
+            .collect::<Vec<_>>();
+        let data = pdb(1024, &named);
+        let out = write_stream(&data, SOURCE_LINK, b"{}").unwrap();
+        assert_eq!(
+            read_stream(&out, SOURCE_LINK).unwrap().as_deref(),
+            Some(&b"{}"[..])
+        );
+        for name in &names {
+            assert_eq!(
+                read_stream(&out, name).unwrap().as_deref(),
+                Some(name.as_bytes())
+            );
+        }
+        assert_eq!(check_info(&out).len(), 21);
+    }
+
+    // A map claiming more names than it has buckets for is rejected rather
+    // than allocated for
+    #[test]
+    fn rejects_a_map_larger_than_its_capacity() {
+        let mut info = INFO_HEADER.to_vec();
+        info.extend_from_slice(&named_stream_map(&[(b"/names".to_vec(), 2)]));
+        let size = INFO_HEADER.len() + 4 + b"/names\0".len();
+        for (size_value, capacity) in [(u32::MAX, 8), (u32::MAX, u32::MAX), (2, 8)] {
+            let mut info = info.clone();
+            info[size..size + 4].copy_from_slice(&size_value.to_le_bytes());
+            info[size + 4..size + 8].copy_from_slice(&capacity.to_le_bytes());
+            assert!(
+                named_streams(&info).is_err(),
+                "{} of {}",
+                size_value,
+                capacity
+            );
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use log::{debug, warn};
+
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::diagnostics::Code;
+use crate::mapping;
+use crate::vcs::{Checkout, Link, LinkContext, VcsBackend};
+
+// A Perforce client workspace, as p4 info describes it
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub client: String,
+    // The depot directory the root is mapped to, like //depot/game/main
+    pub depot_path: String,
+    // The last changelist synced into the workspace
+    pub change: u64,
+    // Where its files are linked to
+    template: UrlTemplate,
+}
+
+// Where files in the depot can be fetched from. Either the address of a Helix
+// Swarm instance, or a template with {path}, the depot path without its
+// leading //, and {change} for other web frontends like the commit server's.
+#[derive(Debug, Clone)]
+pub struct UrlTemplate {
+    template: String,
+}
+
+impl std::str::FromStr for UrlTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let template = if s.contains('{') {
+            s.to_owned()
+        } else {
+            // Swarm serves the raw file at any changelist below /view
+            format!("{}/view/{{path}}?v=@{{change}}", s.trim_end_matches('/'))
+        };
+        if !template.contains("{path}") {
+            return Err(format!("\"{}\" has no {{path}} placeholder", s));
+        }
+        if let Err(e) = url::Url::parse(&template) {
+            return Err(format!("Invalid Perforce URL \"{}\": {}", s, e));
+        }
+        Ok(UrlTemplate { template })
+    }
+}
+
+impl UrlTemplate {
+    // The URL of everything below depot_path at change
+    pub fn url(&self, depot_path: &str, change: u64) -> Option<url::Url> {
+        let path = depot_path.trim_start_matches('/').trim_end_matches('/');
+        let url = self
+            .template
+            .replace("{path}", &format!("{}/*", path))
+            .replace("{change}", &change.to_string());
+        url::Url::parse(&url).ok()
+    }
+}
+
+// Workspaces are read with the p4 command line client, using whichever
+// P4PORT, P4CLIENT and P4CONFIG it would pick up in the directory
+pub struct P4 {
+    template: UrlTemplate,
+    // The workspaces found so far, so the server is asked once for each
+    workspaces: RefCell<Vec<Workspace>>,
+}
+
+impl P4 {
+    pub fn new(template: UrlTemplate) -> P4 {
+        P4 {
+            template,
+            workspaces: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl VcsBackend for P4 {
+    // Nothing on disk marks a workspace, so ask the server for the client
+    // the directory belongs to
+    fn discover(&self, path: &Path) -> Option<Box<dyn Checkout>> {
+        let mut workspaces = self.workspaces.borrow_mut();
+        if let Some(workspace) = workspaces
+            .iter()
+            .find(|workspace| path.starts_with(&workspace.root))
+        {
+            return Some(Box::new(workspace.clone()));
+        }
+        let dir = path.ancestors().find(|dir| dir.is_dir())?;
+        match workspace(dir, &self.template) {
+            Ok(workspace) if path.starts_with(&workspace.root) => {
+                workspaces.push(workspace.clone());
+                Some(Box::new(workspace))
+            }
+            Ok(workspace) => {
+                debug!(
+                    "{} is outside the root {} of client {}",
+                    path.display(),
+                    workspace.root.display(),
+                    workspace.client
+                );
+                None
+            }
+            Err(e) => {
+                debug!("No Perforce workspace for {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+impl Checkout for Workspace {
+    fn workdir(&self) -> &Path {
+        &self.root
+    }
+
+    fn kind(&self) -> &'static str {
+        "Perforce workspace"
+    }
+
+    fn head_name(&self) -> String {
+        format!("changelist {}", self.change)
+    }
+
+    // Files that were never submitted have no revision to sync
+    fn is_tracked(&self, rel_path: &Path) -> Result<bool, String> {
+        let path = self.root.join(rel_path);
+        match fstat(self, &path, "haveRev") {
+            Ok(have) => Ok(!have.is_empty()),
+            Err(e) if e.contains("no such file") || e.contains("not in client view") => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Files opened for edit may differ from the synced revision
+    fn is_clean(&self, rel_path: &Path) -> bool {
+        let path = self.root.join(rel_path);
+        fstat(self, &path, "action").map_or(true, |action| action.is_empty())
+    }
+
+    fn link(&self, _: &LinkContext) -> Option<Link> {
+        let link = link(self, &self.depot_path);
+        if link.is_none() {
+            diag!(
+                Code::InvalidRemoteUrl,
+                "Skipping workspace {}. No valid URL for {}",
+                self.root.display(),
+                self.depot_path
+            );
+        }
+        link
+    }
+
+    // The client view can map directories anywhere in the depot
+    fn link_dir(&self, dir: &Path) -> Option<Link> {
+        let depot_dir = match depot_dir(self, dir) {
+            Ok(depot_dir) => depot_dir,
+            Err(e) => {
+                warn!("Unable to find {} in the depot: {}", dir.display(), e);
+                return None;
+            }
+        };
+        let expected = dir
+            .strip_prefix(&self.root)
+            .ok()?
+            .components()
+            .fold(self.depot_path.clone(), |path, component| {
+                format!("{}/{}", path, component.as_os_str().to_string_lossy())
+            });
+        if depot_dir == expected {
+            return None;
+        }
+        link(self, &depot_dir)
+    }
+}
+
+// The link of depot_path at the changelist synced into workspace
+fn link(workspace: &Workspace, depot_path: &str) -> Option<Link> {
+    let url = workspace.template.url(depot_path, workspace.change)?;
+    let origin = mapping::Origin::new(depot_path.to_owned(), format!("@{}", workspace.change));
+    Some(Link { url, origin })
+}
+
+// The depot directory dir is mapped to by the client's view, which may differ
+// from its place below the root
+fn depot_dir(workspace: &Workspace, dir: &Path) -> Result<String, String> {
+    let records = p4(
+        &workspace.root,
+        [
+            OsStr::new("-ztag"),
+            OsStr::new("where"),
+            dir.join("...").as_os_str(),
+        ],
+    )?;
+    // Later view lines override earlier ones, and exclude files when they
+    // unmap them
+    let record = records.split("\n\n").last().unwrap_or_default();
+    let mut fields = record.lines().filter_map(|line| line.strip_prefix("... "));
+    let file = fields
+        .clone()
+        .find_map(|field| field.strip_prefix("depotFile "))
+        .filter(|_| !fields.any(|field| field == "unmap"))
+        .ok_or_else(|| {
+            format!(
+                "{} is not in the view of {}",
+                dir.display(),
+                workspace.client
+            )
+        })?;
+    Ok(file
+        .trim_end_matches("...")
+        .trim_end_matches('/')
+        .to_owned())
+}
+
+fn workspace(dir: &Path, template: &UrlTemplate) -> Result<Workspace, String> {
+    let item = |name: &str| p4(dir, ["-ztag", "-F", &format!("%{}%", name), "info"]);
+    let client = item("clientName")?;
+    if client.is_empty() || client == "*unknown*" {
+        return Err("no client workspace is set".to_owned());
+    }
+    let root = PathBuf::from(item("clientRoot")?);
+    let change = p4(
+        &root,
+        [
+            OsStr::new("-ztag"),
+            OsStr::new("-F"),
+            OsStr::new("%change%"),
+            OsStr::new("changes"),
+            OsStr::new("-m1"),
+            root.join("...#have").as_os_str(),
+        ],
+    )?;
+    let mut workspace = Workspace {
+        root,
+        client,
+        depot_path: String::new(),
+        change: change
+            .parse()
+            .map_err(|_| format!("Invalid changelist \"{}\"", change))?,
+        template: template.clone(),
+    };
+    workspace.depot_path = depot_dir(&workspace, &workspace.root)?;
+    Ok(workspace)
+}
+
+// The value of field in p4 fstat of path, empty when it is not set
+fn fstat(workspace: &Workspace, path: &Path, field: &str) -> Result<String, String> {
+    p4(
+        &workspace.root,
+        [
+            OsStr::new("-ztag"),
+            OsStr::new("-F"),
+            OsStr::new(&format!("%{}%", field)),
+            OsStr::new("fstat"),
+            path.as_os_str(),
+        ],
+    )
+}
+
+fn p4<I, S>(dir: &Path, args: I) -> Result<String, String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    // p4 finds its P4CONFIG file from PWD rather than the working directory
+    let output = Command::new("p4")
+        .arg("-d")
+        .arg(dir)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("Unable to run p4: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    // Some errors, like files not in the view, still exit with 0
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        return Err(stderr.trim().to_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
@@ -0,0 +1,268 @@
+use std::path::{Component, Path, PathBuf};
+
+// C and C++ package managers that build dependencies from source archives,
+// with no repo on disk but a record of where the archive came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Manager {
+    Vcpkg,
+    Conan,
+}
+
+// Where the sources a package manager unpacked to dir came from
+#[derive(Debug)]
+pub struct Upstream {
+    pub dir: PathBuf,
+    pub remote: String,
+    // A commit, or the tag or branch the archive was made from
+    pub reference: String,
+}
+
+// The directory the sources of the package path is in were unpacked to, and
+// by what
+pub fn source_dir(path: &Path) -> Option<(Manager, PathBuf)> {
+    let names = path
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let prefix = |len: usize| path.components().take(len).collect::<PathBuf>();
+
+    // <vcpkg root>/buildtrees/<port>/src/<ref>-<hash>.clean
+    if let Some(i) = names.iter().position(|name| *name == Some("buildtrees")) {
+        if names.get(i + 2) == Some(&Some("src")) && names.len() > i + 4 {
+            return Some((Manager::Vcpkg, prefix(i + 4)));
+        }
+    }
+
+    // Conan 1: ~/.conan/data/<name>/<version>/<user>/<channel>/source or
+    // .../build/<package id>. Conan 2: ~/.conan2/p/<package>/s or
+    // ~/.conan2/p/b/<package>/b.
+    let i = names.iter().position(|name| *name == Some(".conan"));
+    let dir = match i.map(|i| (i, &names[i..])) {
+        Some((i, [_, Some("data"), _, _, _, _, Some("source"), _, ..])) => Some(i + 7),
+        Some((i, [_, Some("data"), _, _, _, _, Some("build"), _, _, ..])) => Some(i + 8),
+        _ => None,
+    };
+    let i = names.iter().position(|name| *name == Some(".conan2"));
+    let dir = dir.or(match i.map(|i| (i, &names[i..])) {
+        Some((i, [_, Some("p"), Some("b"), _, Some("b"), _, ..])) => Some(i + 5),
+        Some((i, [_, Some("p"), _, Some("s"), _, ..])) => Some(i + 4),
+        _ => None,
+    })?;
+    // Recipes unpack the archive to a subfolder of their own
+    let dir = match names.get(dir) {
+        Some(Some("src")) | Some(Some("source_subfolder")) if names.len() > dir + 1 => dir + 1,
+        _ => dir,
+    };
+    Some((Manager::Conan, prefix(dir)))
+}
+
+pub fn load(manager: Manager, dir: &Path) -> Result<Upstream, String> {
+    let (remote, reference) = match manager {
+        Manager::Vcpkg => vcpkg_source(dir)?,
+        Manager::Conan => conan_source(dir)?,
+    };
+    Ok(Upstream {
+        dir: dir.to_owned(),
+        remote,
+        reference,
+    })
+}
+
+// The vcpkg_from_github, vcpkg_from_gitlab or vcpkg_from_git call in the
+// port's portfile.cmake that the sources in dir were unpacked by
+fn vcpkg_source(dir: &Path) -> Result<(String, String), String> {
+    let port_dir = dir.parent().and_then(Path::parent).unwrap();
+    let port = port_dir.file_name().unwrap().to_string_lossy();
+    let root = port_dir.parent().and_then(Path::parent).unwrap();
+    let ports = root.join("ports").join(&*port);
+    let portfile = std::fs::read_to_string(ports.join("portfile.cmake"))
+        .map_err(|e| format!("Unable to read the portfile of {}: {}", port, e))?;
+
+    // ${VERSION} is the version in the port's manifest
+    let manifest = std::fs::read(ports.join("vcpkg.json"))
+        .ok()
+        .and_then(|data| serde_json::from_slice::<serde_json::Value>(&data).ok())
+        .unwrap_or_default();
+    let version = [
+        "version",
+        "version-semver",
+        "version-date",
+        "version-string",
+    ]
+    .iter()
+    .find_map(|field| manifest[field].as_str());
+
+    let mut sources = Vec::new();
+    for function in ["vcpkg_from_github", "vcpkg_from_gitlab", "vcpkg_from_git"] {
+        for (at, call) in portfile.match_indices(&format!("{}(", function)) {
+            let rest = &portfile[at + call.len()..];
+            let args = &rest[..rest.find(')').unwrap_or(rest.len())];
+            let arg = |name: &str| {
+                let mut tokens = args.split_whitespace().map(|token| token.trim_matches('"'));
+                tokens.find(|token| *token == name)?;
+                let value = tokens.next()?;
+                match version {
+                    Some(version) => Some(value.replace("${VERSION}", version)),
+                    None => Some(value.to_owned()),
+                }
+            };
+            let remote = match function {
+                "vcpkg_from_github" => arg("REPO").map(|repo| {
+                    let host =
+                        arg("GITHUB_HOST").unwrap_or_else(|| "https://github.com".to_owned());
+                    format!("{}/{}", host, repo)
+                }),
+                "vcpkg_from_gitlab" => arg("REPO")
+                    .zip(arg("GITLAB_URL"))
+                    .map(|(repo, host)| format!("{}/{}", host.trim_end_matches('/'), repo)),
+                _ => arg("URL"),
+            };
+            if let (Some(remote), Some(reference)) = (remote, arg("REF")) {
+                sources.push((remote, reference));
+            }
+        }
+    }
+    if sources.is_empty() {
+        return Err(format!("No source of {} in its portfile", port));
+    }
+
+    // vcpkg names the directory after the ref, which tells the sources of
+    // ports with several apart
+    let name = dir.file_name().unwrap().to_string_lossy();
+    let index = sources
+        .iter()
+        .position(|(_, reference)| name.starts_with(&reference.replace('/', "-")))
+        .unwrap_or(0);
+    let (remote, reference) = sources.swap_remove(index);
+    if remote.contains("${") || reference.contains("${") {
+        return Err(format!(
+            "The source of {} depends on variables of its portfile",
+            port
+        ));
+    }
+    Ok((remote, reference))
+}
+
+// The archive in the recipe's conandata.yml for the package's version
+fn conan_source(dir: &Path) -> Result<(String, String), String> {
+    let names = dir
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>();
+    let (conandata, version) = match names.iter().rposition(|name| name == ".conan") {
+        // data/<name>/<version>/<user>/<channel>/export/conandata.yml
+        Some(i) => (
+            dir.components()
+                .take(i + 6)
+                .collect::<PathBuf>()
+                .join("export")
+                .join("conandata.yml"),
+            names.get(i + 3).map(|version| version.to_string()),
+        ),
+        // p/<package>/e/conandata.yml or p/b/<package>/e/conandata.yml, the
+        // path has no version
+        None => {
+            let i = names.iter().rposition(|name| name == ".conan2").unwrap();
+            let len = if names[i + 2] == "b" { i + 4 } else { i + 3 };
+            (
+                dir.components()
+                    .take(len)
+                    .collect::<PathBuf>()
+                    .join("e")
+                    .join("conandata.yml"),
+                None,
+            )
+        }
+    };
+    let conandata = std::fs::read_to_string(&conandata)
+        .map_err(|e| format!("Unable to read {}: {}", conandata.display(), e))?;
+    source_urls(&conandata, version.as_deref())?
+        .iter()
+        .find_map(|url| archive_source(url))
+        .ok_or_else(|| "No source archive of the recipe is from GitHub or GitLab".to_owned())
+}
+
+// The URLs of the version's entry in the sources of conandata.yml. Only the
+// subset of YAML recipes use is understood:
+//
+//   sources:
+//     "1.3.1":
+//       url:
+//         - "https://github.com/madler/zlib/releases/download/v1.3.1/zlib-1.3.1.tar.gz"
+//       sha256: "..."
+fn source_urls(conandata: &str, version: Option<&str>) -> Result<Vec<String>, String> {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let mut lines = conandata
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .skip_while(|line| line.trim_end() != "sources:")
+        .skip(1)
+        .take_while(|line| indent(line) > 0)
+        .peekable();
+
+    let key_indent = lines.peek().map(|line| indent(line));
+    let mut entries = Vec::<(String, Vec<String>)>::new();
+    for line in lines {
+        let trimmed = line.trim();
+        if Some(indent(line)) == key_indent {
+            let key = trimmed.trim_end_matches(':').trim_matches(['"', '\'']);
+            entries.push((key.to_owned(), Vec::new()));
+        } else if let Some((_, urls)) = entries.last_mut() {
+            let value = trimmed
+                .trim_start_matches("url:")
+                .trim_start_matches('-')
+                .trim()
+                .trim_matches(['"', '\'']);
+            if value.starts_with("https://") || value.starts_with("http://") {
+                urls.push(value.to_owned());
+            }
+        }
+    }
+
+    let entry = match version {
+        Some(version) => entries.into_iter().find(|(key, _)| key == version),
+        None if entries.len() > 1 => {
+            return Err("conandata.yml has several versions and the path names none".to_owned())
+        }
+        None => entries.pop(),
+    };
+    entry.map(|(_, urls)| urls).ok_or_else(|| {
+        format!(
+            "No sources for {} in conandata.yml",
+            version.unwrap_or("the recipe")
+        )
+    })
+}
+
+// The repo and ref of a GitHub or GitLab archive or release download URL
+fn archive_source(url: &str) -> Option<(String, String)> {
+    let url = url::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    let segments = url.path_segments()?.collect::<Vec<_>>();
+    let strip = |name: &str| {
+        [".tar.gz", ".tar.bz2", ".tar.xz", ".tgz", ".zip"]
+            .iter()
+            .find_map(|extension| name.strip_suffix(extension))
+            .map(str::to_owned)
+    };
+    let (repo, reference) = match segments.as_slice() {
+        [owner, repo, "archive", "refs", "tags", tag] if host == "github.com" => {
+            (format!("{}/{}", owner, repo), strip(tag)?)
+        }
+        [owner, repo, "archive", reference] if host == "github.com" => {
+            (format!("{}/{}", owner, repo), strip(reference)?)
+        }
+        [owner, repo, "releases", "download", tag, _] if host == "github.com" => {
+            (format!("{}/{}", owner, repo), tag.to_string())
+        }
+        // <project>/-/archive/<ref>/<name>-<ref>.tar.gz
+        [project @ .., "-", "archive", reference, _] if !project.is_empty() => {
+            (project.join("/"), reference.to_string())
+        }
+        _ => return None,
+    };
+    Some((format!("https://{}/{}", host, repo), reference))
+}
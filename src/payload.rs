@@ -0,0 +1,48 @@
+use std::io::{Error, ErrorKind, Result};
+use std::str::FromStr;
+
+// Encoding of the mapping inside the section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    // Smaller and faster to parse for consumers without a JSON parser at hand
+    Cbor,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "cbor" => Ok(Format::Cbor),
+            _ => Err(format!("Unknown payload format \"{}\"", s)),
+        }
+    }
+}
+
+pub fn encode(format: Format, value: &serde_json::Value) -> Vec<u8> {
+    match format {
+        Format::Json => serde_json::to_vec(value).unwrap(),
+        Format::Cbor => {
+            let mut encoded = Vec::new();
+            ciborium::into_writer(value, &mut encoded).unwrap();
+            encoded
+        }
+    }
+}
+
+// JSON sections always start with '{'. A CBOR map has major type 5 in the top
+// three bits of its first byte.
+pub fn decode(data: &[u8]) -> Result<serde_json::Value> {
+    match data.first() {
+        Some(b'{') => Ok(serde_json::from_slice(data)?),
+        Some(byte) if byte >> 5 == 5 => {
+            ciborium::from_reader(data).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "section is neither JSON nor CBOR",
+        )),
+    }
+}
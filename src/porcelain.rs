@@ -0,0 +1,46 @@
+use std::path::Path;
+
+// --porcelain=v1 prints one line per binary, and per embedded image, to stdout:
+//
+//   STATUS <TAB> PATH [<TAB> OFFSET] <NEWLINE>
+//
+// STATUS is one of skipped, unmapped, dryrun, updated, failed or, for images
+// that are only reported, image. OFFSET is only there for embedded images.
+// Nothing else is printed to stdout, and the format only changes with a new
+// version.
+pub fn print(status: &str, path: &Path, offset: Option<usize>) {
+    match offset {
+        Some(offset) => println!("{}\t{}\t{}", status, quote(path), offset),
+        None => println!("{}\t{}", status, quote(path)),
+    }
+}
+
+// The path with backslashes, tabs and newlines escaped so it stays one field.
+// Paths that are not valid UTF-8 have their non ASCII bytes printed as \xNN.
+fn quote(path: &Path) -> String {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    let mut quoted = String::with_capacity(bytes.len());
+    match std::str::from_utf8(bytes) {
+        Ok(path) => path.chars().for_each(|c| escape(c, &mut quoted)),
+        Err(_) => {
+            for &b in bytes {
+                if b.is_ascii() {
+                    escape(b as char, &mut quoted);
+                } else {
+                    quoted.push_str(&format!("\\x{:02x}", b));
+                }
+            }
+        }
+    }
+    quoted
+}
+
+fn escape(c: char, quoted: &mut String) {
+    match c {
+        '\\' => quoted.push_str("\\\\"),
+        '\t' => quoted.push_str("\\t"),
+        '\n' => quoted.push_str("\\n"),
+        '\r' => quoted.push_str("\\r"),
+        c => quoted.push(c),
+    }
+}
@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+
+// The -fdebug-prefix-map=OLD=NEW (or --remap-path-prefix OLD=NEW) binaries were
+// built with. Their debug info names files under NEW, a neutral prefix that
+// doesn't disclose the build machine's layout, while on this machine the files
+// are under OLD.
+#[derive(Debug, Clone)]
+pub struct PrefixMap {
+    pub old: PathBuf,
+    pub new: PathBuf,
+}
+
+impl std::str::FromStr for PrefixMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.rsplit_once('=') {
+            Some((old, new)) if !old.is_empty() && !new.is_empty() => Ok(PrefixMap {
+                old: PathBuf::from(old),
+                new: PathBuf::from(new),
+            }),
+            _ => Err(format!("Invalid prefix map \"{}\", expected OLD=NEW", s)),
+        }
+    }
+}
+
+// The same map written the other way around, REMAPPED=LOCAL, for builds whose
+// prefix map isn't at hand, like Bazel's /proc/self/cwd. LOCAL may be relative
+// to the working directory.
+#[derive(Debug, Clone)]
+pub struct PathMap(pub PrefixMap);
+
+impl std::str::FromStr for PathMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((remapped, local)) if !remapped.is_empty() && !local.is_empty() => {
+                Ok(PathMap(local_map(remapped, local)?))
+            }
+            _ => Err(format!(
+                "Invalid path map \"{}\", expected REMAPPED=LOCAL",
+                s
+            )),
+        }
+    }
+}
+
+// The root a container build saw its sources under, OLD:NEW, and where they
+// are on this machine. Either may be a Windows path with a drive letter, whose
+// colon isn't the separator. NEW may be relative to the working directory.
+#[derive(Debug, Clone)]
+pub struct BuildRoot(pub PrefixMap);
+
+impl std::str::FromStr for BuildRoot {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.as_bytes();
+        let is_drive = |colon: usize| {
+            colon > 0
+                && bytes[colon - 1].is_ascii_alphabetic()
+                && (colon == 1 || bytes[colon - 2] == b':')
+        };
+        let split = s
+            .match_indices(':')
+            .map(|(colon, _)| colon)
+            .find(|colon| !is_drive(*colon));
+        match split.map(|colon| (&s[..colon], &s[colon + 1..])) {
+            Some((old, new)) if !old.is_empty() && !new.is_empty() => {
+                Ok(BuildRoot(local_map(old, new)?))
+            }
+            _ => Err(format!("Invalid build root \"{}\", expected OLD:NEW", s)),
+        }
+    }
+}
+
+// A bind mount, host=OLD,container=NEW, for running in a container that sees
+// the directory sources were built in on the host under another path
+#[derive(Debug, Clone)]
+pub struct PathTranslate(pub PrefixMap);
+
+impl std::str::FromStr for PathTranslate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid path translation \"{}\", expected host=OLD,container=NEW",
+                s
+            )
+        };
+        let (mut host, mut container) = (None, None);
+        for field in s.split(',') {
+            match field.split_once('=') {
+                Some(("host", path)) if !path.is_empty() => host = Some(path),
+                Some(("container", path)) if !path.is_empty() => container = Some(path),
+                _ => return Err(invalid()),
+            }
+        }
+        match (host, container) {
+            (Some(host), Some(container)) => Ok(PathTranslate(local_map(host, container)?)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// The map of files debug info names under remapped to local, relative to the
+// working directory
+fn local_map(remapped: &str, local: &str) -> Result<PrefixMap, String> {
+    let local = std::env::current_dir()
+        .map_err(|e| format!("Unable to resolve \"{}\": {}", local, e))?
+        .join(local)
+        .components()
+        .collect();
+    Ok(PrefixMap {
+        old: local,
+        new: PathBuf::from(remapped),
+    })
+}
+
+// path with / separators and a lower case drive letter, when it is a Windows
+// path like C:\src, which debug info of binaries built on Windows names files
+// by. Windows paths are only compared case-insensitively.
+fn windows_path(path: &Path) -> Option<String> {
+    let path = path.to_str()?;
+    match path.as_bytes() {
+        [drive, b':', b'\\' | b'/', ..] if drive.is_ascii_alphabetic() => {
+            Some(path.replace('\\', "/").to_ascii_lowercase())
+        }
+        _ => None,
+    }
+}
+
+// Where the file debug info names path is on this machine
+pub fn to_local(maps: &[PrefixMap], path: &Path) -> Option<PathBuf> {
+    maps.iter().find_map(|map| {
+        if let (Some(new), Some(windows)) = (windows_path(&map.new), windows_path(path)) {
+            let rest = windows.strip_prefix(new.trim_end_matches('/'))?;
+            if !rest.is_empty() && !rest.starts_with('/') {
+                return None;
+            }
+            // The case of the names below NEW matters on this machine, so take
+            // them from path, which is as long as its lowercase form
+            let path = path.to_str()?;
+            return Some(
+                path[path.len() - rest.len()..]
+                    .split(['/', '\\'])
+                    .filter(|name| !name.is_empty())
+                    .fold(map.old.clone(), |local, name| local.join(name)),
+            );
+        }
+        Some(map.old.join(path.strip_prefix(&map.new).ok()?))
+    })
+}
+
+// The name debug info has for path on this machine
+pub fn to_debug(maps: &[PrefixMap], path: &Path) -> Option<PathBuf> {
+    maps.iter().find_map(|map| {
+        let rest = path.strip_prefix(&map.old).ok()?;
+        if windows_path(&map.new).is_none() {
+            return Some(map.new.join(rest));
+        }
+        // Named with the separator NEW is written with
+        let new = map.new.to_str()?;
+        let separator = if new.contains('/') { '/' } else { '\\' };
+        let mut debug = new.trim_end_matches(['/', '\\']).to_owned();
+        for name in rest.components() {
+            debug.push(separator);
+            debug.push_str(name.as_os_str().to_str()?);
+        }
+        Some(PathBuf::from(debug))
+    })
+}
@@ -0,0 +1,63 @@
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+// A crate cargo unpacked from a registry, which is not a checkout of anything
+#[derive(Debug)]
+pub struct Package {
+    pub dir: PathBuf,
+    pub name: String,
+    pub version: String,
+    pub repository: Option<String>,
+    // The commit it was published from, recorded by cargo package
+    pub commit: Option<String>,
+    // Where the package is in the repo, for workspace members
+    pub path_in_vcs: String,
+}
+
+// The directory of the registry crate path is in. Cargo unpacks them to
+// $CARGO_HOME/registry/src/<registry>/<name>-<version>.
+pub fn package_dir(path: &Path) -> Option<&Path> {
+    path.ancestors().skip(1).find(|dir| {
+        let mut registry = dir.ancestors().skip(2);
+        registry.next().and_then(Path::file_name) == Some("src".as_ref())
+            && registry.next().and_then(Path::file_name) == Some("registry".as_ref())
+    })
+}
+
+// Read the Cargo.toml and .cargo_vcs_info.json cargo package put in dir
+pub fn load(dir: &Path) -> Result<Package> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+    let manifest = std::fs::read_to_string(dir.join("Cargo.toml"))?
+        .parse::<toml::Table>()
+        .map_err(|e| invalid(e.to_string()))?;
+    let package = manifest
+        .get("package")
+        .and_then(toml::Value::as_table)
+        .ok_or_else(|| invalid("Cargo.toml has no [package]".to_owned()))?;
+    let field = |name: &str| package.get(name).and_then(toml::Value::as_str);
+
+    // Only missing when the package was published without git, or dirty with
+    // --allow-dirty before cargo recorded that
+    let vcs_info = match std::fs::read(dir.join(".cargo_vcs_info.json")) {
+        Ok(data) => serde_json::from_slice::<serde_json::Value>(&data)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => serde_json::Value::Null,
+        Err(e) => return Err(e),
+    };
+
+    Ok(Package {
+        dir: dir.to_owned(),
+        name: field("name")
+            .ok_or_else(|| invalid("Cargo.toml has no package name".to_owned()))?
+            .to_owned(),
+        version: field("version")
+            .ok_or_else(|| invalid("Cargo.toml has no package version".to_owned()))?
+            .to_owned(),
+        repository: field("repository").map(str::to_owned),
+        commit: vcs_info["git"]["sha1"].as_str().map(str::to_owned),
+        path_in_vcs: vcs_info["path_in_vcs"]
+            .as_str()
+            .unwrap_or_default()
+            .trim_matches('/')
+            .to_owned(),
+    })
+}
@@ -0,0 +1,159 @@
+use log::{debug, info};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::Metadata;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::cleanup;
+
+// Remembers which files are possible symbol files across runs, so re-scans of
+// mostly unchanged artifact trees only read the magic of files that changed.
+// An entry is only trusted while the file's inode, size and mtime still match.
+// Only files seen during a run are saved, so one cache file should be used per
+// tree.
+struct ScanCache {
+    path: PathBuf,
+    entries: HashMap<PathBuf, Entry>,
+    seen: HashMap<PathBuf, Entry>,
+    hits: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry {
+    stamp: Stamp,
+    symbol: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    inode: u64,
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+}
+
+impl Stamp {
+    fn new(metadata: &Metadata) -> Option<Stamp> {
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Stamp {
+            inode: inode(metadata),
+            size: metadata.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> u64 {
+    0
+}
+
+static CACHE: Mutex<Option<ScanCache>> = Mutex::new(None);
+
+// Use the cache at path for the rest of the run, starting empty if it does not
+// exist yet
+pub fn load(path: &Path) -> Result<()> {
+    let entries = match std::fs::read(path) {
+        Ok(data) => parse(&data)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "scan cache is corrupt"))?,
+        Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+        Err(e) => return Err(e),
+    };
+    debug!("Loaded {} entries from {}", entries.len(), path.display());
+    *CACHE.lock().unwrap() = Some(ScanCache {
+        path: path.to_owned(),
+        entries,
+        seen: HashMap::new(),
+        hits: 0,
+    });
+    Ok(())
+}
+
+// Whether path was a possible symbol file the last time it was scanned, if it
+// has not changed since
+pub fn lookup(path: &Path, metadata: &Metadata) -> Option<bool> {
+    let mut cache = CACHE.lock().unwrap();
+    let cache = cache.as_mut()?;
+    let entry = *cache.entries.get(path)?;
+    if Some(entry.stamp) != Stamp::new(metadata) {
+        return None;
+    }
+    cache.hits += 1;
+    cache.seen.insert(path.to_owned(), entry);
+    Some(entry.symbol)
+}
+
+pub fn store(path: &Path, metadata: &Metadata, symbol: bool) {
+    if let Some(cache) = CACHE.lock().unwrap().as_mut() {
+        if let Some(stamp) = Stamp::new(metadata) {
+            cache.seen.insert(path.to_owned(), Entry { stamp, symbol });
+        }
+    }
+}
+
+// Write the files seen during this run back to the cache file
+pub fn save() -> Result<()> {
+    let cache = CACHE.lock().unwrap();
+    let cache = match cache.as_ref() {
+        Some(cache) => cache,
+        None => return Ok(()),
+    };
+    info!(
+        "Scan cache hit for {} of {} files",
+        cache.hits,
+        cache.seen.len()
+    );
+
+    let files = cache
+        .seen
+        .iter()
+        .filter_map(|(path, entry)| {
+            Some((
+                path.to_str()?.to_owned(),
+                serde_json::json!([
+                    entry.stamp.inode,
+                    entry.stamp.size,
+                    entry.stamp.mtime_secs,
+                    entry.stamp.mtime_nanos,
+                    entry.symbol,
+                ]),
+            ))
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let json = serde_json::json!({ "version": 1, "files": files });
+
+    cleanup::write(&cache.path, json.to_string().as_bytes())
+}
+
+// {"version": 1, "files": {PATH: [inode, size, mtime_secs, mtime_nanos, symbol]}}
+fn parse(data: &[u8]) -> Option<HashMap<PathBuf, Entry>> {
+    let json = serde_json::from_slice::<serde_json::Value>(data).ok()?;
+    if json["version"] != 1 {
+        // An older layout, rebuilding it is only a slower scan
+        return Some(HashMap::new());
+    }
+    json["files"]
+        .as_object()?
+        .iter()
+        .map(|(path, entry)| {
+            let stamp = Stamp {
+                inode: entry[0].as_u64()?,
+                size: entry[1].as_u64()?,
+                mtime_secs: entry[2].as_u64()?,
+                mtime_nanos: u32::try_from(entry[3].as_u64()?).ok()?,
+            };
+            let symbol = entry[4].as_bool()?;
+            Some((PathBuf::from(path), Entry { stamp, symbol }))
+        })
+        .collect()
+}
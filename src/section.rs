@@ -0,0 +1,642 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result, Write};
+
+// The section debuggers look for the Source Link JSON of an ELF in
+pub const SOURCE_LINK: &str = ".debug_sourcelink";
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_NOBITS: u32 = 8;
+const SHF_COMPRESSED: u64 = 0x800;
+const ELFCOMPRESS_ZLIB: u32 = 1;
+const ELFCOMPRESS_ZSTD: u32 = 2;
+
+// How sections are written, compressed like compilers compress .debug_*
+// sections with -gz. Debuggers and sourcelynk decompress them when reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Zlib,
+    Zstd,
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "zlib" => Ok(Compression::Zlib),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!(
+                "Unknown compression \"{}\", expected none, zlib or zstd",
+                s
+            )),
+        }
+    }
+}
+
+// binary with the Source Link JSON sourcelink, like {"documents": {...}},
+// embedded. For linkers and packagers that hold binaries in memory, the
+// command line tool writes sections with it too.
+pub fn embed(binary: &[u8], sourcelink: &serde_json::Value) -> Result<Vec<u8>> {
+    let data = serde_json::to_vec(sourcelink)?;
+    write(binary, SOURCE_LINK, &data)
+}
+
+// The ELF binary with the section called name holding data. An existing
+// section is overwritten when data fits in it and moved to the end of the file
+// otherwise. New sections are added at the end, along with a copy of the
+// section header table and the section name table that includes them.
+pub fn write(binary: &[u8], name: &str, data: &[u8]) -> Result<Vec<u8>> {
+    write_compressed(binary, name, data, Compression::None)
+}
+
+// Like write, with data compressed into an SHF_COMPRESSED section unless
+// compression is None
+pub fn write_compressed(
+    binary: &[u8],
+    name: &str,
+    data: &[u8],
+    compression: Compression,
+) -> Result<Vec<u8>> {
+    let elf = Layout::parse(binary)?;
+    let mut out = binary.to_vec();
+    // The compression header is aligned like the section header table
+    let (data, flags, align) = match compression {
+        Compression::None => (Cow::Borrowed(data), 0, 1),
+        _ => (
+            Cow::Owned(elf.compress(data, compression)?),
+            SHF_COMPRESSED,
+            elf.class(8, 4),
+        ),
+    };
+
+    let existing = (0..elf.shnum).find(|&i| elf.section_name(binary, i) == Some(name.as_bytes()));
+    if let Some(index) = existing {
+        let header = elf.header(index);
+        let (offset, size) = (
+            elf.word(binary, header + elf.sh_offset()),
+            elf.word(binary, header + elf.sh_size()),
+        );
+        // NOBITS sections, like a .debug_sourcelink stripped into a separate
+        // debug file, take no space in the file whatever their size
+        let nobits = elf.u32(binary, header + 4) == SHT_NOBITS;
+        let fits = !nobits
+            && to_usize(size).is_ok_and(|size| data.len() <= size)
+            && offset % align as u64 == 0;
+        let offset = if fits {
+            let offset = to_usize(offset)?;
+            out[offset..offset + data.len()].copy_from_slice(&data);
+            offset
+        } else {
+            append(&mut out, &data, align)
+        };
+        elf.check_size(&out)?;
+        if nobits {
+            elf.set_u32(&mut out, header + 4, SHT_PROGBITS);
+        }
+        let old_flags = elf.word(binary, header + elf.sh_flags());
+        elf.set_word(
+            &mut out,
+            header + elf.sh_flags(),
+            old_flags & !SHF_COMPRESSED | flags,
+        );
+        elf.set_word(&mut out, header + elf.sh_offset(), offset as u64);
+        elf.set_word(&mut out, header + elf.sh_size(), data.len() as u64);
+        elf.set_word(&mut out, header + elf.sh_addralign(), align as u64);
+        return Ok(out);
+    }
+
+    // The new name goes at the end of a copy of the section name table
+    let strtab = elf.header(elf.shstrndx);
+    let strtab_offset = to_usize(elf.word(binary, strtab + elf.sh_offset()))?;
+    let strtab_size = to_usize(elf.word(binary, strtab + elf.sh_size()))?;
+    let mut names = strtab_offset
+        .checked_add(strtab_size)
+        .and_then(|end| binary.get(strtab_offset..end))
+        .ok_or_else(|| invalid("Section name table is out of bounds"))?
+        .to_vec();
+    let name_offset =
+        u32::try_from(names.len()).map_err(|_| invalid("Section name table too large"))?;
+    names.extend_from_slice(name.as_bytes());
+    names.push(0);
+    let names_offset = append(&mut out, &names, 1);
+    let data_offset = append(&mut out, &data, align);
+
+    // Then a copy of the section header table with the new header
+    let mut headers = binary[elf.shoff..elf.shoff + elf.shnum * elf.shentsize].to_vec();
+    let mut header = vec![0; elf.shentsize];
+    elf.set_u32(&mut header, 0, name_offset);
+    elf.set_u32(&mut header, 4, SHT_PROGBITS);
+    elf.set_word(&mut header, elf.sh_flags(), flags);
+    elf.set_word(&mut header, elf.sh_offset(), data_offset as u64);
+    elf.set_word(&mut header, elf.sh_size(), data.len() as u64);
+    elf.set_word(&mut header, elf.sh_addralign(), align as u64);
+    headers.extend_from_slice(&header);
+    let strtab = elf.shstrndx * elf.shentsize;
+    elf.set_word(&mut headers, strtab + elf.sh_offset(), names_offset as u64);
+    elf.set_word(&mut headers, strtab + elf.sh_size(), names.len() as u64);
+    let shoff = append(&mut out, &headers, elf.class(8, 4));
+    elf.check_size(&out)?;
+
+    let shnum = u16::try_from(elf.shnum + 1).map_err(|_| invalid("Too many sections"))?;
+    elf.set_word(&mut out, elf.e_shoff(), shoff as u64);
+    elf.set_u16(&mut out, elf.e_shnum(), shnum);
+    Ok(out)
+}
+
+// Where the section header table and the section names are
+struct Layout {
+    is_64: bool,
+    little_endian: bool,
+    shoff: usize,
+    shentsize: usize,
+    shnum: usize,
+    shstrndx: usize,
+}
+
+impl Layout {
+    fn parse(binary: &[u8]) -> Result<Layout> {
+        if binary.get(..4) != Some(b"\x7FELF") {
+            return Err(invalid("Not an ELF binary"));
+        }
+        let is_64 = match binary.get(4) {
+            Some(1) => false,
+            Some(2) => true,
+            _ => return Err(invalid("Unknown ELF class")),
+        };
+        let little_endian = match binary.get(5) {
+            Some(1) => true,
+            Some(2) => false,
+            _ => return Err(invalid("Unknown ELF data encoding")),
+        };
+        let mut elf = Layout {
+            is_64,
+            little_endian,
+            shoff: 0,
+            shentsize: 0,
+            shnum: 0,
+            shstrndx: 0,
+        };
+        if binary.len() < elf.e_shstrndx() + 2 {
+            return Err(invalid("Truncated ELF header"));
+        }
+        elf.shoff = to_usize(elf.word(binary, elf.e_shoff()))?;
+        elf.shentsize = elf.u16(binary, elf.e_shnum() - 2) as usize;
+        elf.shnum = elf.u16(binary, elf.e_shnum()) as usize;
+        elf.shstrndx = elf.u16(binary, elf.e_shstrndx()) as usize;
+
+        // Extended numbering keeps the counts in the first section header
+        if elf.shoff == 0 || elf.shnum == 0 || elf.shstrndx >= elf.shnum {
+            return Err(invalid("No usable section header table"));
+        }
+        if elf.shentsize != elf.class(64, 40) {
+            return Err(invalid("Unexpected section header size"));
+        }
+        let end = elf
+            .shnum
+            .checked_mul(elf.shentsize)
+            .and_then(|size| size.checked_add(elf.shoff));
+        if end.is_none_or(|end| end > binary.len()) {
+            return Err(invalid("Section header table is out of bounds"));
+        }
+        for index in 0..elf.shnum {
+            let header = elf.header(index);
+            let offset = to_usize(elf.word(binary, header + elf.sh_offset()))?;
+            let size = to_usize(elf.word(binary, header + elf.sh_size()))?;
+            let nobits = elf.u32(binary, header + 4) == SHT_NOBITS;
+            if !nobits
+                && offset
+                    .checked_add(size)
+                    .is_none_or(|end| end > binary.len())
+            {
+                return Err(invalid("Section is out of bounds"));
+            }
+        }
+        Ok(elf)
+    }
+
+    // 32 bit ELFs can't have offsets past 4 GiB
+    fn check_size(&self, out: &[u8]) -> Result<()> {
+        if !self.is_64 && u32::try_from(out.len()).is_err() {
+            return Err(invalid("Too large for a 32 bit ELF"));
+        }
+        Ok(())
+    }
+
+    // data compressed behind an ELF compression header
+    fn compress(&self, data: &[u8], compression: Compression) -> Result<Vec<u8>> {
+        let ch_type = match compression {
+            Compression::None => return Ok(data.to_vec()),
+            Compression::Zlib => ELFCOMPRESS_ZLIB,
+            Compression::Zstd => ELFCOMPRESS_ZSTD,
+        };
+        if !self.is_64 && u32::try_from(data.len()).is_err() {
+            return Err(invalid("Too large for a 32 bit ELF"));
+        }
+        let mut out = vec![0; self.class(24, 12)];
+        self.set_u32(&mut out, 0, ch_type);
+        self.set_word(&mut out, self.class(8, 4), data.len() as u64);
+        self.set_word(&mut out, self.class(16, 8), 1);
+        match compression {
+            Compression::Zlib => {
+                let mut encoder = flate2::write::ZlibEncoder::new(out, flate2::Compression::best());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            _ => {
+                let level = ruzstd::encoding::CompressionLevel::Fastest;
+                ruzstd::encoding::compress(data, &mut out, level);
+                Ok(out)
+            }
+        }
+    }
+
+    // The offsets of the fields in the ELF header and section headers that
+    // differ between 32 and 64 bit
+    fn e_shoff(&self) -> usize {
+        self.class(0x28, 0x20)
+    }
+
+    fn e_shnum(&self) -> usize {
+        self.class(0x3C, 0x30)
+    }
+
+    fn e_shstrndx(&self) -> usize {
+        self.class(0x3E, 0x32)
+    }
+
+    // The same in both
+    fn sh_flags(&self) -> usize {
+        8
+    }
+
+    fn sh_offset(&self) -> usize {
+        self.class(24, 16)
+    }
+
+    fn sh_size(&self) -> usize {
+        self.class(32, 20)
+    }
+
+    fn sh_addralign(&self) -> usize {
+        self.class(48, 32)
+    }
+
+    fn class(&self, elf64: usize, elf32: usize) -> usize {
+        if self.is_64 {
+            elf64
+        } else {
+            elf32
+        }
+    }
+
+    fn header(&self, index: usize) -> usize {
+        self.shoff + index * self.shentsize
+    }
+
+    fn section_name<'a>(&self, binary: &'a [u8], index: usize) -> Option<&'a [u8]> {
+        let strtab = self.header(self.shstrndx);
+        let names = to_usize(self.word(binary, strtab + self.sh_offset())).ok()?;
+        let name = self.u32(binary, self.header(index)) as usize;
+        let name = binary.get(names.checked_add(name)?..)?;
+        name.split(|&b| b == 0).next()
+    }
+
+    fn u16(&self, data: &[u8], at: usize) -> u16 {
+        let bytes = [data[at], data[at + 1]];
+        if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, data: &[u8], at: usize) -> u32 {
+        let bytes = <[u8; 4]>::try_from(&data[at..at + 4]).unwrap();
+        if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    // An address, offset or size, which are 32 bit in 32 bit ELFs
+    fn word(&self, data: &[u8], at: usize) -> u64 {
+        if !self.is_64 {
+            return self.u32(data, at).into();
+        }
+        let bytes = <[u8; 8]>::try_from(&data[at..at + 8]).unwrap();
+        if self.little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        }
+    }
+
+    fn set_u16(&self, data: &mut [u8], at: usize, value: u16) {
+        let bytes = if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        };
+        data[at..at + 2].copy_from_slice(&bytes);
+    }
+
+    fn set_u32(&self, data: &mut [u8], at: usize, value: u32) {
+        let bytes = if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        };
+        data[at..at + 4].copy_from_slice(&bytes);
+    }
+
+    // Values written to a 32 bit ELF are checked to fit with check_size
+    fn set_word(&self, data: &mut [u8], at: usize, value: u64) {
+        if !self.is_64 {
+            return self.set_u32(data, at, value as u32);
+        }
+        let bytes = if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        };
+        data[at..at + 8].copy_from_slice(&bytes);
+    }
+}
+
+// Append data to out at a multiple of align, returning where it starts
+fn append(out: &mut Vec<u8>, data: &[u8], align: usize) -> usize {
+    let offset = out.len().div_ceil(align) * align;
+    out.resize(offset, 0);
+    out.extend_from_slice(data);
+    offset
+}
+
+fn to_usize(value: u64) -> Result<usize> {
+    usize::try_from(value).map_err(|_| invalid("Offset does not fit in memory"))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object::{Object, ObjectSection};
+
+    const TEXT: &[u8] = b"0123456789abcdef";
+
+    // The .debug_sourcelink section a fixture starts with
+    enum Existing {
+        None,
+        Progbits(Vec<u8>),
+        Nobits { offset: u64, size: u64 },
+    }
+
+    fn put(out: &mut [u8], at: usize, value: u64, width: usize, little_endian: bool) {
+        if little_endian {
+            out[at..at + width].copy_from_slice(&value.to_le_bytes()[..width]);
+        } else {
+            out[at..at + width].copy_from_slice(&value.to_be_bytes()[8 - width..]);
+        }
+    }
+
+    // A relocatable ELF with a .text section, the existing section and the
+    // section name table, followed by the section header table
+    fn fixture(is_64: bool, little_endian: bool, existing: Existing) -> Vec<u8> {
+        let class = |elf64: usize, elf32: usize| if is_64 { elf64 } else { elf32 };
+        let word = class(8, 4);
+        let mut out = vec![0; class(64, 52)];
+        let mut names = b"\0.text\0.shstrtab\0".to_vec();
+        // The name, type, offset and size of each section
+        let mut sections = vec![(0, 0, 0, 0)];
+        sections.push((1, SHT_PROGBITS, out.len() as u64, TEXT.len() as u64));
+        out.extend_from_slice(TEXT);
+        let name = names.len() as u64;
+        match existing {
+            Existing::None => {}
+            Existing::Progbits(data) => {
+                sections.push((name, SHT_PROGBITS, out.len() as u64, data.len() as u64));
+                out.extend_from_slice(&data);
+            }
+            Existing::Nobits { offset, size } => sections.push((name, SHT_NOBITS, offset, size)),
+        }
+        if sections.len() > 2 {
+            names.extend_from_slice(SOURCE_LINK.as_bytes());
+            names.push(0);
+        }
+        sections.push((7, 3, out.len() as u64, names.len() as u64));
+        out.extend_from_slice(&names);
+
+        let shoff = out.len().div_ceil(8) * 8;
+        let shentsize = class(64, 40);
+        out.resize(shoff + sections.len() * shentsize, 0);
+        for (index, (name, sh_type, offset, size)) in sections.iter().enumerate() {
+            let header = shoff + index * shentsize;
+            put(&mut out, header, *name, 4, little_endian);
+            put(&mut out, header + 4, (*sh_type).into(), 4, little_endian);
+            put(
+                &mut out,
+                header + class(24, 16),
+                *offset,
+                word,
+                little_endian,
+            );
+            put(&mut out, header + class(32, 20), *size, word, little_endian);
+            put(&mut out, header + class(48, 32), 1, word, little_endian);
+        }
+
+        out[..4].copy_from_slice(b"\x7FELF");
+        out[4] = if is_64 { 2 } else { 1 };
+        out[5] = if little_endian { 1 } else { 2 };
+        out[6] = 1;
+        put(&mut out, 16, 1, 2, little_endian);
+        put(&mut out, 18, if is_64 { 62 } else { 3 }, 2, little_endian);
+        put(&mut out, 20, 1, 4, little_endian);
+        put(
+            &mut out,
+            class(0x28, 0x20),
+            shoff as u64,
+            word,
+            little_endian,
+        );
+        put(
+            &mut out,
+            class(0x34, 0x28),
+            class(64, 52) as u64,
+            2,
+            little_endian,
+        );
+        put(
+            &mut out,
+            class(0x3A, 0x2E),
+            shentsize as u64,
+            2,
+            little_endian,
+        );
+        put(
+            &mut out,
+            class(0x3C, 0x30),
+            sections.len() as u64,
+            2,
+            little_endian,
+        );
+        put(
+            &mut out,
+            class(0x3E, 0x32),
+            (sections.len() - 1) as u64,
+            2,
+            little_endian,
+        );
+        out
+    }
+
+    const CLASSES: [(bool, bool); 4] = [(true, true), (true, false), (false, true), (false, false)];
+
+    // The data of the section called name as object reads it, with where it
+    // is in the file
+    fn read_back(binary: &[u8], name: &str) -> (Vec<u8>, u64) {
+        let file = object::File::parse(binary).unwrap();
+        assert_eq!(file.section_by_name(".text").unwrap().data().unwrap(), TEXT);
+        let section = file.section_by_name(name).unwrap();
+        let (offset, _) = section.file_range().unwrap();
+        (section.data().unwrap().to_vec(), offset)
+    }
+
+    #[test]
+    fn adds_a_section() {
+        for (is_64, little_endian) in CLASSES {
+            let binary = fixture(is_64, little_endian, Existing::None);
+            let out = write(&binary, SOURCE_LINK, b"{\"documents\":{}}").unwrap();
+            let (data, _) = read_back(&out, SOURCE_LINK);
+            assert_eq!(data, b"{\"documents\":{}}", "64 bit: {}", is_64);
+            let count = |binary: &[u8]| object::File::parse(binary).unwrap().sections().count();
+            assert_eq!(count(&out), count(&binary) + 1, "64 bit: {}", is_64);
+        }
+    }
+
+    #[test]
+    fn replaces_a_section_in_place() {
+        for (is_64, little_endian) in CLASSES {
+            let binary = fixture(is_64, little_endian, Existing::Progbits(vec![b' '; 64]));
+            let (_, before) = read_back(&binary, SOURCE_LINK);
+            let out = write(&binary, SOURCE_LINK, b"{}").unwrap();
+            assert_eq!(out.len(), binary.len(), "64 bit: {}", is_64);
+            let (data, offset) = read_back(&out, SOURCE_LINK);
+            assert_eq!(data, b"{}", "64 bit: {}", is_64);
+            assert_eq!(offset, before, "64 bit: {}", is_64);
+        }
+    }
+
+    #[test]
+    fn grows_a_section_by_appending_it() {
+        let json = vec![b'x'; 1000];
+        for (is_64, little_endian) in CLASSES {
+            let binary = fixture(is_64, little_endian, Existing::Progbits(b"{}".to_vec()));
+            let out = write(&binary, SOURCE_LINK, &json).unwrap();
+            let (data, offset) = read_back(&out, SOURCE_LINK);
+            assert_eq!(data, json, "64 bit: {}", is_64);
+            assert!(offset as usize >= binary.len(), "64 bit: {}", is_64);
+        }
+    }
+
+    // NOBITS sections have no bytes in the file to overwrite, whatever their
+    // offset and size say
+    #[test]
+    fn replaces_a_nobits_section_by_appending_it() {
+        for (is_64, little_endian) in CLASSES {
+            let existing = Existing::Nobits {
+                offset: 0x7FFF_0000,
+                size: 0x1000,
+            };
+            let binary = fixture(is_64, little_endian, existing);
+            let out = write(&binary, SOURCE_LINK, b"{}").unwrap();
+            let (data, offset) = read_back(&out, SOURCE_LINK);
+            assert_eq!(data, b"{}", "64 bit: {}", is_64);
+            assert!(offset as usize >= binary.len(), "64 bit: {}", is_64);
+        }
+    }
+
+    fn get(data: &[u8], at: usize, width: usize, little_endian: bool) -> u64 {
+        let mut bytes = [0; 8];
+        if little_endian {
+            bytes[..width].copy_from_slice(&data[at..at + width]);
+            u64::from_le_bytes(bytes)
+        } else {
+            bytes[8 - width..].copy_from_slice(&data[at..at + width]);
+            u64::from_be_bytes(bytes)
+        }
+    }
+
+    #[test]
+    fn compressed_sections_round_trip() {
+        let json = br#"{"documents":{"/src/*":"https://example.com/*"}}"#.repeat(20);
+        for (compression, ch_type) in [
+            (Compression::Zlib, ELFCOMPRESS_ZLIB),
+            (Compression::Zstd, ELFCOMPRESS_ZSTD),
+        ] {
+            for (is_64, little_endian) in CLASSES {
+                let case = format!("{:?}, 64 bit: {}", compression, is_64);
+                let binary = fixture(is_64, little_endian, Existing::None);
+                let out = write_compressed(&binary, SOURCE_LINK, &json, compression).unwrap();
+
+                let file = object::File::parse(&*out).unwrap();
+                let section = file.section_by_name(SOURCE_LINK).unwrap();
+                let sh_flags = match section.flags() {
+                    object::SectionFlags::Elf { sh_flags } => sh_flags,
+                    _ => unreachable!(),
+                };
+                assert_ne!(sh_flags & SHF_COMPRESSED, 0, "{}", case);
+                // Elf64_Chdr is 24 bytes with a reserved word after ch_type,
+                // Elf32_Chdr 12, and either is aligned like the class' words
+                let align = if is_64 { 8 } else { 4 };
+                assert_eq!(section.align(), align, "{}", case);
+                let (offset, _) = section.file_range().unwrap();
+                assert_eq!(offset % align, 0, "{}", case);
+                let chdr = section.data().unwrap();
+                let word = align as usize;
+                assert_eq!(
+                    get(chdr, 0, 4, little_endian),
+                    u64::from(ch_type),
+                    "{}",
+                    case
+                );
+                if is_64 {
+                    assert_eq!(get(chdr, 4, 4, little_endian), 0, "{}", case);
+                }
+                assert_eq!(
+                    get(chdr, word, word, little_endian),
+                    json.len() as u64,
+                    "{}",
+                    case
+                );
+                assert_eq!(get(chdr, 2 * word, word, little_endian), 1, "{}", case);
+                assert!(chdr.len() < json.len(), "{}", case);
+
+                let (data, found) = crate::elf::find_section(&out, SOURCE_LINK).unwrap();
+                assert_eq!(data, &json[..], "{}", case);
+                assert_eq!(found, compression, "{}", case);
+            }
+        }
+    }
+
+    // Writing a section uncompressed over a compressed one must clear
+    // SHF_COMPRESSED, or readers would take the JSON for a compression header
+    #[test]
+    fn uncompressed_over_compressed() {
+        for (is_64, little_endian) in CLASSES {
+            let binary = fixture(is_64, little_endian, Existing::None);
+            let json = b"{\"documents\":{}}";
+            let compressed =
+                write_compressed(&binary, SOURCE_LINK, json, Compression::Zlib).unwrap();
+            let out = write(&compressed, SOURCE_LINK, json).unwrap();
+            let (data, found) = crate::elf::find_section(&out, SOURCE_LINK).unwrap();
+            assert_eq!(data, &json[..], "64 bit: {}", is_64);
+            assert_eq!(found, Compression::None, "64 bit: {}", is_64);
+        }
+    }
+}
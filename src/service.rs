@@ -0,0 +1,245 @@
+use log::{info, warn};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::cleanup;
+use crate::metrics;
+use crate::summary::Summary;
+use crate::webhook::Webhook;
+use crate::{binary_mapping, embed_mapping, is_possible_symbol_file, Options, Outcome};
+
+type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+// Where submitted paths may be and how large a request body may be
+pub struct Limits {
+    // Canonical directories that paths submitted as JSON must be under. With
+    // none only uploads are accepted.
+    pub roots: Vec<PathBuf>,
+    pub max_body: u64,
+}
+
+// A binary is submitted either as a JSON body of {"path": "..."} naming a file
+// the service can read, or as the raw bytes of the binary.
+enum Submission {
+    Path(PathBuf),
+    Upload(cleanup::Temporary),
+}
+
+impl Submission {
+    fn path(&self) -> &Path {
+        match self {
+            Submission::Path(path) => path,
+            Submission::Upload(file) => file.path(),
+        }
+    }
+}
+
+// Serve the API on addr until the process is killed.
+//
+//   POST /mapping  compute the mapping of a submitted binary
+//   POST /embed    compute and embed the mapping. Uploaded binaries are
+//                  returned with the section added.
+//   GET  /index    every binary submitted by path, or only ?binary=PATH
+//   GET  /metrics  Prometheus metrics
+pub fn run(
+    addr: &str,
+    limits: &Limits,
+    options: &Options,
+    webhook: Option<&Webhook>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let server = Server::http(addr)?;
+    info!("Listening on {}", addr);
+
+    let mut index = BTreeMap::<PathBuf, serde_json::Value>::new();
+    for mut request in server.incoming_requests() {
+        let mut summary = Summary::default();
+        let response = handle(&mut request, limits, options, &mut index, &mut summary);
+        if let Some(webhook) = webhook {
+            if summary.processed() > 0 {
+                webhook.post(&summary);
+            }
+        }
+        if let Err(e) = request.respond(response) {
+            warn!("Failed to send response: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle(
+    request: &mut Request,
+    limits: &Limits,
+    options: &Options,
+    index: &mut BTreeMap<PathBuf, serde_json::Value>,
+    summary: &mut Summary,
+) -> HttpResponse {
+    let url = request.url().to_owned();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    info!("{} {}", request.method(), url);
+
+    match (request.method(), path) {
+        (Method::Post, "/mapping") => match submission(request, limits) {
+            Ok(submission) => {
+                let result = result_json(submission.path(), options, false, summary);
+                if let Submission::Path(path) = &submission {
+                    index.insert(path.clone(), result.clone());
+                }
+                json_response(200, &result)
+            }
+            Err(response) => response,
+        },
+        (Method::Post, "/embed") => match submission(request, limits) {
+            Ok(Submission::Path(path)) => {
+                let result = result_json(&path, options, true, summary);
+                index.insert(path, result.clone());
+                json_response(200, &result)
+            }
+            Ok(Submission::Upload(file)) => {
+                // Only the in-place sink writes the result back to the upload
+                let result = result_json(file.path(), options, true, summary);
+                let outcome = &result["outcome"];
+                if outcome != Outcome::Updated.as_str() && outcome != Outcome::Unchanged.as_str() {
+                    return json_response(422, &result);
+                }
+                match std::fs::read(file.path()) {
+                    Ok(bytes) => Response::from_data(bytes)
+                        .with_header(header("Content-Type", "application/octet-stream")),
+                    Err(e) => error_response(500, &e.to_string()),
+                }
+            }
+            Err(response) => response,
+        },
+        (Method::Get, "/metrics") => Response::from_data(metrics::render())
+            .with_header(header("Content-Type", "text/plain; version=0.0.4")),
+        (Method::Get, "/index") => {
+            let binary = url::form_urlencoded::parse(query.as_bytes())
+                .find(|(key, _)| key == "binary")
+                .map(|(_, value)| {
+                    // Submitted paths are indexed canonicalized
+                    let binary = PathBuf::from(value.into_owned());
+                    std::fs::canonicalize(&binary).unwrap_or(binary)
+                });
+            match binary {
+                Some(binary) => match index.get(&binary) {
+                    Some(result) => json_response(200, result),
+                    None => error_response(404, "Binary has not been submitted"),
+                },
+                None => json_response(200, &serde_json::json!(index.values().collect::<Vec<_>>())),
+            }
+        }
+        (_, "/mapping") | (_, "/embed") | (_, "/index") | (_, "/metrics") => {
+            error_response(405, "Method not allowed")
+        }
+        _ => error_response(404, "Not found"),
+    }
+}
+
+fn submission(request: &mut Request, limits: &Limits) -> Result<Submission, HttpResponse> {
+    if request
+        .body_length()
+        .is_some_and(|length| length as u64 > limits.max_body)
+    {
+        return Err(too_large(limits));
+    }
+    let is_json = request.headers().iter().any(|header| {
+        header.field.equiv("Content-Type") && header.value.as_str().starts_with("application/json")
+    });
+
+    // Chunked bodies don't say how long they are up front
+    let mut body = request.as_reader().take(limits.max_body + 1);
+
+    let submission = if is_json {
+        let mut json = String::new();
+        if let Err(e) = body.read_to_string(&mut json) {
+            return Err(error_response(400, &e.to_string()));
+        }
+        if json.len() as u64 > limits.max_body {
+            return Err(too_large(limits));
+        }
+        let json = serde_json::from_str::<serde_json::Value>(&json)
+            .map_err(|e| error_response(400, &e.to_string()))?;
+        let path = match json["path"].as_str() {
+            Some(path) => path,
+            None => return Err(error_response(400, "Expected {\"path\": \"...\"}")),
+        };
+        // Links and .. could otherwise lead out of the roots
+        let path = std::fs::canonicalize(path)
+            .map_err(|_| error_response(404, "Binary does not exist"))?;
+        if !limits.roots.iter().any(|root| path.starts_with(root)) {
+            return Err(error_response(403, "Binary is not under any --root"));
+        }
+        Submission::Path(path)
+    } else {
+        // Removed by the tracked copy of its path from here on
+        let (mut file, path) = tempfile::NamedTempFile::new()
+            .and_then(|file| file.keep().map_err(|e| e.error))
+            .map_err(|e| error_response(500, &e.to_string()))?;
+        let temporary = cleanup::track(&path);
+        let copied =
+            std::io::copy(&mut body, &mut file).map_err(|e| error_response(500, &e.to_string()))?;
+        if copied > limits.max_body {
+            return Err(too_large(limits));
+        }
+        Submission::Upload(temporary)
+    };
+
+    if !submission.path().is_file() {
+        return Err(error_response(404, "Binary does not exist"));
+    }
+    if !is_possible_symbol_file(submission.path()) {
+        return Err(error_response(422, "Not a binary with debug info"));
+    }
+    Ok(submission)
+}
+
+fn result_json(
+    binary: &Path,
+    options: &Options,
+    embed: bool,
+    summary: &mut Summary,
+) -> serde_json::Value {
+    let start = Instant::now();
+    let (outcome, documents) = match binary_mapping(binary, options) {
+        Ok(mapping) => {
+            let outcome = if embed {
+                embed_mapping(binary, &mapping, options)
+            } else {
+                Outcome::DryRun
+            };
+            (outcome, serde_json::Value::Object(mapping.documents()))
+        }
+        Err(outcome) => (outcome, serde_json::Value::Null),
+    };
+    summary.record(binary, outcome);
+    metrics::processed(outcome, start.elapsed());
+    serde_json::json!({
+        "binary": binary,
+        "outcome": outcome.as_str(),
+        "documents": documents,
+    })
+}
+
+fn too_large(limits: &Limits) -> HttpResponse {
+    error_response(
+        413,
+        &format!("Request body is larger than {} bytes", limits.max_body),
+    )
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn json_response(status: u16, json: &serde_json::Value) -> HttpResponse {
+    Response::from_data(json.to_string())
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn error_response(status: u16, message: &str) -> HttpResponse {
+    json_response(status, &serde_json::json!({ "error": message }))
+}
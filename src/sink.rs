@@ -0,0 +1,344 @@
+use path_slash::PathExt;
+
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{cleanup, providers};
+
+// Where rewritten binaries go. Packaging systems can take them from a
+// directory, a tar stream or an artifact repository instead of rereading the
+// scanned tree.
+pub trait Sink: Send + Sync {
+    // Take binary, the rewrite of original, with the mode given by --chmod
+    // instead of the original's if set
+    fn put(&self, original: &Path, binary: &[u8], mode: Option<u32>) -> Result<()>;
+
+    // Take data, a file that goes with the rewrite of original, named like it
+    // with suffix appended
+    fn put_beside(&self, original: &Path, suffix: &str, data: &[u8]) -> Result<()>;
+
+    // Called after the last binary of a scan
+    fn finish(&self) -> Result<()> {
+        Ok(())
+    }
+
+    // Whether binaries are put where they were read from, so one that
+    // wouldn't change needn't be put at all
+    fn is_in_place(&self) -> bool {
+        false
+    }
+}
+
+// What --sink names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Spec {
+    InPlace,
+    Dir(PathBuf),
+    // - is stdout
+    Tar(PathBuf),
+    // URL with a wildcard for the path below the scanned directory
+    Put(String),
+}
+
+impl std::str::FromStr for Spec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once(':') {
+            _ if s == "in-place" => Ok(Spec::InPlace),
+            Some(("dir", path)) if !path.is_empty() => Ok(Spec::Dir(PathBuf::from(path))),
+            Some(("tar", path)) if !path.is_empty() => Ok(Spec::Tar(PathBuf::from(path))),
+            Some(("put", url)) if url.contains('*') => Ok(Spec::Put(url.to_owned())),
+            Some(("put", url)) if !url.is_empty() => {
+                Ok(Spec::Put(format!("{}/*", url.trim_end_matches('/'))))
+            }
+            _ => Err(format!(
+                "Unknown sink \"{}\", expected in-place, dir:PATH, tar:PATH or put:URL",
+                s
+            )),
+        }
+    }
+}
+
+impl Spec {
+    // Whether binaries are written to stdout, which nothing else may then be
+    pub fn is_stdout(&self) -> bool {
+        matches!(self, Spec::Tar(path) if path == Path::new("-"))
+    }
+}
+
+// The sink spec names, for the binaries of a scan of root
+pub fn open(spec: &Spec, root: &Path) -> Result<Box<dyn Sink>> {
+    let root = root.to_owned();
+    Ok(match spec {
+        Spec::InPlace => Box::new(InPlace),
+        Spec::Dir(dir) => Box::new(Dir {
+            root,
+            dir: dir.clone(),
+        }),
+        Spec::Tar(path) => {
+            let out: Box<dyn Write + Send> = if spec.is_stdout() {
+                Box::new(std::io::stdout())
+            } else {
+                Box::new(
+                    File::create(path)
+                        .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?,
+                )
+            };
+            Box::new(Tar {
+                root,
+                out: Mutex::new(BufWriter::new(out)),
+            })
+        }
+        Spec::Put(url) => Box::new(Put {
+            root,
+            url: url.clone(),
+            agent: ureq::Agent::config_builder()
+                .timeout_global(Some(Duration::from_secs(300)))
+                .build()
+                .into(),
+        }),
+    })
+}
+
+// Where original is below root. Binaries outside of it, like those the daemon
+// is told about, keep their whole path.
+pub fn rel_path(root: &Path, original: &Path) -> PathBuf {
+    let rel_path = original
+        .strip_prefix(root)
+        .unwrap_or(original)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect::<PathBuf>();
+    // A single binary was scanned
+    match (rel_path.as_os_str().is_empty(), original.file_name()) {
+        (true, Some(name)) => PathBuf::from(name),
+        _ => rel_path,
+    }
+}
+
+// Write binary to path through a temporary file next to it, so readers never
+// see a partly written binary. Renaming is only atomic within a file system,
+// so the temporary file goes next to the file a link points to rather than
+// the link, which is left as it is.
+pub fn replace(path: &Path, original: &Path, binary: &[u8], mode: Option<u32>) -> Result<()> {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut output = tempfile::NamedTempFile::new_in(dir)?;
+    let temporary = cleanup::track(output.path());
+    output.write_all(binary)?;
+    if let Err(e) = crate::copy_metadata(original, output.path(), mode) {
+        log::warn!("Failed to set the mode of {}: {}", path.display(), e);
+    }
+    match temporary.persist(|_| output.persist(&path).map(drop).map_err(|e| e.error)) {
+        Ok(()) => Ok(()),
+        // A file mounted on its own, like one bind mounted into a container,
+        // can't be renamed over. Overwriting it is all that's left.
+        Err(e)
+            if matches!(
+                e.kind(),
+                ErrorKind::CrossesDevices | ErrorKind::ResourceBusy
+            ) =>
+        {
+            log::warn!(
+                "{} is a mount point, so it is overwritten rather than replaced atomically",
+                path.display()
+            );
+            std::fs::write(&path, binary)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// path with suffix appended to its name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+// Replace the scanned binaries
+pub struct InPlace;
+
+impl Sink for InPlace {
+    fn put(&self, original: &Path, binary: &[u8], mode: Option<u32>) -> Result<()> {
+        replace(original, original, binary, mode)
+    }
+
+    fn put_beside(&self, original: &Path, suffix: &str, data: &[u8]) -> Result<()> {
+        cleanup::write(&with_suffix(original, suffix), data)
+    }
+
+    fn is_in_place(&self) -> bool {
+        true
+    }
+}
+
+// Write to the same path below another directory, leaving the scanned tree as
+// it is
+struct Dir {
+    root: PathBuf,
+    dir: PathBuf,
+}
+
+impl Sink for Dir {
+    fn put(&self, original: &Path, binary: &[u8], mode: Option<u32>) -> Result<()> {
+        let path = self.dir.join(rel_path(&self.root, original));
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        replace(&path, original, binary, mode)
+    }
+
+    fn put_beside(&self, original: &Path, suffix: &str, data: &[u8]) -> Result<()> {
+        let path = self.dir.join(rel_path(&self.root, original));
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        cleanup::write(&with_suffix(&path, suffix), data)
+    }
+}
+
+// Stream a ustar archive of the binaries
+struct Tar {
+    root: PathBuf,
+    out: Mutex<BufWriter<Box<dyn Write + Send>>>,
+}
+
+impl Tar {
+    fn append(&self, name: &str, data: &[u8], mode: u32) -> Result<()> {
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let header = tar_header(name, data.len() as u64, mode, mtime)?;
+
+        let mut out = self.out.lock().unwrap();
+        out.write_all(&header)?;
+        out.write_all(data)?;
+        out.write_all(&[0; 512][..data.len().next_multiple_of(512) - data.len()])
+    }
+}
+
+impl Sink for Tar {
+    fn put(&self, original: &Path, binary: &[u8], mode: Option<u32>) -> Result<()> {
+        let name = rel_path(&self.root, original).to_slash_lossy().into_owned();
+        let mode = match mode {
+            Some(mode) => mode,
+            None => file_mode(&std::fs::metadata(original)?),
+        };
+        self.append(&name, binary, mode)
+    }
+
+    fn put_beside(&self, original: &Path, suffix: &str, data: &[u8]) -> Result<()> {
+        let name = rel_path(&self.root, original).to_slash_lossy().into_owned();
+        self.append(&format!("{}{}", name, suffix), data, 0o644)
+    }
+
+    // Two empty blocks end the archive
+    fn finish(&self) -> Result<()> {
+        let mut out = self.out.lock().unwrap();
+        out.write_all(&[0; 1024])?;
+        out.flush()
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o555
+    } else {
+        0o755
+    }
+}
+
+// The header of a regular file in a ustar archive. Names longer than 100
+// bytes are split at a / into the prefix field.
+fn tar_header(name: &str, size: u64, mode: u32, mtime: u64) -> Result<[u8; 512]> {
+    let too_long = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is too long for a tar entry", name),
+        )
+    };
+    let (prefix, name) = if name.len() <= 100 {
+        ("", name)
+    } else {
+        let split = name
+            .match_indices('/')
+            .map(|(at, _)| at)
+            .find(|&at| at <= 155 && name.len() - at - 1 <= 100)
+            .ok_or_else(too_long)?;
+        (&name[..split], &name[split + 1..])
+    };
+
+    let mut header = [0; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut header[100..108], mode.into())?;
+    octal(&mut header[108..116], 0)?;
+    octal(&mut header[116..124], 0)?;
+    octal(&mut header[124..136], size)?;
+    octal(&mut header[136..148], mtime)?;
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    // Summed with the checksum field itself as spaces
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|&b| u32::from(b)).sum::<u32>();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    Ok(header)
+}
+
+// value as zero padded octal digits and a NUL filling field
+fn octal(field: &mut [u8], value: u64) -> Result<()> {
+    let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+    if digits.len() > field.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Too large for a tar entry",
+        ));
+    }
+    field.copy_from_slice(digits.as_bytes());
+    Ok(())
+}
+
+// PUT each binary to an artifact repository
+struct Put {
+    root: PathBuf,
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl Sink for Put {
+    fn put(&self, original: &Path, binary: &[u8], _mode: Option<u32>) -> Result<()> {
+        let rel_path = rel_path(&self.root, original).to_slash_lossy().into_owned();
+        self.send(&rel_path, binary, "application/octet-stream")
+    }
+
+    fn put_beside(&self, original: &Path, suffix: &str, data: &[u8]) -> Result<()> {
+        let rel_path = rel_path(&self.root, original).to_slash_lossy().into_owned();
+        self.send(&format!("{}{}", rel_path, suffix), data, "application/json")
+    }
+}
+
+impl Put {
+    fn send(&self, rel_path: &str, data: &[u8], content_type: &str) -> Result<()> {
+        let url = providers::substitute(&self.url, rel_path, false)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        self.agent
+            .put(&url)
+            .header("User-Agent", crate::APP_NAME)
+            .header("Content-Type", content_type)
+            .send(data)
+            .map_err(|e| Error::other(format!("PUT {} failed: {}", url, e)))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use std::path::{Path, PathBuf};
+
+// A submodule whose files are on disk without a repo of their own, as when a
+// source tree is copied without its .git files. It is mapped from what the
+// superproject records for it.
+#[derive(Debug)]
+pub struct Pinned {
+    pub workdir: PathBuf,
+    pub remote: String,
+    // The commit the superproject's HEAD pins the submodule at
+    pub commit: git2::Oid,
+}
+
+// The submodule of repo that rel_path is in
+pub fn find<'r>(repo: &'r git2::Repository, rel_path: &Path) -> Option<git2::Submodule<'r>> {
+    repo.submodules()
+        .ok()?
+        .into_iter()
+        .find(|submodule| rel_path.starts_with(submodule.path()))
+}
+
+// Like git, resolve a submodule URL starting with ./ or ../ against the
+// superproject's remote as if that were a directory
+pub fn resolve_url(superproject_remote: &str, url: &str) -> Option<String> {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return Some(url.to_owned());
+    }
+    let mut base = crate::providers::parse_remote(superproject_remote).ok()?;
+    let dir = format!("{}/", base.path().trim_end_matches('/'));
+    base.set_path(&dir);
+    base.join(url).ok().map(String::from)
+}
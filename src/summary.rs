@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::mapping::Origin;
+use crate::Outcome;
+
+// Counts of what happened to a set of binaries
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub skipped: usize,
+    pub unmapped: usize,
+    pub dryrun: usize,
+    pub updated: usize,
+    // Mapped, but their section already held the mapping
+    pub unchanged: usize,
+    pub failed: Vec<PathBuf>,
+    pub unmapped_binaries: Vec<PathBuf>,
+    // How many mapped binaries link to each repo and revision
+    pub origins: BTreeMap<Origin, usize>,
+}
+
+impl Summary {
+    pub fn record(&mut self, binary: &Path, outcome: Outcome) {
+        match outcome {
+            Outcome::Skipped => self.skipped += 1,
+            Outcome::Unmapped => {
+                self.unmapped += 1;
+                self.unmapped_binaries.push(binary.to_owned());
+            }
+            Outcome::DryRun => self.dryrun += 1,
+            Outcome::Updated => self.updated += 1,
+            Outcome::Unchanged => self.unchanged += 1,
+            Outcome::Failed => self.failed.push(binary.to_owned()),
+        }
+    }
+
+    pub fn processed(&self) -> usize {
+        self.skipped
+            + self.unmapped
+            + self.dryrun
+            + self.updated
+            + self.unchanged
+            + self.failed.len()
+    }
+
+    // Fraction of binaries with debug info that got a mapping. Skipped
+    // binaries had nothing to map so they don't count against coverage.
+    pub fn coverage(&self) -> f64 {
+        let candidates = self.processed() - self.skipped;
+        if candidates == 0 {
+            1.0
+        } else {
+            (self.dryrun + self.updated + self.unchanged) as f64 / candidates as f64
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "processed": self.processed(),
+            "skipped": self.skipped,
+            "unmapped": self.unmapped,
+            "dryrun": self.dryrun,
+            "updated": self.updated,
+            "unchanged": self.unchanged,
+            "failed": self.failed.len(),
+            "failures": self.failed,
+            "coverage": self.coverage(),
+            "repos": self
+                .origins
+                .iter()
+                .map(|(origin, binaries)| {
+                    let mut json = origin.to_json();
+                    json["binaries"] = (*binaries).into();
+                    json
+                })
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Processed {} binaries: {} updated, {} unchanged, {} dry run, {} unmapped, {} failed, {} skipped ({:.0}% coverage)",
+            self.processed(),
+            self.updated,
+            self.unchanged,
+            self.dryrun,
+            self.unmapped,
+            self.failed.len(),
+            self.skipped,
+            self.coverage() * 100.0
+        )?;
+        for binary in &self.failed {
+            write!(f, "\nFailed: {}", binary.display())?;
+        }
+        // Grouped by repo, so a revision that differs from the rest stands out
+        let mut repo = None;
+        for (origin, binaries) in &self.origins {
+            if repo != Some(&origin.repo) {
+                write!(f, "\n{}", origin.repo)?;
+                repo = Some(&origin.repo);
+            }
+            write!(
+                f,
+                "\n    {}{} {} {}",
+                origin.revision,
+                if origin.dirty == Some(true) {
+                    " (dirty)"
+                } else {
+                    ""
+                },
+                binaries,
+                if *binaries == 1 { "binary" } else { "binaries" }
+            )?;
+        }
+        Ok(())
+    }
+}
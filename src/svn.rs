@@ -0,0 +1,163 @@
+use log::warn;
+
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::diagnostics::Code;
+use crate::vcs::{Checkout, Link, LinkContext, VcsBackend};
+use crate::{mapping, providers};
+
+// A Subversion working copy, as svn info describes its root
+#[derive(Debug, Clone)]
+pub struct WorkingCopy {
+    pub root: PathBuf,
+    // Where the root is in the repository
+    pub url: String,
+    pub repos_root_url: String,
+    pub revision: u64,
+}
+
+impl WorkingCopy {
+    // Apache's mod_dav_svn serves every revision of the repository below
+    // !svn/bc/REV, so the files stay as they were built after later commits
+    pub fn raw_url(&self) -> Option<String> {
+        let root = self.repos_root_url.trim_end_matches('/');
+        let path = self.url.strip_prefix(root)?.trim_matches('/');
+        Some(match path {
+            "" => format!("{}/!svn/bc/{}/*", root, self.revision),
+            path => format!("{}/!svn/bc/{}/{}/*", root, self.revision, path),
+        })
+    }
+}
+
+// Working copies are read with the svn command line client, which knows every
+// version of the metadata format
+#[derive(Default)]
+pub struct Svn {
+    // The working copies found so far, so svn info runs once for each
+    working_copies: RefCell<Vec<WorkingCopy>>,
+}
+
+impl VcsBackend for Svn {
+    // Since Subversion 1.7 only the root of a working copy has a .svn
+    // directory
+    fn discover(&self, path: &Path) -> Option<Box<dyn Checkout>> {
+        let mut working_copies = self.working_copies.borrow_mut();
+        if let Some(working_copy) = working_copies
+            .iter()
+            .find(|working_copy| path.starts_with(&working_copy.root))
+        {
+            return Some(Box::new(working_copy.clone()));
+        }
+        let root = path.ancestors().find(|dir| dir.join(".svn").is_dir())?;
+        match info(root) {
+            Ok(working_copy) => {
+                working_copies.push(working_copy.clone());
+                Some(Box::new(working_copy))
+            }
+            Err(e) => {
+                warn!(
+                    "Unable to read Subversion working copy {}: {}",
+                    root.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+impl Checkout for WorkingCopy {
+    fn workdir(&self) -> &Path {
+        &self.root
+    }
+
+    fn kind(&self) -> &'static str {
+        "Subversion working copy"
+    }
+
+    fn head_name(&self) -> String {
+        format!("revision {}", self.revision)
+    }
+
+    fn is_tracked(&self, rel_path: &Path) -> Result<bool, String> {
+        let status = status(self, rel_path)?;
+        Ok(!status.starts_with(['?', 'I']))
+    }
+
+    fn is_clean(&self, rel_path: &Path) -> bool {
+        status(self, rel_path).map_or(true, |status| status.is_empty())
+    }
+
+    // svn:// and file:// repositories have no URL to fetch files from
+    fn link(&self, _: &LinkContext) -> Option<Link> {
+        let url = self
+            .raw_url()
+            .and_then(|url| url::Url::parse(&url).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https"));
+        match url {
+            Some(mut url) => {
+                providers::strip_credentials(&mut url);
+                let origin = mapping::Origin::new(
+                    url.as_str().split("/!svn/").next().unwrap().to_owned(),
+                    format!("r{}", self.revision),
+                );
+                Some(Link { url, origin })
+            }
+            None => {
+                diag!(
+                    Code::InvalidRemoteUrl,
+                    "Skipping working copy {}. {} is not served over HTTP",
+                    self.root.display(),
+                    self.url
+                );
+                None
+            }
+        }
+    }
+}
+
+fn info(root: &Path) -> Result<WorkingCopy, String> {
+    let item = |name: &str| svn(root, ["info", "--show-item", name, "."]);
+    let revision = item("revision")?;
+    Ok(WorkingCopy {
+        root: root.to_owned(),
+        url: item("url")?,
+        repos_root_url: item("repos-root-url")?,
+        revision: revision
+            .parse()
+            .map_err(|_| format!("Invalid revision \"{}\"", revision))?,
+    })
+}
+
+// The svn status line of the file, empty when it is unmodified
+fn status(working_copy: &WorkingCopy, rel_path: &Path) -> Result<String, String> {
+    svn(
+        &working_copy.root,
+        [
+            OsStr::new("status"),
+            OsStr::new("--depth"),
+            OsStr::new("empty"),
+            rel_path.as_os_str(),
+        ],
+    )
+}
+
+fn svn<I, S>(dir: &Path, args: I) -> Result<String, String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = Command::new("svn")
+        .arg("--non-interactive")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("Unable to run svn: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
@@ -0,0 +1,85 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Bytes per second, like 50M, with K, M and G meaning powers of 1024
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate(pub u64);
+
+impl std::str::FromStr for Rate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, scale) = match s.char_indices().last() {
+            Some((at, 'K')) | Some((at, 'k')) => (&s[..at], 1 << 10),
+            Some((at, 'M')) | Some((at, 'm')) => (&s[..at], 1 << 20),
+            Some((at, 'G')) | Some((at, 'g')) => (&s[..at], 1 << 30),
+            _ => (s, 1),
+        };
+        number
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| number.checked_mul(scale))
+            .filter(|rate| *rate > 0)
+            .map(Rate)
+            .ok_or_else(|| format!("Invalid rate \"{}\", expected bytes per second like 50M", s))
+    }
+}
+
+// Bytes read and written since start. Sleeping until they would have taken
+// the limit's time keeps the average under it without splitting up the reads
+// done by the debug info parser.
+struct Budget {
+    rate: u64,
+    start: Instant,
+    bytes: u64,
+}
+
+static BUDGET: Mutex<Option<Budget>> = Mutex::new(None);
+
+pub fn limit(rate: Rate) {
+    *BUDGET.lock().unwrap() = Some(Budget {
+        rate: rate.0,
+        start: Instant::now(),
+        bytes: 0,
+    });
+}
+
+// Wait until bytes more of I/O fit in the limit, if there is one
+pub fn io(bytes: u64) {
+    let wait = {
+        let mut budget = BUDGET.lock().unwrap();
+        let budget = match budget.as_mut() {
+            Some(budget) => budget,
+            None => return,
+        };
+        let due = Duration::from_secs_f64(budget.bytes as f64 / budget.rate as f64);
+        budget.bytes += bytes;
+        due.saturating_sub(budget.start.elapsed())
+    };
+    if !wait.is_zero() {
+        std::thread::sleep(wait);
+    }
+}
+
+// Only do I/O when the disk is otherwise idle. Processes started later, like
+// git, inherit the priority.
+#[cfg(target_os = "linux")]
+pub fn lower_priority() -> Result<(), std::io::Error> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    let priority = IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT;
+    // 0 is the calling process
+    match unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, priority) } {
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn lower_priority() -> Result<(), std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "I/O priorities are only supported on Linux",
+    ))
+}
@@ -0,0 +1,67 @@
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// A point in time given on the command line, as seconds since the epoch or
+// RFC 3339: 2024-05-01, 2024-05-01T12:00:00Z or 2024-05-01T12:00:00+02:00.
+// Times without an offset are UTC.
+pub fn parse(s: &str) -> Result<SystemTime, String> {
+    let invalid = || {
+        format!(
+            "Invalid timestamp \"{}\", expected seconds since the epoch or RFC 3339",
+            s
+        )
+    };
+    if let Ok(seconds) = s.parse::<u64>() {
+        return Ok(UNIX_EPOCH + Duration::from_secs(seconds));
+    }
+
+    let (date, time) = s.split_once(['T', 't', ' ']).unwrap_or((s, "00:00:00Z"));
+    let number = |s: &str| s.parse::<i64>().map_err(|_| invalid());
+    let (year, month, day) = match date.split('-').collect::<Vec<_>>().as_slice() {
+        [year, month, day] if year.len() == 4 => (number(year)?, number(month)?, number(day)?),
+        _ => return Err(invalid()),
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(at) => time.split_at(at),
+        None => (time, ""),
+    };
+    // Fractions of a second don't matter for file times this coarse
+    let time = time.split('.').next().unwrap();
+    let (hour, minute, second) = match time.split(':').collect::<Vec<_>>().as_slice() {
+        [hour, minute, second] => (number(hour)?, number(minute)?, number(second)?),
+        [hour, minute] => (number(hour)?, number(minute)?, 0),
+        _ => return Err(invalid()),
+    };
+    let offset = match offset {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let (sign, offset) = offset.split_at(1);
+            let (hours, minutes) = offset.split_once(':').ok_or_else(invalid)?;
+            let offset = number(hours)? * 3600 + number(minutes)? * 60;
+            if sign == "-" {
+                -offset
+            } else {
+                offset
+            }
+        }
+    };
+
+    let seconds =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let seconds = u64::try_from(seconds).map_err(|_| invalid())?;
+    Ok(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+// Days from 1970-01-01 to the date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
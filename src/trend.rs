@@ -0,0 +1,105 @@
+use log::debug;
+use path_slash::PathExt;
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{Result, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::summary::Summary;
+
+// The trend database holds one JSON object per line, one for each run and
+// project, oldest first:
+//
+//   {"time": 1714557600, "project": "out", "processed": 212, "coverage": 0.99,
+//    "uncovered": ["lib/libfoo.so"]}
+//
+// Uncovered binaries are the unmapped and failed ones, relative to the
+// scanned directory so runs in different CI workspaces compare.
+pub fn append(path: &Path, project: &str, root: &Path, summary: &Summary) -> Result<()> {
+    let relative = |binary: &PathBuf| {
+        let binary = binary.strip_prefix(root).unwrap_or(binary);
+        binary.to_slash_lossy().into_owned()
+    };
+    let mut uncovered = summary
+        .unmapped_binaries
+        .iter()
+        .chain(&summary.failed)
+        .map(relative)
+        .collect::<Vec<_>>();
+    uncovered.sort();
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let record = serde_json::json!({
+        "time": time,
+        "project": project,
+        "processed": summary.processed(),
+        "coverage": summary.coverage(),
+        "uncovered": uncovered,
+    });
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", record)?;
+    debug!("Recorded coverage of {} in {}", project, path.display());
+    Ok(())
+}
+
+// Compare the latest run of each project in the database at path with the one
+// before. Returns the number of projects whose coverage went down or that have
+// binaries that weren't uncovered before.
+pub fn report(path: &Path) -> Result<usize> {
+    let text = std::fs::read_to_string(path)?;
+    let mut runs = BTreeMap::<String, Vec<serde_json::Value>>::new();
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let record = serde_json::from_str::<serde_json::Value>(line)?;
+        let project = record["project"].as_str().unwrap_or_default().to_owned();
+        runs.entry(project).or_default().push(record);
+    }
+
+    let mut regressions = 0;
+    for (project, runs) in &runs {
+        let latest = &runs[runs.len() - 1];
+        let coverage = latest["coverage"].as_f64().unwrap_or_default();
+        let previous = match runs.len() {
+            1 => {
+                println!("{}: {:.1}% coverage, first run", project, coverage * 100.0);
+                continue;
+            }
+            len => &runs[len - 2],
+        };
+        let before = previous["coverage"].as_f64().unwrap_or_default();
+        let was_uncovered = previous["uncovered"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let newly_uncovered = latest["uncovered"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|binary| !was_uncovered.contains(binary))
+            .filter_map(|binary| binary.as_str())
+            .collect::<Vec<_>>();
+
+        // Coverage is only printed to a tenth of a percent
+        let dropped = coverage < before - 0.0005;
+        let status = if dropped || !newly_uncovered.is_empty() {
+            regressions += 1;
+            "REGRESSED"
+        } else {
+            "ok"
+        };
+        println!(
+            "{}: {:.1}% coverage, was {:.1}%, {}",
+            project,
+            coverage * 100.0,
+            before * 100.0,
+            status
+        );
+        for binary in newly_uncovered {
+            println!("    not mapped: {}", binary);
+        }
+    }
+    Ok(regressions)
+}
@@ -0,0 +1,665 @@
+use log::{debug, error, trace, warn};
+use path_slash::PathExt;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::diagnostics::{self, Code};
+use crate::{checksum, commit_override, forge, mapping, providers, submodule, RefStrategy};
+
+// What the mapping engine needs from a version control system. Mercurial
+// support, and fakes for tests, are other implementations of it.
+pub trait VcsBackend {
+    // The checkout path is in, None when it isn't in one
+    fn discover(&self, path: &Path) -> Option<Box<dyn Checkout>>;
+}
+
+// A checkout a backend discovered, which the engine maps the source files in
+pub trait Checkout {
+    // The directory the checkout's files are in
+    fn workdir(&self) -> &Path;
+
+    // What the checkout is, for messages, like "git repo"
+    fn kind(&self) -> &'static str;
+
+    // The revision checked out, for messages, like "HEAD"
+    fn head_name(&self) -> String;
+
+    // Whether the checked out revision has the file at rel_path, relative to
+    // the workdir
+    fn is_tracked(&self, rel_path: &Path) -> Result<bool, String>;
+
+    // Whether the file at rel_path is unchanged from the checked out revision
+    fn is_clean(&self, rel_path: &Path) -> bool;
+
+    // What tracks the file at rel_path when the checkout itself doesn't, like
+    // a submodule
+    fn nested(&self, _rel_path: &Path, _remote: Option<&str>) -> Option<Tracked> {
+        None
+    }
+
+    // The checkout this one is in, which may track the files it doesn't
+    fn enclosing(&self, _path: &Path) -> Option<Box<dyn Checkout>> {
+        None
+    }
+
+    // The URL of the files below the workdir, with a wildcard for their path,
+    // and what it links to. None once it was reported why there is none.
+    fn link(&self, context: &LinkContext) -> Option<Link>;
+
+    // The link of dir when it isn't below the workdir's URL, like directories
+    // a Perforce client view maps elsewhere
+    fn link_dir(&self, _dir: &Path) -> Option<Link> {
+        None
+    }
+}
+
+// What tracks a source file
+pub enum Tracked {
+    Checkout(Box<dyn Checkout>),
+    // A submodule with no repo on disk
+    Pinned(submodule::Pinned),
+}
+
+pub struct Link {
+    pub url: url::Url,
+    pub origin: mapping::Origin,
+}
+
+// What a checkout may need to know to link its files
+pub struct LinkContext<'a> {
+    pub(crate) options: &'a crate::Options,
+    pub(crate) source_files: &'a [compiledfiles::FileInfo],
+    // The workdirs of the checkouts inside this one, whose files are theirs
+    pub(crate) nested: &'a [&'a Path],
+}
+
+pub struct Git;
+
+impl Git {
+    // The remote consumers can fetch from: name if given, otherwise upstream
+    // (the canonical repo of a fork), origin or the first remote
+    pub fn find_remote<'r>(
+        &self,
+        repo: &'r git2::Repository,
+        name: Option<&str>,
+    ) -> Result<git2::Remote<'r>, git2::Error> {
+        let not_found = |message: String| {
+            git2::Error::new(git2::ErrorCode::NotFound, git2::ErrorClass::Config, message)
+        };
+        if let Some(name) = name {
+            return repo
+                .find_remote(name)
+                .map_err(|_| not_found(format!("No remote named {}", name)));
+        }
+
+        for name in ["upstream", "origin"] {
+            match repo.find_remote(name) {
+                Ok(remote) => return Ok(remote),
+                Err(e) if e.code() == git2::ErrorCode::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let remotes = repo.remotes()?;
+        match remotes.iter().flatten().next() {
+            Some(name) => repo.find_remote(name),
+            None => Err(not_found("No remotes".to_owned())),
+        }
+    }
+
+    // repo if path is in its checkout. Bare repos have none, and GIT_DIR names
+    // the same repo for every path, even ones outside of it.
+    pub fn checkout_of(&self, repo: git2::Repository, path: &Path) -> Option<git2::Repository> {
+        let workdir = match repo.workdir() {
+            Some(workdir) => workdir,
+            None => {
+                debug!(
+                    "Not indexing {} as {} is a bare repo",
+                    path.display(),
+                    repo.path().display()
+                );
+                return None;
+            }
+        };
+        if !path.starts_with(workdir) {
+            debug!(
+                "Not indexing {} as it is outside of the checkout {}",
+                path.display(),
+                workdir.display()
+            );
+            return None;
+        }
+        if repo.is_worktree() {
+            // Linked worktrees share their remotes but have a HEAD of their own
+            trace!(
+                "{} is a linked worktree with git dir {}",
+                workdir.display(),
+                repo.path().display()
+            );
+        }
+        Some(repo)
+    }
+
+    // The commit the checkout's files are from. jj keeps HEAD of checkouts
+    // colocated with it detached at the parent of its working-copy commit,
+    // but only updates it when a jj command runs, and leaves it unborn while
+    // that parent is the root commit. Ask jj itself when it is installed.
+    pub fn head_commit<'r>(
+        &self,
+        repo: &'r git2::Repository,
+    ) -> Result<git2::Commit<'r>, git2::Error> {
+        let head = repo.head().and_then(|head| head.peel_to_commit());
+        let workdir = match repo.workdir() {
+            Some(workdir) if is_jj_colocated(workdir) => workdir,
+            _ => return head,
+        };
+        match cached_jj_parent(workdir) {
+            Ok(parent) => {
+                let commit = repo.find_commit(parent)?;
+                match &head {
+                    Ok(head) if head.id() != parent => debug!(
+                        "HEAD of {} is {} but jj has its working copy on {}",
+                        workdir.display(),
+                        head.id(),
+                        parent
+                    ),
+                    _ => {}
+                }
+                Ok(commit)
+            }
+            Err(e) => {
+                debug!("Using HEAD of the jj checkout {}. {}", workdir.display(), e);
+                head
+            }
+        }
+    }
+
+    // The repo path is in the checkout of. GIT_DIR, GIT_WORK_TREE and
+    // GIT_CEILING_DIRECTORIES are honored like git does.
+    pub fn open(&self, path: &Path) -> Option<git2::Repository> {
+        let flags = git2::RepositoryOpenFlags::FROM_ENV;
+        match git2::Repository::open_ext(path, flags, std::iter::empty::<&std::ffi::OsStr>()) {
+            Ok(repo) => self.checkout_of(repo, path),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                debug!(
+                    "Not indexing {} as it is not tracked by source control",
+                    path.display()
+                );
+                None
+            }
+            Err(e) => {
+                warn!("Error {} discovering git repo at \"{}\"", e, path.display());
+                None
+            }
+        }
+    }
+
+    // Whether HEAD is a branch or detached as in most CI checkouts. Shallow
+    // clones have the commit even without its history.
+    pub fn head(&self, repo: &git2::Repository) -> Result<git2::Oid, git2::Error> {
+        Ok(self.head_commit(repo)?.id())
+    }
+
+    // The URL of the remote name, or of the one consumers should fetch from
+    pub fn remote_url(
+        &self,
+        repo: &git2::Repository,
+        name: Option<&str>,
+    ) -> Result<String, git2::Error> {
+        let remote = self.find_remote(repo, name)?;
+        match remote.url() {
+            Some(url) => Ok(url.to_owned()),
+            None => Err(git2::Error::from_str("Remote URL is not valid UTF-8")),
+        }
+    }
+}
+
+// jj checkouts colocated with git have their .jj next to .git
+pub fn is_jj_colocated(workdir: &Path) -> bool {
+    workdir
+        .join(".jj")
+        .join("repo")
+        .join("store")
+        .join("git_target")
+        .is_file()
+}
+
+// jj_parent of each colocated checkout, along with when its last operation
+// was recorded. Every jj command that changes the repo records one.
+type JjParent = (Option<SystemTime>, Result<git2::Oid, String>);
+static JJ_PARENTS: Mutex<BTreeMap<PathBuf, JjParent>> = Mutex::new(BTreeMap::new());
+
+fn cached_jj_parent(workdir: &Path) -> Result<git2::Oid, String> {
+    let op_heads = workdir.join(".jj/repo/op_heads/heads");
+    let recorded = std::fs::metadata(op_heads)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let mut parents = JJ_PARENTS.lock().unwrap();
+    match parents.get(workdir) {
+        Some((at, parent)) if recorded.is_some() && *at == recorded => parent.clone(),
+        _ => {
+            let parent = jj_parent(workdir);
+            parents.insert(workdir.to_owned(), (recorded, parent.clone()));
+            parent
+        }
+    }
+}
+
+// The first parent of jj's working-copy commit, which it exports to git. The
+// working copy isn't snapshotted, so this neither records nor changes
+// anything.
+fn jj_parent(workdir: &Path) -> Result<git2::Oid, String> {
+    let output = Command::new("jj")
+        .arg("--repository")
+        .arg(workdir)
+        .args([
+            "--ignore-working-copy",
+            "--no-pager",
+            "--color=never",
+            "log",
+            "--no-graph",
+            "-r",
+            "@-",
+            "-T",
+            "commit_id ++ \"\\n\"",
+        ])
+        .output()
+        .map_err(|e| format!("Unable to run jj: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let parent = stdout.lines().next().unwrap_or_default();
+    match git2::Oid::from_str(parent) {
+        Ok(oid) if !oid.is_zero() => Ok(oid),
+        Ok(_) => Err("The working copy is on the root commit".to_owned()),
+        Err(_) => Err(format!("Invalid commit \"{}\" from jj", parent)),
+    }
+}
+
+impl VcsBackend for Git {
+    fn discover(&self, path: &Path) -> Option<Box<dyn Checkout>> {
+        Some(Box::new(self.open(path)?))
+    }
+}
+
+// Only checkouts are discovered, so repos always have a workdir
+impl Checkout for git2::Repository {
+    fn workdir(&self) -> &Path {
+        git2::Repository::workdir(self).unwrap()
+    }
+
+    fn kind(&self) -> &'static str {
+        "git repo"
+    }
+
+    fn head_name(&self) -> String {
+        "HEAD".to_owned()
+    }
+
+    fn is_tracked(&self, rel_path: &Path) -> Result<bool, String> {
+        let rel_path = PathBuf::from(rel_path.to_slash().unwrap().to_string());
+        let tree = Git
+            .head_commit(self)
+            .and_then(|commit| commit.tree())
+            .map_err(|e| format!("HEAD can not be resolved: {}", e.message()))?;
+        Ok(tree.get_path(&rel_path).is_ok())
+    }
+
+    // Modified or staged files are not, ignored ones are
+    fn is_clean(&self, rel_path: &Path) -> bool {
+        match self.status_file(rel_path) {
+            Ok(status) => (status - git2::Status::IGNORED).is_empty(),
+            Err(e) => {
+                trace!("Unable to get the status of {}: {}", rel_path.display(), e);
+                true
+            }
+        }
+    }
+
+    // A submodule is a single commit in its superproject's tree. It is
+    // mapped as a repo if it has one on disk and otherwise as what the
+    // superproject pins it at.
+    fn nested(&self, rel_path: &Path, remote: Option<&str>) -> Option<Tracked> {
+        let rel_path = PathBuf::from(rel_path.to_slash().unwrap().to_string());
+        let submodule = submodule::find(self, &rel_path)?;
+        if let Ok(repo) = submodule.open() {
+            return Some(Tracked::Checkout(Box::new(repo)));
+        }
+
+        let workdir = Checkout::workdir(self).join(submodule.path());
+        let commit = submodule.head_id()?;
+        let superproject_remote = Git.remote_url(self, remote).ok()?;
+        let url = submodule::resolve_url(&superproject_remote, submodule.url()?)?;
+        let url = match self.config() {
+            Ok(config) => crate::apply_instead_of(&config, &url),
+            Err(_) => url,
+        };
+        debug!(
+            "Submodule {} has no repo on disk, mapping it at commit {}",
+            workdir.display(),
+            commit
+        );
+        Some(Tracked::Pinned(submodule::Pinned {
+            workdir,
+            remote: url,
+            commit,
+        }))
+    }
+
+    fn enclosing(&self, path: &Path) -> Option<Box<dyn Checkout>> {
+        let parent = git2::Repository::discover(Checkout::workdir(self).parent()?).ok()?;
+        Some(Box::new(Git.checkout_of(parent, path)?))
+    }
+
+    fn link(&self, context: &LinkContext) -> Option<Link> {
+        let options = context.options;
+        let workdir = Checkout::workdir(self);
+
+        let remote = match Git.find_remote(self, options.remote.as_deref()) {
+            Ok(remote) => remote,
+            Err(e) => {
+                match e.code() {
+                    git2::ErrorCode::NotFound => {
+                        diag!(
+                            Code::NoOrigin,
+                            "Skipping repo {}. {}",
+                            workdir.display(),
+                            e.message()
+                        );
+                    }
+                    _ => {
+                        error!(
+                            "Skipping repo {}. Unexpected error getting remote {}",
+                            workdir.display(),
+                            e
+                        );
+                    }
+                };
+                return None;
+            }
+        };
+
+        let remote_url_str = match remote.url() {
+            Some(url) => url,
+            None => {
+                diag!(
+                    Code::InvalidRemoteUrl,
+                    "Skiping repo {}. URL is invalid",
+                    workdir.display()
+                );
+                return None;
+            }
+        };
+
+        if is_rewritten(self, &remote, remote_url_str) {
+            debug!(
+                "Remote {} of {} was rewritten by url.<base>.insteadOf",
+                remote.name().unwrap_or_default(),
+                workdir.display()
+            );
+        }
+
+        let mut remote_url = match providers::parse_remote(remote_url_str) {
+            Ok(url) => url,
+            Err(e) => {
+                diag!(
+                    Code::InvalidRemoteUrl,
+                    "Skipping repo {}. Unable to parse url due to: {}",
+                    workdir.display(),
+                    e
+                );
+                return None;
+            }
+        };
+
+        // Remotes like https://token@github.com/org/repo carry credentials,
+        // which must never end up in logs or the embedded JSON
+        if providers::strip_credentials(&mut remote_url) {
+            debug!(
+                "Removed credentials from the remote of {}",
+                workdir.display()
+            );
+        }
+
+        let hash = match commit_override(&options.commits, workdir) {
+            Some(commit) => {
+                debug!(
+                    "Linking {} to {} instead of HEAD",
+                    workdir.display(),
+                    commit
+                );
+                commit
+            }
+            None => match Git.head(self) {
+                Ok(commit) => {
+                    trace!(
+                        "Resolved HEAD of {} to {} ({}{})",
+                        workdir.display(),
+                        commit,
+                        head_state(self),
+                        if self.is_shallow() { ", shallow" } else { "" }
+                    );
+                    commit
+                }
+                Err(e) => {
+                    warn!(
+                        "Skipping repo {}. Unable to resolve HEAD: {}",
+                        workdir.display(),
+                        e.message()
+                    );
+                    return None;
+                }
+            },
+        };
+        // The commit check asks the network and the checksums read every file
+        if diagnostics::abandoned() {
+            return None;
+        }
+        if options.check_commits {
+            let name = remote.name().unwrap_or(remote_url_str);
+            match forge::commit_exists(self, name, &remote_url, &hash, &options.hosts) {
+                Ok(true) => trace!("{} exists on {}", hash, remote_url),
+                Ok(false) => {
+                    diag!(
+                        Code::CommitNotOnRemote,
+                        "Skipping repo {}. Commit {} does not exist on {}",
+                        workdir.display(),
+                        hash,
+                        remote_url
+                    );
+                    return None;
+                }
+                Err(e) if options.require_pushed => {
+                    diag!(
+                        Code::CommitNotOnRemote,
+                        "Skipping repo {}. Unable to check {} exists on {}: {}",
+                        workdir.display(),
+                        hash,
+                        remote_url,
+                        e
+                    );
+                    return None;
+                }
+                Err(e) => warn!("Unable to check {} exists on {}: {}", hash, remote_url, e),
+            }
+        }
+        if diagnostics::abandoned() {
+            return None;
+        }
+        check_checksums(self, hash, context);
+        let rev = revision(self, hash, options.ref_strategy);
+        let origin = origin(self, &remote_url, hash, options.provenance);
+        match providers::generate_url(&remote_url, &rev, &options.hosts) {
+            Some(url) => Some(Link { url, origin }),
+            None => {
+                warn!(
+                    "Skipping repo {}. Unable to generate url",
+                    workdir.display()
+                );
+                None
+            }
+        }
+    }
+}
+
+// The origin of commit of repo, and with provenance how it describes and
+// whether the checkout has changes
+fn origin(
+    repo: &git2::Repository,
+    remote_url: &url::Url,
+    commit: git2::Oid,
+    provenance: bool,
+) -> mapping::Origin {
+    let mut origin = mapping::Origin::new(remote_url.to_string(), commit.to_string());
+    if provenance {
+        origin.describe = describe(repo, commit);
+        let mut status_options = git2::StatusOptions::new();
+        status_options
+            .include_untracked(false)
+            .include_ignored(false);
+        origin.dirty = repo
+            .statuses(Some(&mut status_options))
+            .ok()
+            .map(|statuses| !statuses.is_empty());
+    }
+    origin
+}
+
+// git describe --tags --always of commit
+fn describe(repo: &git2::Repository, commit: git2::Oid) -> Option<String> {
+    let object = repo.find_object(commit, None).ok()?;
+    let description = object
+        .describe(
+            git2::DescribeOptions::new()
+                .describe_tags()
+                .show_commit_oid_as_fallback(true),
+        )
+        .ok()?;
+    description.format(None).ok()
+}
+
+// What to link commit of repo with, falling back to the commit when strategy
+// finds no tag or branch for it
+fn revision(
+    repo: &git2::Repository,
+    commit: git2::Oid,
+    strategy: RefStrategy,
+) -> providers::Revision {
+    let name = match strategy {
+        RefStrategy::Commit => return providers::Revision::Commit(commit),
+        RefStrategy::Tag => exact_tag(repo, commit).map(providers::Revision::Tag),
+        RefStrategy::Branch => upstream_branch(repo, commit).map(providers::Revision::Branch),
+    };
+    name.unwrap_or_else(|| {
+        warn!(
+            "No {} for {} in {}, linking to the commit",
+            if strategy == RefStrategy::Tag {
+                "tag"
+            } else {
+                "branch"
+            },
+            commit,
+            Checkout::workdir(repo).display()
+        );
+        providers::Revision::Commit(commit)
+    })
+}
+
+// The tag git describe --tags --exact-match finds for commit. A tag further
+// back would link to other versions of the files.
+fn exact_tag(repo: &git2::Repository, commit: git2::Oid) -> Option<String> {
+    let object = repo.find_object(commit, None).ok()?;
+    let mut options = git2::DescribeOptions::new();
+    options.describe_tags().max_candidates_tags(0);
+    let describe = object.describe(&options).ok()?;
+    describe
+        .format(Some(git2::DescribeFormatOptions::new().abbreviated_size(0)))
+        .ok()
+}
+
+// The name the remote has for the branch HEAD is on, when that is commit
+fn upstream_branch(repo: &git2::Repository, commit: git2::Oid) -> Option<String> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() || head.target() != Some(commit) {
+        return None;
+    }
+    let key = format!("branch.{}.merge", head.shorthand()?);
+    let merge = repo.config().ok()?.get_string(&key).ok()?;
+    merge.strip_prefix("refs/heads/").map(str::to_owned)
+}
+
+fn head_state(repo: &git2::Repository) -> String {
+    if repo.workdir().is_some_and(is_jj_colocated) {
+        return "colocated with jj".to_owned();
+    }
+    if repo.head_detached().unwrap_or(false) {
+        return "detached".to_owned();
+    }
+    match repo.head() {
+        Ok(head) => format!("on {}", head.shorthand().unwrap_or("an unnamed branch")),
+        Err(_) => "unborn".to_owned(),
+    }
+}
+
+// Whether the URL of remote differs from its configured one because of
+// url.<base>.insteadOf
+fn is_rewritten(repo: &git2::Repository, remote: &git2::Remote, url: &str) -> bool {
+    let configured = remote.name().and_then(|name| {
+        repo.config()
+            .and_then(|config| config.get_string(&format!("remote.{}.url", name)))
+            .ok()
+    });
+    configured.is_some_and(|configured| configured != url)
+}
+
+// Debug info can record a checksum of each source file as it was compiled.
+// Links to files that don't match at commit show other code than was built.
+fn check_checksums(repo: &git2::Repository, commit: git2::Oid, context: &LinkContext) {
+    let workdir = Checkout::workdir(repo);
+    let tree = match repo.find_commit(commit).and_then(|commit| commit.tree()) {
+        Ok(tree) => tree,
+        Err(e) => {
+            debug!("Not checking checksums at {}: {}", commit, e.message());
+            return;
+        }
+    };
+    for file in context.source_files {
+        let checksum = match &file.checksum {
+            Some(checksum) => checksum,
+            None => continue,
+        };
+        let rel_path = match file.path.strip_prefix(workdir) {
+            Ok(rel_path)
+                if !context
+                    .nested
+                    .iter()
+                    .any(|other| file.path.starts_with(other)) =>
+            {
+                rel_path
+            }
+            _ => continue,
+        };
+        // Files the commit doesn't have are reported as untracked
+        let blob = match tree
+            .get_path(rel_path)
+            .and_then(|entry| entry.to_object(repo))
+            .and_then(|object| object.peel_to_blob())
+        {
+            Ok(blob) => blob,
+            Err(_) => continue,
+        };
+        if !checksum::matches(checksum, blob.content()) {
+            diag!(
+                Code::ChecksumMismatch,
+                file: &file.path,
+                "{} at {} does not match the checksum recorded when it was compiled, its link would show other code",
+                file.path.display(),
+                commit
+            );
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use log::{debug, info};
+use path_slash::PathExt;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+type Request = ureq::RequestBuilder<ureq::typestate::WithoutBody>;
+
+// Fetch the URL a Source Link consumer would build for every source file of
+// binary from documents, with up to concurrency requests in flight per host.
+// Returns the number of URLs that did not resolve.
+pub fn verify(binary: &Path, documents: &serde_json::Value, concurrency: usize) -> Result<usize> {
+    let documents = documents
+        .as_object()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "section has no documents"))?;
+    let source_files = compiledfiles::parse(File::open(binary)?)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+
+    let mut urls = BTreeSet::new();
+    for file in &source_files {
+        match resolve(documents, &file.path) {
+            Some(url) => {
+                urls.insert(url);
+            }
+            None => debug!("{} is not mapped", file.path.display()),
+        }
+    }
+
+    // Each host gets its own workers, so one slow host doesn't hold up the
+    // others and no host sees more than concurrency requests at once
+    let mut by_host = BTreeMap::<String, Vec<url::Url>>::new();
+    for url in &urls {
+        let host = url.host_str().unwrap_or_default().to_owned();
+        by_host.entry(host).or_default().push(url.clone());
+    }
+    let queues = by_host
+        .into_values()
+        .map(|urls| (urls, AtomicUsize::new(0)))
+        .collect::<Vec<_>>();
+
+    // The agent keeps connections alive, so workers reuse them between
+    // requests to the same host
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .max_idle_connections_per_host(concurrency)
+        .build()
+        .into();
+    let failed = AtomicUsize::new(0);
+    let start = Instant::now();
+    let (agent, failures) = (&agent, &failed);
+    std::thread::scope(|scope| {
+        for (urls, next) in &queues {
+            for _ in 0..concurrency.min(urls.len()) {
+                scope.spawn(move || {
+                    while let Some(url) = urls.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        match request(agent, url).call() {
+                            Ok(_) => info!("ok {}", url),
+                            Err(e) => {
+                                println!("failed {} ({})", url, e);
+                                failures.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                });
+            }
+        }
+    });
+    let failed = failed.into_inner();
+
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "{} of {} URLs resolved for {} in {:.1}s ({:.1} URLs/s)",
+        urls.len() - failed,
+        urls.len(),
+        binary.display(),
+        elapsed,
+        urls.len() as f64 / elapsed.max(0.001)
+    );
+    Ok(failed)
+}
+
+// Like a debugger would, use the longest matching key and replace its "*"
+// with the rest of the path
+fn resolve(
+    documents: &serde_json::Map<String, serde_json::Value>,
+    path: &Path,
+) -> Option<url::Url> {
+    let (rel_path, url) = documents
+        .iter()
+        .filter_map(|(key, url)| {
+            let rel_path = match key.strip_suffix('*') {
+                Some(dir) => path.strip_prefix(dir).ok()?,
+                // Keys without a wildcard only match the file they name
+                None if path == Path::new(key) => Path::new(""),
+                None => return None,
+            };
+            Some((rel_path, url.as_str()?))
+        })
+        .min_by_key(|(rel_path, _)| rel_path.components().count())?;
+    url::Url::parse(&url.replacen('*', &rel_path.to_slash()?, 1)).ok()
+}
+
+// Hosts answer the same URL with the raw file or with metadata depending on
+// how they are asked, so send what a Source Link consumer for each host would.
+fn request(agent: &ureq::Agent, url: &url::Url) -> Request {
+    let request = agent
+        .get(url.as_str())
+        .header("User-Agent", crate::APP_NAME);
+    let domain = url.domain().unwrap_or_default();
+    let path = url.path();
+
+    if (domain == "api.github.com" || path.starts_with("/api/v3/")) && path.contains("/contents/") {
+        // Without the raw media type the contents API returns base64 in JSON
+        let request = request.header("Accept", "application/vnd.github.raw");
+        with_token(request, "GITHUB_TOKEN", "Authorization", "Bearer ")
+    } else if domain == "dev.azure.com" {
+        // The items API returns JSON metadata unless asked for the content
+        let request = request.header("Accept", "application/octet-stream");
+        with_token(request, "AZURE_DEVOPS_TOKEN", "Authorization", "Bearer ")
+    } else if path.contains("/-/raw/") {
+        with_token(request, "GITLAB_TOKEN", "PRIVATE-TOKEN", "")
+    } else {
+        request
+    }
+}
+
+fn with_token(request: Request, var: &str, header: &str, prefix: &str) -> Request {
+    match std::env::var(var) {
+        Ok(token) => request.header(header, format!("{prefix}{token}")),
+        Err(_) => request,
+    }
+}
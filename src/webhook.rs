@@ -0,0 +1,56 @@
+use log::{debug, warn};
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::summary::Summary;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // The summary as a JSON object
+    Generic,
+    // {"text": "..."}, as accepted by Slack and compatible incoming webhooks
+    Slack,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "generic" => Ok(Format::Generic),
+            "slack" => Ok(Format::Slack),
+            _ => Err(format!("Unknown webhook format \"{}\"", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub url: String,
+    pub format: Format,
+}
+
+impl Webhook {
+    // Failures are only logged, a broken webhook shouldn't stop processing.
+    // The URL is left out of log messages as webhook URLs are usually secret.
+    pub fn post(&self, summary: &Summary) {
+        let body = match self.format {
+            Format::Generic => summary.to_json(),
+            Format::Slack => serde_json::json!({ "text": summary.to_string() }),
+        };
+        // A hung endpoint would otherwise stall processing for good
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(30)))
+            .build()
+            .into();
+        match agent
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .send(body.to_string())
+        {
+            Ok(_) => debug!("Posted summary to webhook"),
+            Err(e) => warn!("Failed to post summary to webhook: {}", e),
+        }
+    }
+}
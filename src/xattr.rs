@@ -0,0 +1,105 @@
+use std::io::Result;
+use std::path::Path;
+
+// Copy the extended attributes of original to output, like the file
+// capabilities setcap grants and SELinux labels. Returns the names of those
+// that couldn't be set, with why.
+#[cfg(target_os = "linux")]
+pub fn copy(original: &Path, output: &Path) -> Result<Vec<(String, std::io::Error)>> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let original = CString::new(original.as_os_str().as_bytes())?;
+    let output = CString::new(output.as_os_str().as_bytes())?;
+    let mut failed = Vec::new();
+    let names = list(&original)?;
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let name = CString::new(name)?;
+        let set = get(&original, &name).and_then(|value| {
+            // The paths and name are NUL terminated and value outlives
+            // the call
+            let result = unsafe {
+                libc::setxattr(
+                    output.as_ptr(),
+                    name.as_ptr(),
+                    value.as_ptr().cast(),
+                    value.len(),
+                    0,
+                )
+            };
+            match result {
+                -1 => Err(std::io::Error::last_os_error()),
+                _ => Ok(()),
+            }
+        });
+        if let Err(e) = set {
+            failed.push((name.to_string_lossy().into_owned(), e));
+        }
+    }
+    Ok(failed)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn copy(_original: &Path, _output: &Path) -> Result<Vec<(String, std::io::Error)>> {
+    Ok(Vec::new())
+}
+
+// The NUL separated names of the attributes of path, none when the file
+// system has no extended attributes
+#[cfg(target_os = "linux")]
+fn list(path: &std::ffi::CStr) -> Result<Vec<u8>> {
+    // Attributes may be added between asking for the size and listing them
+    loop {
+        // path is NUL terminated and a size of 0 only asks for the size
+        let size = unsafe { libc::listxattr(path.as_ptr(), std::ptr::null_mut(), 0) };
+        if size == -1 {
+            let e = std::io::Error::last_os_error();
+            return match e.raw_os_error() {
+                Some(libc::ENOTSUP) => Ok(Vec::new()),
+                _ => Err(e),
+            };
+        }
+        let mut names = vec![0; size as usize];
+        // names is as long as the size passed
+        let size =
+            unsafe { libc::listxattr(path.as_ptr(), names.as_mut_ptr().cast(), names.len()) };
+        match size {
+            -1 if std::io::Error::last_os_error().raw_os_error() == Some(libc::ERANGE) => continue,
+            -1 => return Err(std::io::Error::last_os_error()),
+            size => {
+                names.truncate(size as usize);
+                return Ok(names);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn get(path: &std::ffi::CStr, name: &std::ffi::CStr) -> Result<Vec<u8>> {
+    loop {
+        // path and name are NUL terminated and a size of 0 only asks
+        // for the size
+        let size = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        if size == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut value = vec![0u8; size as usize];
+        // value is as long as the size passed
+        let size = unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        match size {
+            -1 if std::io::Error::last_os_error().raw_os_error() == Some(libc::ERANGE) => continue,
+            -1 => return Err(std::io::Error::last_os_error()),
+            size => {
+                value.truncate(size as usize);
+                return Ok(value);
+            }
+        }
+    }
+}